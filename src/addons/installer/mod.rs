@@ -13,14 +13,14 @@
 //! steps remain if a later step fails; the bottle storage layer is responsible
 //! for any transaction-level rollback.
 //!
-//! # Component removal
+//! # Removal
 //!
 //! Resources and steps are visited in reverse order. Uninstallation can restore
 //! copied files, delete DLL overrides, and remove environment entries. Actions
 //! without an inverse—executing programs, extracting archives, registering DLLs,
 //! and setting registry values—are skipped. Consequently, a recipe is not
-//! necessarily fully reversible. Dependencies cannot be removed separately from
-//! their bottle.
+//! necessarily fully reversible. Components derive their recipe from their slot;
+//! dependency removal uses the recipe from the downloaded index entry.
 //!
 //! # Cancellation and cleanup
 //!
@@ -0,0 +1,325 @@
+//! Automatic snapshots taken under a per-bottle policy.
+
+use std::{
+    cmp::Reverse,
+    sync::PoisonError,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use fvs_rs::Repository;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::{
+    Context, Operation, Progress, Stage, Transfer,
+    error::Result,
+    prefix::{FVS_BLOCK_SIZE, finish_commit},
+    winebridge::WineBridgeClient,
+};
+
+use super::{
+    state::{Bottle, BottleState},
+    tags::SnapshotTags,
+};
+
+/// Message of automatic backups, followed by their creation time in Unix
+/// seconds.
+const BACKUP_MESSAGE: &str = "bottles-next:backup";
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// When a bottle is backed up automatically and how long backups are kept.
+///
+/// Backups are ordinary snapshots listed by [`Bottle::snapshots`] with the
+/// message `bottles-next:backup` followed by their creation time in Unix
+/// seconds. Only snapshots with such a message are ever pruned; snapshots
+/// created with [`Bottle::create_snapshot`] under another message and
+/// internal rollback checkpoints are kept.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackupPolicy {
+    /// When backups are taken.
+    pub schedule: BackupSchedule,
+    /// Which backups survive pruning.
+    #[serde(default)]
+    pub retention: Retention,
+}
+
+/// When [`BackupPolicy`] takes a backup.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackupSchedule {
+    /// Before each launch that starts a stopped bottle. Launches into a
+    /// running bottle are not preceded by a backup, since taking one would
+    /// stop the programs already running.
+    BeforeLaunch,
+    /// Before a launch that starts a stopped bottle or an addon change, when
+    /// the newest backup is more than a day old.
+    #[default]
+    Daily,
+    /// Before each component or dependency is installed, replaced, or
+    /// removed.
+    BeforeAddonChange,
+}
+
+/// Which automatic backups survive pruning.
+///
+/// A backup is kept if either rule keeps it, and the newest backup is always
+/// kept. Backups pinned with [`Bottle::pin_snapshot`] are never pruned.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Retention {
+    /// Keep this many of the newest backups.
+    pub keep_last: u32,
+    /// Keep the newest backup of each of this many days, counting today, in
+    /// UTC.
+    pub keep_daily: u32,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            keep_last: 5,
+            keep_daily: 7,
+        }
+    }
+}
+
+/// What is about to happen when a backup may be due.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(super) enum Trigger {
+    Launch,
+    AddonChange,
+}
+
+impl BackupSchedule {
+    /// Returns whether `trigger` calls for a backup given the creation time of
+    /// the newest backup.
+    fn is_due(self, trigger: Trigger, newest: Option<u64>, now: u64) -> bool {
+        match self {
+            Self::BeforeLaunch => trigger == Trigger::Launch,
+            Self::BeforeAddonChange => trigger == Trigger::AddonChange,
+            Self::Daily => newest.is_none_or(|newest| now.saturating_sub(newest) >= DAY),
+        }
+    }
+}
+
+impl Bottle {
+    /// Deletes automatic backups that the bottle's [`BackupPolicy`] no longer
+    /// keeps, and returns their state IDs.
+    ///
+    /// Backups are also pruned after each automatic backup. A bottle without
+    /// a policy keeps all of its backups, so nothing is deleted.
+    ///
+    /// # Errors
+    ///
+    /// The operation returns an error if the bottle was deleted, the FVS
+    /// service is unavailable, or a backup cannot be deleted.
+    pub fn prune_backups(&self) -> Operation<Vec<String>> {
+        let bottle = self.clone();
+        Operation::new(move |progress, _cancellation| async move {
+            let _write = bottle.0.write_lock.write().await;
+            let state = bottle.state()?;
+            let Some(policy) = state.backup else {
+                return Ok(Vec::new());
+            };
+            progress.send_replace(Some(Progress::new(Stage::Removing)));
+            prune(&state, &bottle.0.cx, policy.retention).await
+        })
+    }
+
+    /// Backs up a stopped bottle before a launch when its policy asks for it.
+    ///
+    /// Failures are logged rather than returned so that a missing FVS service
+    /// does not prevent programs from starting.
+    pub(super) async fn back_up_before_launch(&self) {
+        if self.state().is_ok_and(|state| state.backup.is_none()) {
+            return;
+        }
+        let result = async {
+            let _write = self.0.write_lock.write().await;
+            let state = self.state()?;
+            let running = !self
+                .0
+                .launches
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_empty();
+            if state.backup.is_none()
+                || running
+                || WineBridgeClient::try_connect(&self.prefix_path())
+                    .await?
+                    .is_some()
+            {
+                return Ok(());
+            }
+            Self::stop_state(&state, &self.0.cx).await?;
+            back_up_if_due(&state, &self.0.cx, Trigger::Launch, None).await
+        }
+        .await;
+        if let Err(error) = result {
+            tracing::warn!("automatic backup of bottle {} failed: {error}", self.0.id);
+        }
+    }
+}
+
+/// Takes a backup and prunes old ones if the bottle's policy asks for one
+/// before `trigger`.
+///
+/// The caller holds exclusive bottle access and has stopped the bottle.
+/// Commit progress is reported to `progress` when given.
+pub(super) async fn back_up_if_due(
+    state: &BottleState,
+    cx: &Context,
+    trigger: Trigger,
+    progress: Option<&watch::Sender<Option<Progress>>>,
+) -> Result<()> {
+    let Some(policy) = state.backup else {
+        return Ok(());
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let newest = backups(state, cx)
+        .await?
+        .first()
+        .map(|(_, created)| *created);
+    if !policy.schedule.is_due(trigger, newest, now) {
+        return Ok(());
+    }
+    let stream = cx
+        .fvs()
+        .await?
+        .commit_stream(&repository(state, cx), format!("{BACKUP_MESSAGE} {now}"))
+        .await?;
+    finish_commit(stream, |update| {
+        if let Some(progress) = progress {
+            progress.send_replace(Some(Progress::transferring(
+                Stage::Committing,
+                Transfer::from(update),
+            )));
+        }
+    })
+    .await?;
+    prune(state, cx, policy.retention).await?;
+    Ok(())
+}
+
+/// Deletes the backups that `retention` does not keep, except pinned ones.
+async fn prune(state: &BottleState, cx: &Context, retention: Retention) -> Result<Vec<String>> {
+    let backups = backups(state, cx).await?;
+    let path = cx.directories().snapshot_tags(state.id);
+    let mut tags = SnapshotTags::load(&path).await?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let client = cx.fvs().await?;
+    let repository = repository(state, cx);
+    let mut deleted = Vec::new();
+    let mut forgotten = false;
+    for state_id in expired(&backups, retention, now) {
+        if tags.is_pinned(state_id) {
+            continue;
+        }
+        client.delete_commit(&repository, state_id).await?;
+        forgotten |= tags.forget(state_id);
+        deleted.push(state_id.to_owned());
+    }
+    if forgotten {
+        tags.save(&path).await?;
+    }
+    Ok(deleted)
+}
+
+/// Lists automatic backups newest-first with their creation times.
+async fn backups(state: &BottleState, cx: &Context) -> Result<Vec<(String, u64)>> {
+    let mut backups = cx
+        .fvs()
+        .await?
+        .list_commits(&repository(state, cx))
+        .await?
+        .into_iter()
+        .filter_map(|commit| {
+            let created = commit
+                .message
+                .strip_prefix(BACKUP_MESSAGE)?
+                .strip_prefix(' ')?
+                .parse()
+                .ok()?;
+            Some((commit.state_id, created))
+        })
+        .collect::<Vec<_>>();
+    backups.sort_by_key(|(_, created)| Reverse(*created));
+    Ok(backups)
+}
+
+/// Returns the state IDs of newest-first `backups` that `retention` does not
+/// keep at time `now`.
+fn expired(backups: &[(String, u64)], retention: Retention, now: u64) -> Vec<&str> {
+    let today = now / DAY;
+    let mut kept_days = Vec::new();
+    let mut expired = Vec::new();
+    for (index, (state_id, created)) in backups.iter().enumerate() {
+        let day = created / DAY;
+        let daily = today.saturating_sub(day) < u64::from(retention.keep_daily)
+            && !kept_days.contains(&day);
+        if daily {
+            kept_days.push(day);
+        }
+        if index > 0 && index >= retention.keep_last as usize && !daily {
+            expired.push(state_id.as_str());
+        }
+    }
+    expired
+}
+
+/// Addresses the bottle's snapshot history repository.
+fn repository(state: &BottleState, cx: &Context) -> Repository {
+    Repository {
+        repository_path: cx.directories().bottle(state.id).display().to_string(),
+        block_size: FVS_BLOCK_SIZE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retention_keeps_the_newest_and_one_backup_per_recent_day() {
+        let now = 100 * DAY + 12 * 60 * 60;
+        let backups = [
+            ("today-late", now - 60),
+            ("today-early", now - 2 * 60 * 60),
+            ("yesterday-late", now - DAY),
+            ("yesterday-early", now - DAY - 60),
+            ("last-week", now - 8 * DAY),
+        ]
+        .map(|(id, created)| (id.to_owned(), created));
+
+        let retention = Retention {
+            keep_last: 1,
+            keep_daily: 2,
+        };
+        assert_eq!(
+            expired(&backups, retention, now),
+            ["today-early", "yesterday-early", "last-week"]
+        );
+        let retention = Retention {
+            keep_last: 0,
+            keep_daily: 0,
+        };
+        assert_eq!(expired(&backups, retention, now).len(), backups.len() - 1);
+    }
+
+    #[test]
+    fn daily_backups_wait_a_day() {
+        let now = 10 * DAY;
+        assert!(BackupSchedule::Daily.is_due(Trigger::Launch, None, now));
+        assert!(!BackupSchedule::Daily.is_due(Trigger::AddonChange, Some(now - 60), now));
+        assert!(BackupSchedule::Daily.is_due(Trigger::AddonChange, Some(now - DAY), now));
+        assert!(!BackupSchedule::BeforeLaunch.is_due(Trigger::AddonChange, None, now));
+    }
+}
@@ -0,0 +1,277 @@
+//! Freedesktop launchers for registered programs.
+//!
+//! An entry and its icon are named `bottles-next-<bottle UUID>-<program UUID>`
+//! in the user's XDG data directory, so they can be found again for removal
+//! without recording anything in `bottle.toml`.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use uuid::Uuid;
+
+use crate::{Directories, error::Result, pe::Image, prefix::dosdevices};
+
+use super::{
+    error::BottleError,
+    state::{Bottle, Program},
+};
+
+/// Prefix of the names of entries and icons.
+const NAME_PREFIX: &str = "bottles-next";
+
+/// Icon sizes that have a directory in the `hicolor` theme.
+const ICON_SIZES: [u32; 9] = [16, 22, 24, 32, 48, 64, 96, 128, 256];
+
+/// Themed icon shown for executables without a usable icon.
+const FALLBACK_ICON: &str = "application-x-ms-dos-executable";
+
+impl Bottle {
+    /// Writes a freedesktop desktop entry that launches a registered program.
+    ///
+    /// The entry runs `launcher` followed by the bottle and program UUIDs,
+    /// for example `bottles-next launch <bottle UUID> <program UUID>` for the
+    /// launcher `bottles-next launch`. The launcher is written to the entry's
+    /// `Exec` key as is, so it must follow the desktop entry quoting rules.
+    /// Frontends must keep accepting the command line they chose, which stays
+    /// valid when the bottle or program is renamed.
+    ///
+    /// The largest square icon of a standard size is extracted from the
+    /// executable's resources into the `hicolor` icon theme; executables
+    /// without one get a generic icon. Calling this again rewrites the entry
+    /// and icon from the current program. Both are removed when
+    /// [`BottleEdit::remove_program`](crate::BottleEdit::remove_program)
+    /// commits or the bottle is deleted.
+    ///
+    /// Returns the path of the written `.desktop` file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::InvalidLauncher`] if `launcher` is blank or
+    /// spans several lines, [`BottleError::ProgramNotFound`] if `program_id`
+    /// is not registered, [`BottleError::ProjectDirectoriesUnavailable`] if
+    /// the XDG data directory cannot be determined, or an error if the prefix
+    /// cannot be prepared or the entry or icon cannot be written.
+    pub async fn create_desktop_entry(&self, program_id: Uuid, launcher: &str) -> Result<PathBuf> {
+        if launcher.trim().is_empty() || launcher.contains(['\n', '\r', '\0']) {
+            return Err(BottleError::InvalidLauncher(launcher.to_owned()).into());
+        }
+        let data_dir = self.0.cx.directories().user_data_dir()?;
+        let name = entry_name(self.0.id, program_id);
+        let (entry, icon) = self
+            .with_prefix(async |state, _| {
+                let program = state
+                    .program(program_id)
+                    .ok_or(BottleError::ProgramNotFound(program_id))?;
+                let icon = extract_icon(&self.prefix_path(), program).await;
+                let icon_name = if icon.is_some() {
+                    name.as_str()
+                } else {
+                    FALLBACK_ICON
+                };
+                Ok((
+                    render(launcher, state.id(), state.name(), program, icon_name),
+                    icon,
+                ))
+            })
+            .await?;
+
+        write(data_dir, &name, &entry, icon).await
+    }
+}
+
+/// Writes an entry and its icon, replacing icons of other sizes, and returns
+/// the entry's path.
+async fn write(
+    data_dir: &Path,
+    name: &str,
+    entry: &str,
+    icon: Option<(u32, Vec<u8>)>,
+) -> Result<PathBuf> {
+    remove_icons(data_dir, name).await?;
+    if let Some((size, png)) = icon {
+        let path = icon_path(data_dir, size, name);
+        if let Some(parent) = path.parent() {
+            async_fs::create_dir_all(parent).await?;
+        }
+        async_fs::write(&path, png).await?;
+    }
+    let path = entry_path(data_dir, name);
+    if let Some(parent) = path.parent() {
+        async_fs::create_dir_all(parent).await?;
+    }
+    async_fs::write(&path, entry).await?;
+    Ok(path)
+}
+
+/// Removes the desktop entry and icons of a program, if it has any.
+pub(super) async fn remove(directories: &Directories, bottle: Uuid, program: Uuid) -> Result<()> {
+    let data_dir = directories.user_data_dir()?;
+    let name = entry_name(bottle, program);
+    remove_file(&entry_path(data_dir, &name)).await?;
+    remove_icons(data_dir, &name).await
+}
+
+fn entry_name(bottle: Uuid, program: Uuid) -> String {
+    format!("{NAME_PREFIX}-{bottle}-{program}")
+}
+
+fn entry_path(data_dir: &Path, name: &str) -> PathBuf {
+    data_dir
+        .join("applications")
+        .join(format!("{name}.desktop"))
+}
+
+fn icon_path(data_dir: &Path, size: u32, name: &str) -> PathBuf {
+    data_dir
+        .join("icons/hicolor")
+        .join(format!("{size}x{size}"))
+        .join("apps")
+        .join(format!("{name}.png"))
+}
+
+async fn remove_icons(data_dir: &Path, name: &str) -> Result<()> {
+    for size in ICON_SIZES {
+        remove_file(&icon_path(data_dir, size, name)).await?;
+    }
+    Ok(())
+}
+
+async fn remove_file(path: &Path) -> Result<()> {
+    match async_fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Extracts the program's icon as a PNG together with its size.
+///
+/// Icons are decoration, so an unreadable executable only skips the icon.
+async fn extract_icon(prefix: &Path, program: &Program) -> Option<(u32, Vec<u8>)> {
+    let path = match dosdevices::host_path(prefix, program.executable()).await {
+        Ok(path) => path?,
+        Err(error) => {
+            tracing::warn!("failed to resolve {}: {error}", program.executable());
+            return None;
+        }
+    };
+    let image = match Image::read(&path).await {
+        Ok(image) => image?,
+        Err(error) => {
+            if error.kind() != io::ErrorKind::NotFound {
+                tracing::warn!("failed to read {}: {error}", path.display());
+            }
+            return None;
+        }
+    };
+    let entry = image
+        .icon_groups()
+        .into_iter()
+        .next()?
+        .into_iter()
+        .filter(|entry| entry.width == entry.height && ICON_SIZES.contains(&entry.width))
+        .max_by_key(|entry| (entry.width, entry.bit_count))?;
+    Some((entry.width, image.icon_png(&entry)?))
+}
+
+/// Renders the desktop entry that runs `launcher` for `program` in the bottle
+/// `bottle_id`.
+fn render(
+    launcher: &str,
+    bottle_id: Uuid,
+    bottle_name: &str,
+    program: &Program,
+    icon: &str,
+) -> String {
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name={}\n\
+         Comment={}\n\
+         Exec={launcher} {bottle_id} {}\n\
+         Icon={icon}\n\
+         Terminal=false\n",
+        escape(program.name()),
+        escape(&format!("Run in {bottle_name}")),
+        program.id(),
+    )
+}
+
+/// Escapes a desktop entry string value.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for (index, character) in value.chars().enumerate() {
+        match character {
+            ' ' if index == 0 => escaped.push_str("\\s"),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_launch_by_id_and_escape_names() {
+        let bottle = Uuid::new_v4();
+        let program = Program::new(" Game\\Editor\n", r"C:\game.exe").unwrap();
+        let name = entry_name(bottle, program.id());
+
+        let entry = render(
+            "bottles-next launch",
+            bottle,
+            "Games",
+            &program,
+            FALLBACK_ICON,
+        );
+
+        assert!(entry.starts_with("[Desktop Entry]\n"));
+        assert!(entry.contains("\nName=\\sGame\\\\Editor\\n\n"));
+        assert!(entry.contains(&format!(
+            "\nExec=bottles-next launch {bottle} {}\n",
+            program.id()
+        )));
+        assert!(entry.contains(&format!("\nIcon={FALLBACK_ICON}\n")));
+        assert_eq!(
+            icon_path(Path::new("/data"), 48, &name),
+            Path::new(&format!("/data/icons/hicolor/48x48/apps/{name}.png"))
+        );
+    }
+
+    #[test]
+    fn entries_are_rewritten_and_removed_with_their_icons() {
+        futures_lite::future::block_on(async {
+            let root = std::env::temp_dir().join(format!("bottles-next-{}", Uuid::new_v4()));
+            let directories = Directories::from_path(&root).unwrap();
+            let data_dir = directories.user_data_dir().unwrap();
+            let (bottle, program) = (Uuid::new_v4(), Uuid::new_v4());
+            let name = entry_name(bottle, program);
+
+            let path = write(data_dir, &name, "first", Some((48, vec![1])))
+                .await
+                .unwrap();
+            assert_eq!(path, entry_path(data_dir, &name));
+            assert!(icon_path(data_dir, 48, &name).is_file());
+
+            write(data_dir, &name, "second", Some((32, vec![2])))
+                .await
+                .unwrap();
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "second");
+            assert!(!icon_path(data_dir, 48, &name).exists());
+
+            remove(&directories, bottle, program).await.unwrap();
+            assert!(!path.exists());
+            assert!(!icon_path(data_dir, 32, &name).exists());
+            remove(&directories, bottle, program).await.unwrap();
+            std::fs::remove_dir_all(root).unwrap();
+        });
+    }
+}
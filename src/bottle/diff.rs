@@ -0,0 +1,502 @@
+//! Differences between two snapshots of a bottle.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use regdiff_rs::prelude::{Diff, Hive, Registry};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::{
+    Operation, Progress, Stage,
+    error::{Error, Result},
+    prefix::with_snapshot,
+};
+
+use super::{error::BottleError, files::CDrivePath, state::Bottle};
+
+/// Registry hives compared by [`Bottle::diff_snapshots`].
+const HIVES: [(&str, Hive); 2] = [
+    ("user.reg", Hive::CurrentUser),
+    ("system.reg", Hive::LocalMachine),
+];
+
+/// What changed between two snapshots, as returned by
+/// [`Bottle::diff_snapshots`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SnapshotDiff {
+    /// Changed files below `C:`, sorted by path.
+    pub files: Vec<FileChange>,
+    /// Changed registry keys and values, sorted by key.
+    pub registry: Vec<RegistryChange>,
+}
+
+/// How an entry differs in the newer snapshot.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ChangeKind {
+    /// The entry exists only in the newer snapshot.
+    Added,
+    /// The entry exists only in the older snapshot.
+    Removed,
+    /// The entry exists in both snapshots with different contents.
+    Modified,
+}
+
+/// A file that differs between two snapshots.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct FileChange {
+    /// Windows path of the file, such as `C:\users\steamuser\save.dat`.
+    pub path: String,
+    /// How the file differs.
+    pub kind: ChangeKind,
+}
+
+/// A registry key or value that differs between two snapshots.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct RegistryChange {
+    /// Full key path as written in registry patches.
+    pub key: String,
+    /// Name of the changed value, empty for the key's default value, or
+    /// `None` when the key itself was added or removed.
+    pub value: Option<String>,
+    /// How the key or value differs.
+    pub kind: ChangeKind,
+}
+
+impl Bottle {
+    /// Compares two snapshots, each selected by full state ID or prefix.
+    ///
+    /// The result lists what changed from `from` to `to`: files below
+    /// `drive_c` that were added, removed, or modified, and registry keys and
+    /// values that differ in `user.reg` and `system.reg`. Pass the current
+    /// snapshot as `from` and a rollback target as `to` to see what
+    /// [`rollback`](Self::rollback) would change. Directories are not listed
+    /// themselves; their files are. For Virgo storage, only files the bottle
+    /// changed on top of its layers are part of a snapshot, so files provided
+    /// by layers do not appear.
+    ///
+    /// Both snapshots are mounted read-only while the operation holds shared
+    /// bottle access, so the bottle may keep running. Cancellation is
+    /// observed between directories.
+    ///
+    /// # Errors
+    ///
+    /// The operation returns an error if the bottle was deleted, either
+    /// snapshot is missing or ambiguous, the FVS service is unavailable,
+    /// cancellation is requested, a file cannot be read, or a registry hive
+    /// cannot be parsed.
+    pub fn diff_snapshots(&self, from: &str, to: &str) -> Operation<SnapshotDiff> {
+        let bottle = self.clone();
+        let (from, to) = (from.to_owned(), to.to_owned());
+        Operation::new(move |progress, cancellation| async move {
+            let _read = bottle.0.write_lock.read().await;
+            let state = bottle.state()?;
+            let bottle_path = bottle.bottle_path();
+            let cx = &bottle.0.cx;
+            let prefix = state.storage.writable_dir();
+            let scratch = cx
+                .directories()
+                .runtime_dir()
+                .join("snapshots")
+                .join(format!("{}.diff", Uuid::new_v4()));
+            progress.send_replace(Some(Progress::new(Stage::Preparing)));
+            let result = with_snapshot(&bottle_path, &from, cx, async |old| {
+                with_snapshot(&bottle_path, &to, cx, async |new| {
+                    progress.send_replace(Some(Progress::new(Stage::Comparing)));
+                    let (old, new) = (old.join(prefix), new.join(prefix));
+                    let scratch = scratch.clone();
+                    let cancellation = cancellation.clone();
+                    blocking::unblock(move || {
+                        let files =
+                            diff_files(&old.join("drive_c"), &new.join("drive_c"), &cancellation)?;
+                        fs::create_dir_all(&scratch)?;
+                        let registry = diff_registry(&old, &new, &scratch)?;
+                        Ok::<_, Error>(SnapshotDiff { files, registry })
+                    })
+                    .await
+                })
+                .await
+            })
+            .await;
+            let _ = async_fs::remove_dir_all(&scratch).await;
+            result
+        })
+    }
+}
+
+/// A non-directory entry found while scanning a snapshot.
+#[derive(Debug)]
+enum Entry {
+    File {
+        len: u64,
+        modified: Option<SystemTime>,
+    },
+    Link(PathBuf),
+}
+
+/// Lists files and symbolic links below `root` by relative path.
+///
+/// Symbolic links are recorded rather than followed, and other special
+/// files are skipped. A missing root has no entries.
+fn scan(root: &Path, cancellation: &CancellationToken) -> Result<BTreeMap<PathBuf, Entry>> {
+    let mut entries = BTreeMap::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        if cancellation.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        let dir = match fs::read_dir(root.join(&relative)) {
+            Ok(dir) => dir,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error.into()),
+        };
+        for entry in dir {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_file() {
+                let metadata = entry.metadata()?;
+                entries.insert(
+                    path,
+                    Entry::File {
+                        len: metadata.len(),
+                        modified: metadata.modified().ok(),
+                    },
+                );
+            } else if file_type.is_symlink() {
+                entries.insert(path, Entry::Link(fs::read_link(entry.path())?));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Compares the files below two `drive_c` directories.
+fn diff_files(old: &Path, new: &Path, cancellation: &CancellationToken) -> Result<Vec<FileChange>> {
+    let before = scan(old, cancellation)?;
+    let after = scan(new, cancellation)?;
+    let mut changes = Vec::new();
+    for (path, entry) in &before {
+        let kind = match after.get(path) {
+            None => ChangeKind::Removed,
+            Some(current) if changed(&old.join(path), &new.join(path), entry, current)? => {
+                ChangeKind::Modified
+            }
+            Some(_) => continue,
+        };
+        changes.push((path, kind));
+    }
+    for path in after.keys().filter(|path| !before.contains_key(*path)) {
+        changes.push((path, ChangeKind::Added));
+    }
+    changes.sort_by_key(|(path, _)| *path);
+    Ok(changes
+        .into_iter()
+        .map(|(path, kind)| FileChange {
+            path: CDrivePath {
+                relative: path.clone(),
+            }
+            .windows(),
+            kind,
+        })
+        .collect())
+}
+
+/// Returns whether an entry present in both snapshots differs.
+///
+/// Files with equal sizes and modification times are taken as unchanged;
+/// otherwise equal sizes are settled by comparing contents.
+fn changed(old: &Path, new: &Path, before: &Entry, after: &Entry) -> io::Result<bool> {
+    match (before, after) {
+        (
+            Entry::File {
+                len: old_len,
+                modified: old_modified,
+            },
+            Entry::File {
+                len: new_len,
+                modified: new_modified,
+            },
+        ) => {
+            if old_len != new_len {
+                return Ok(true);
+            }
+            if old_modified.is_some() && old_modified == new_modified {
+                return Ok(false);
+            }
+            Ok(!same_contents(old, new)?)
+        }
+        (Entry::Link(old), Entry::Link(new)) => Ok(old != new),
+        _ => Ok(true),
+    }
+}
+
+fn same_contents(old: &Path, new: &Path) -> io::Result<bool> {
+    let mut old = BufReader::new(fs::File::open(old)?);
+    let mut new = BufReader::new(fs::File::open(new)?);
+    loop {
+        let (left, right) = (old.fill_buf()?, new.fill_buf()?);
+        if left.is_empty() || right.is_empty() {
+            return Ok(left.is_empty() && right.is_empty());
+        }
+        let length = left.len().min(right.len());
+        if left[..length] != right[..length] {
+            return Ok(false);
+        }
+        old.consume(length);
+        new.consume(length);
+    }
+}
+
+/// Compares the registry hives of two prefixes with `regdiff-rs`.
+///
+/// A hive missing from one side is compared as empty. Patches in both
+/// directions are written to `scratch`: the forward patch names what
+/// changed, and the reverse patch tells added entries from modified ones.
+fn diff_registry(old: &Path, new: &Path, scratch: &Path) -> Result<Vec<RegistryChange>> {
+    let empty = scratch.join("empty.reg");
+    fs::write(&empty, "WINE REGISTRY Version 2\n")?;
+    let mut changes = Vec::new();
+    for (file, hive) in HIVES {
+        let (old, new) = (old.join(file), new.join(file));
+        if !old.exists() && !new.exists() {
+            continue;
+        }
+        let load = |path: &Path| {
+            let source = if path.exists() { path } else { &empty };
+            Registry::try_from(source, hive).map_err(|error| BottleError::InvalidRegistry {
+                path: path.to_path_buf(),
+                reason: error.to_string(),
+            })
+        };
+        let (old, new) = (load(&old)?, load(&new)?);
+        let (forward, reverse) = (scratch.join("forward.reg"), scratch.join("reverse.reg"));
+        for (patch, from, to) in [(&forward, &old, &new), (&reverse, &new, &old)] {
+            Registry::diff(from, to)
+                .serialize_file(patch)
+                .map_err(|error| BottleError::InvalidRegistry {
+                    path: patch.clone(),
+                    reason: error.to_string(),
+                })?;
+        }
+        changes.extend(classify(
+            &parse_patch(&read_patch(&forward)?),
+            &parse_patch(&read_patch(&reverse)?),
+        ));
+    }
+    Ok(changes)
+}
+
+/// Reads a serialized registry patch, which may be UTF-16 with a byte order
+/// mark like files written by `regedit`.
+fn read_patch(path: &Path) -> io::Result<String> {
+    let data = fs::read(path)?;
+    Ok(match data.strip_prefix(&[0xFF, 0xFE]) {
+        Some(utf16) => String::from_utf16_lossy(
+            &utf16
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect::<Vec<_>>(),
+        ),
+        None => String::from_utf8_lossy(&data).into_owned(),
+    })
+}
+
+/// The keys and values a registry patch touches.
+#[derive(Debug, Default)]
+struct KeyPatch {
+    /// The patch deletes the whole key.
+    deleted: bool,
+    /// Values the patch sets, or deletes when `true`.
+    values: BTreeMap<String, bool>,
+}
+
+/// Reads the keys and values of a `.reg` patch, ignoring value data.
+fn parse_patch(text: &str) -> BTreeMap<String, KeyPatch> {
+    let mut keys = BTreeMap::<String, KeyPatch>::new();
+    let mut key = None;
+    let mut continued = false;
+    for line in text.lines() {
+        if continued {
+            continued = line.ends_with('\\');
+            continue;
+        }
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix('[') {
+            let Some(end) = rest.rfind(']') else {
+                continue;
+            };
+            key = match rest[..end].strip_prefix('-') {
+                Some(name) => {
+                    keys.entry(name.to_owned()).or_default().deleted = true;
+                    None
+                }
+                None => {
+                    keys.entry(rest[..end].to_owned()).or_default();
+                    Some(rest[..end].to_owned())
+                }
+            };
+            continue;
+        }
+        let (Some(current), Some((name, data))) = (&key, value_name(line)) else {
+            continue;
+        };
+        if let Some(patch) = keys.get_mut(current) {
+            patch.values.insert(name, data.trim() == "-");
+        }
+        continued = data.ends_with('\\');
+    }
+    keys
+}
+
+/// Splits a value line into its unescaped name and the text after `=`.
+fn value_name(line: &str) -> Option<(String, &str)> {
+    if let Some(data) = line.strip_prefix("@=") {
+        return Some((String::new(), data));
+    }
+    let mut chars = line.strip_prefix('"')?.char_indices();
+    let mut name = String::new();
+    while let Some((index, char)) = chars.next() {
+        match char {
+            '\\' => name.push(chars.next()?.1),
+            '"' => return Some((name, line[index + 2..].strip_prefix('=')?)),
+            char => name.push(char),
+        }
+    }
+    None
+}
+
+/// Classifies the entries of a forward patch using the reverse patch.
+///
+/// A value the reverse patch deletes was added, and a key it deletes was
+/// created; anything else the forward patch sets was modified.
+fn classify(
+    forward: &BTreeMap<String, KeyPatch>,
+    reverse: &BTreeMap<String, KeyPatch>,
+) -> Vec<RegistryChange> {
+    let mut changes = Vec::new();
+    for (key, patch) in forward {
+        let undo = reverse.get(key);
+        if patch.deleted || undo.is_some_and(|undo| undo.deleted) {
+            changes.push(RegistryChange {
+                key: key.clone(),
+                value: None,
+                kind: if patch.deleted {
+                    ChangeKind::Removed
+                } else {
+                    ChangeKind::Added
+                },
+            });
+            continue;
+        }
+        for (name, deleted) in &patch.values {
+            let kind = if *deleted {
+                ChangeKind::Removed
+            } else if undo.and_then(|undo| undo.values.get(name)) == Some(&true) {
+                ChangeKind::Added
+            } else {
+                ChangeKind::Modified
+            };
+            changes.push(RegistryChange {
+                key: key.clone(),
+                value: Some(name.clone()),
+                kind,
+            });
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patches_in_both_directions_classify_registry_changes() {
+        let forward = parse_patch(
+            "Windows Registry Editor Version 5.00\r\n\
+             \r\n\
+             [HKEY_CURRENT_USER\\Software\\Game]\r\n\
+             \"Volume\"=dword:00000005\r\n\
+             \"Path\"=-\r\n\
+             @=\"Game\"\r\n\
+             \"Blob\"=hex:00,01,\\\r\n\
+             \x20 02,03\r\n\
+             [HKEY_CURRENT_USER\\Software\\New]\r\n\
+             [-HKEY_CURRENT_USER\\Software\\Old]\r\n",
+        );
+        let reverse = parse_patch(
+            "[HKEY_CURRENT_USER\\Software\\Game]\n\
+             \"Volume\"=dword:00000004\n\
+             \"Path\"=\"C:\\\\Game\"\n\
+             @=-\n\
+             \"Blob\"=-\n\
+             [-HKEY_CURRENT_USER\\Software\\New]\n\
+             [HKEY_CURRENT_USER\\Software\\Old]\n",
+        );
+
+        let change = |key: &str, value: Option<&str>, kind| RegistryChange {
+            key: format!(r"HKEY_CURRENT_USER\Software\{key}"),
+            value: value.map(str::to_owned),
+            kind,
+        };
+        assert_eq!(
+            classify(&forward, &reverse),
+            [
+                change("Game", Some(""), ChangeKind::Added),
+                change("Game", Some("Blob"), ChangeKind::Added),
+                change("Game", Some("Path"), ChangeKind::Removed),
+                change("Game", Some("Volume"), ChangeKind::Modified),
+                change("New", None, ChangeKind::Added),
+                change("Old", None, ChangeKind::Removed),
+            ]
+        );
+    }
+
+    #[test]
+    fn files_are_compared_by_contents() {
+        let root = std::env::temp_dir().join(format!("bottles-next-{}", Uuid::new_v4()));
+        let (old, new) = (root.join("old"), root.join("new"));
+        for dir in [&old, &new] {
+            fs::create_dir_all(dir.join("users/steamuser")).unwrap();
+            fs::write(dir.join("users/steamuser/same.txt"), "same").unwrap();
+        }
+        fs::write(old.join("users/steamuser/save.dat"), "level 1").unwrap();
+        fs::write(new.join("users/steamuser/save.dat"), "level 2").unwrap();
+        for (dir, seconds) in [(&old, 1), (&new, 2)] {
+            for file in ["same.txt", "save.dat"] {
+                fs::File::options()
+                    .write(true)
+                    .open(dir.join("users/steamuser").join(file))
+                    .unwrap()
+                    .set_modified(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(seconds))
+                    .unwrap();
+            }
+        }
+        fs::write(old.join("removed.txt"), "").unwrap();
+        fs::write(new.join("added.txt"), "").unwrap();
+
+        let changes = diff_files(&old, &new, &CancellationToken::new());
+        fs::remove_dir_all(&root).unwrap();
+
+        let change = |path: &str, kind| FileChange {
+            path: path.to_owned(),
+            kind,
+        };
+        assert_eq!(
+            changes.unwrap(),
+            [
+                change(r"C:\added.txt", ChangeKind::Added),
+                change(r"C:\removed.txt", ChangeKind::Removed),
+                change(r"C:\users\steamuser\save.dat", ChangeKind::Modified),
+            ]
+        );
+    }
+}
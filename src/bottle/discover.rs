@@ -0,0 +1,250 @@
+//! Programs found through the Start Menu shortcuts of a prefix.
+
+use std::{
+    collections::HashSet,
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+};
+
+use futures_lite::StreamExt;
+
+use crate::{error::Result, lnk::Shortcut};
+
+use super::state::{Bottle, Program};
+
+/// Start Menu shared by all users, relative to `C:`.
+const COMMON_START_MENU: &str = "ProgramData/Microsoft/Windows/Start Menu";
+
+/// Start Menu of one user, relative to that user's profile.
+const USER_START_MENU: &str = "AppData/Roaming/Microsoft/Windows/Start Menu";
+
+/// Shortcuts larger than this are not links written by an installer.
+const SHORTCUT_LIMIT: u64 = 1024 * 1024;
+
+impl Bottle {
+    /// Returns programs that Start Menu shortcuts in the prefix point to.
+    ///
+    /// Shortcuts are read directly from the `C:` drive, from the Start Menu
+    /// shared by all users and from each user profile's. Every shortcut to an
+    /// `.exe` becomes a candidate named after the shortcut, with the
+    /// shortcut's arguments and working directory. Shortcuts to documents and
+    /// websites, uninstallers, and targets already registered with the same
+    /// arguments are skipped, as are links that cannot be parsed.
+    ///
+    /// Candidates are sorted by name and not registered; pass the ones to
+    /// keep to [`BottleEdit::add_program`](crate::BottleEdit::add_program).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bottle was deleted, the prefix cannot be
+    /// prepared, or a Start Menu directory cannot be read.
+    pub async fn discover_programs(&self) -> Result<Vec<Program>> {
+        self.with_prefix(async |state, _| {
+            let c_drive = self.c_drive_path();
+            let mut roots = vec![(c_drive.join(COMMON_START_MENU), None)];
+            for (profile, user) in entries(&c_drive.join("users")).await? {
+                roots.push((profile.join(USER_START_MENU), Some(user)));
+            }
+
+            let mut seen = state
+                .programs()
+                .map(|program| launch_key(program.executable(), &program.args().join(" ")))
+                .collect::<HashSet<_>>();
+            let mut programs = Vec::new();
+            for (root, user) in roots {
+                for path in shortcuts(&root).await? {
+                    let data = match async_fs::read(&path).await {
+                        Ok(data) => data,
+                        Err(error) => {
+                            tracing::warn!("failed to read {}: {error}", path.display());
+                            continue;
+                        }
+                    };
+                    let Some(name) = path.file_stem().and_then(OsStr::to_str) else {
+                        continue;
+                    };
+                    if let Some(program) = Shortcut::parse(&data)
+                        .and_then(|shortcut| candidate(name, shortcut, user.as_deref()))
+                        && seen.insert(launch_key(program.executable(), &program.args().join(" ")))
+                    {
+                        programs.push(program);
+                    }
+                }
+            }
+            programs.sort_by_key(|program| program.name().to_lowercase());
+            Ok(programs)
+        })
+        .await
+    }
+}
+
+/// Lists the entries of a directory with their file names, or nothing if it
+/// is missing.
+async fn entries(dir: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let mut entries = match async_fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error.into()),
+    };
+    let mut paths = Vec::new();
+    while let Some(entry) = entries.try_next().await? {
+        if let Ok(name) = entry.file_name().into_string() {
+            paths.push((entry.path(), name));
+        }
+    }
+    Ok(paths)
+}
+
+/// Lists `.lnk` files below `root`.
+///
+/// Symbolic links are not followed, so a Start Menu cannot lead the scan
+/// out of the prefix.
+async fn shortcuts(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut pending = vec![root.to_path_buf()];
+    let mut shortcuts = Vec::new();
+    while let Some(dir) = pending.pop() {
+        let mut entries = match async_fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+                ) =>
+            {
+                continue;
+            }
+            Err(error) => return Err(error.into()),
+        };
+        while let Some(entry) = entries.try_next().await? {
+            let metadata = entry.metadata().await?;
+            let path = entry.path();
+            if metadata.is_dir() {
+                pending.push(path);
+            } else if metadata.is_file()
+                && metadata.len() <= SHORTCUT_LIMIT
+                && has_extension(&path.to_string_lossy(), ".lnk")
+            {
+                shortcuts.push(path);
+            }
+        }
+    }
+    Ok(shortcuts)
+}
+
+/// Builds the program a shortcut named `name` launches, if it is one worth
+/// registering.
+///
+/// `user` is the profile the shortcut belongs to, used to expand variables
+/// such as `%APPDATA%` in its target.
+fn candidate(name: &str, shortcut: Shortcut, user: Option<&str>) -> Option<Program> {
+    let target = match shortcut.target {
+        Some(target) => target,
+        None => expand(&shortcut.expandable_target?, user)?,
+    };
+    let file_name = target.rsplit('\\').next().unwrap_or(&target);
+    if !has_extension(file_name, ".exe")
+        || file_name.to_lowercase().starts_with("unins")
+        || name.to_lowercase().contains("uninstall")
+    {
+        return None;
+    }
+    let mut program = Program::new(name, target).ok()?;
+    if let Some(arguments) = shortcut.arguments {
+        program = program.with_args([arguments]);
+    }
+    if let Some(directory) = shortcut.working_directory {
+        program = program.with_working_directory(directory).ok()?;
+    }
+    Some(program)
+}
+
+/// Expands `%VARIABLE%` references to the folders of a 64-bit prefix.
+///
+/// Returns `None` if a variable is not a known folder, or names a profile
+/// folder in a shortcut shared by all users.
+fn expand(template: &str, user: Option<&str>) -> Option<String> {
+    let mut expanded = String::with_capacity(template.len());
+    let mut parts = template.split('%');
+    expanded.push_str(parts.next()?);
+    while let Some(variable) = parts.next() {
+        let rest = parts.next()?;
+        let profile = || user.map(|user| format!(r"C:\users\{user}"));
+        let value = match variable.to_ascii_lowercase().as_str() {
+            "systemdrive" => "C:".to_owned(),
+            "systemroot" | "windir" => r"C:\windows".to_owned(),
+            "programfiles" | "programw6432" => r"C:\Program Files".to_owned(),
+            "programfiles(x86)" => r"C:\Program Files (x86)".to_owned(),
+            "commonprogramfiles" => r"C:\Program Files\Common Files".to_owned(),
+            "commonprogramfiles(x86)" => r"C:\Program Files (x86)\Common Files".to_owned(),
+            "programdata" | "allusersprofile" => r"C:\ProgramData".to_owned(),
+            "public" => r"C:\users\Public".to_owned(),
+            "userprofile" => profile()?,
+            "appdata" => profile()? + r"\AppData\Roaming",
+            "localappdata" => profile()? + r"\AppData\Local",
+            _ => return None,
+        };
+        expanded.push_str(&value);
+        expanded.push_str(rest);
+    }
+    Some(expanded)
+}
+
+/// Identifies launches that run the same Windows command line.
+fn launch_key(executable: &str, arguments: &str) -> (String, String) {
+    (executable.to_lowercase(), arguments.trim().to_owned())
+}
+
+fn has_extension(name: &str, extension: &str) -> bool {
+    name.len() >= extension.len()
+        && name.is_char_boundary(name.len() - extension.len())
+        && name[name.len() - extension.len()..].eq_ignore_ascii_case(extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortcuts_become_programs_unless_they_uninstall() {
+        let shortcut = Shortcut {
+            expandable_target: Some(r"%ProgramFiles(x86)%\Game\Game.EXE".to_owned()),
+            working_directory: Some(r"C:\Program Files (x86)\Game".to_owned()),
+            arguments: Some("-windowed".to_owned()),
+            ..Shortcut::default()
+        };
+        let program = candidate("Game", shortcut, None).unwrap();
+        assert_eq!(program.name(), "Game");
+        assert_eq!(
+            program.executable(),
+            r"C:\Program Files (x86)\Game\Game.EXE"
+        );
+        assert_eq!(program.args(), ["-windowed"]);
+        assert_eq!(
+            program.working_directory(),
+            Some(r"C:\Program Files (x86)\Game")
+        );
+
+        let uninstaller = Shortcut {
+            target: Some(r"C:\Game\unins000.exe".to_owned()),
+            ..Shortcut::default()
+        };
+        let manual = Shortcut {
+            target: Some(r"C:\Game\manual.pdf".to_owned()),
+            ..Shortcut::default()
+        };
+        assert!(candidate("Uninstall Game", uninstaller, None).is_none());
+        assert!(candidate("Manual", manual, None).is_none());
+    }
+
+    #[test]
+    fn profile_variables_need_a_user() {
+        assert_eq!(
+            expand(r"%LocalAppData%\Game\game.exe", Some("steamuser")).as_deref(),
+            Some(r"C:\users\steamuser\AppData\Local\Game\game.exe")
+        );
+        assert_eq!(expand(r"%APPDATA%\game.exe", None), None);
+        assert_eq!(expand(r"%UNKNOWN%\game.exe", None), None);
+        assert_eq!(expand(r"C:\50%.exe", None), None);
+    }
+}
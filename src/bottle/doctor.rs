@@ -0,0 +1,363 @@
+//! Health checks that find why a bottle no longer works.
+
+use std::{io, path::PathBuf};
+
+#[cfg(feature = "fvs")]
+use regdiff_rs::prelude::{Hive, Registry};
+use tokio::sync::watch;
+use uuid::Uuid;
+
+use crate::{
+    Operation, Progress, Stage,
+    addons::{Requirement, Slot},
+    error::{Error, Result},
+    prefix::Prefix,
+    runner::{RunnerKind, detect_runner_kind},
+    utils::exists,
+    winebridge::WineBridgeClient,
+};
+
+use super::{
+    error::BottleError,
+    state::{Bottle, BottleState},
+};
+
+/// First line of every registry hive Wine writes.
+const HIVE_HEADER: &str = "WINE REGISTRY Version 2";
+
+/// Registry hives at the root of a prefix.
+const HIVES: [&str; 3] = ["system.reg", "user.reg", "userdef.reg"];
+
+/// The findings of [`Bottle::diagnose`], empty for a healthy bottle.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Report {
+    /// Problems in the order their checks ran.
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    /// Returns whether no check found a problem.
+    pub fn is_healthy(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+/// One problem found by [`Bottle::diagnose`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Finding {
+    /// The check that found the problem.
+    pub check: Check,
+    /// What is wrong, for display.
+    pub problem: String,
+    /// What is likely to fix it.
+    pub repair: Repair,
+}
+
+/// A check run by [`Bottle::diagnose`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Check {
+    /// Every pinned component is downloaded.
+    Components,
+    /// Every bottle and addon requirement is satisfied.
+    Requirements,
+    /// The runner's files match the launch protocol it was recorded with.
+    RunnerLayout,
+    /// Every Virgo layer resolves to a cached FVS repository with a commit.
+    VirgoLayers,
+    /// The WineBridge port file, if any, belongs to a running WineBridge.
+    BridgePortFile,
+    /// The prefix's registry hives can be parsed.
+    RegistryHives,
+}
+
+impl Check {
+    /// Label reported in [`Stage::Verifying`] while the check runs.
+    fn label(self) -> &'static str {
+        match self {
+            Self::Components => "components",
+            Self::Requirements => "requirements",
+            Self::RunnerLayout => "runner",
+            Self::VirgoLayers => "layers",
+            Self::BridgePortFile => "WineBridge",
+            Self::RegistryHives => "registry",
+        }
+    }
+}
+
+/// A suggested fix for a [`Finding`].
+///
+/// Diagnosis only suggests repairs; callers decide which to apply.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Repair {
+    /// Download the component release again with
+    /// [`Addons::fetch_component`](crate::Addons::fetch_component).
+    FetchComponent(Uuid),
+    /// Select or install addons that satisfy these requirements with
+    /// [`Bottle::set_component`] or [`Bottle::install`].
+    SatisfyRequirements(Vec<Requirement>),
+    /// Select a component for the slot with [`Bottle::set_component`].
+    SetComponent(Slot),
+    /// Recreate the prefix from the bottle's recorded components,
+    /// dependencies, and configuration with [`Bottle::repair`].
+    RebuildPrefix,
+    /// Delete a leftover file; nothing else refers to it.
+    RemoveFile(PathBuf),
+}
+
+impl Bottle {
+    /// Checks the bottle for problems that keep it from starting or running
+    /// programs, and suggests a repair for each.
+    ///
+    /// The checks cover pinned components, addon requirements, the runner's
+    /// layout, Virgo layers, the WineBridge port file, and the registry
+    /// hives. They read files without preparing or starting the prefix, so a
+    /// bottle whose runner cannot load can still be diagnosed. For Virgo
+    /// storage, the port file and hives are read from the bottle's writable
+    /// upper directory, and hives the bottle never changed are not checked.
+    ///
+    /// The operation holds shared bottle access and reports each check as a
+    /// [`Stage::Verifying`] stage. Cancellation is observed between checks.
+    ///
+    /// # Errors
+    ///
+    /// The operation returns an error if the bottle was deleted, cancellation
+    /// is requested, a file cannot be read, or, for Virgo storage, the FVS
+    /// service is unavailable. Problems found are findings, not errors.
+    pub fn diagnose(&self) -> Operation<Report> {
+        let bottle = self.clone();
+        Operation::new(move |progress, cancellation| async move {
+            let _read = bottle.0.write_lock.read().await;
+            let state = bottle.state()?;
+            let mut report = Report::default();
+            for check in [
+                Check::Components,
+                Check::Requirements,
+                Check::RunnerLayout,
+                Check::VirgoLayers,
+                Check::BridgePortFile,
+                Check::RegistryHives,
+            ] {
+                if cancellation.is_cancelled() {
+                    return Err(Error::Cancelled);
+                }
+                report_stage(&progress, check);
+                let findings = bottle.run_check(&state, check).await?;
+                report
+                    .findings
+                    .extend(findings.into_iter().map(|(problem, repair)| Finding {
+                        check,
+                        problem,
+                        repair,
+                    }));
+            }
+            Ok(report)
+        })
+    }
+
+    async fn run_check(&self, state: &BottleState, check: Check) -> Result<Vec<(String, Repair)>> {
+        let directories = self.0.cx.directories();
+        let mut findings = Vec::new();
+        match check {
+            Check::Components => {
+                for component in state.components().values() {
+                    let path = component.path(directories);
+                    if !async_fs::metadata(&path)
+                        .await
+                        .is_ok_and(|entry| entry.is_dir())
+                    {
+                        findings.push((
+                            format!(
+                                "{} component {} {} is missing from {}",
+                                component.slot().as_str(),
+                                component.name(),
+                                component.version(),
+                                path.display()
+                            ),
+                            Repair::FetchComponent(component.id()),
+                        ));
+                    }
+                }
+            }
+            Check::Requirements => match state.validate_requirements() {
+                Ok(()) => {}
+                Err(Error::Bottle(BottleError::RequiresAddon { requirements, .. })) => {
+                    findings.push((
+                        format!("addon requirements are not satisfied: {requirements:?}"),
+                        Repair::SatisfyRequirements(requirements),
+                    ));
+                }
+                Err(Error::Bottle(BottleError::InvalidComponentSlot {
+                    component,
+                    required,
+                })) => {
+                    findings.push((
+                        format!(
+                            "component {component} is recorded in the {} slot",
+                            required.as_str()
+                        ),
+                        Repair::SetComponent(required),
+                    ));
+                }
+                Err(error) => return Err(error),
+            },
+            Check::RunnerLayout => {
+                let Some(runner) = state.component(Slot::Runner) else {
+                    return Ok(findings);
+                };
+                let path = runner.path(directories);
+                let recorded_proton = runner
+                    .requirements()
+                    .contains(&Requirement::Slot(Slot::Umu));
+                let downloaded = exists(&path).await?;
+                match detect_runner_kind(&path).await {
+                    // A missing download is already a component finding.
+                    Err(_) if !downloaded => {}
+                    Err(error) => {
+                        findings.push((error.to_string(), Repair::FetchComponent(runner.id())))
+                    }
+                    Ok(RunnerKind::Proton) if !recorded_proton => findings.push((
+                        format!(
+                            "runner {} is a Proton layout but was recorded as Wine",
+                            runner.name()
+                        ),
+                        Repair::FetchComponent(runner.id()),
+                    )),
+                    Ok(RunnerKind::Wine) if recorded_proton => findings.push((
+                        format!(
+                            "runner {} is a Wine layout but was recorded as Proton",
+                            runner.name()
+                        ),
+                        Repair::FetchComponent(runner.id()),
+                    )),
+                    Ok(RunnerKind::Proton) => {
+                        if let Some(umu) = state.umu() {
+                            let executable = umu.path(directories).join("umu-run");
+                            if exists(umu.path(directories)).await?
+                                && !async_fs::metadata(&executable)
+                                    .await
+                                    .is_ok_and(|entry| entry.is_file())
+                            {
+                                findings.push((
+                                    format!("UMU executable {} is missing", executable.display()),
+                                    Repair::FetchComponent(umu.id()),
+                                ));
+                            }
+                        }
+                    }
+                    Ok(RunnerKind::Wine) => {}
+                }
+            }
+            Check::VirgoLayers => {
+                for repository in state.storage.missing_layers(&self.0.cx).await? {
+                    findings.push((
+                        format!(
+                            "Virgo layer {} is missing from the cache",
+                            repository.display()
+                        ),
+                        Repair::RebuildPrefix,
+                    ));
+                }
+            }
+            Check::BridgePortFile => {
+                let root = self.writable_prefix_path(state);
+                if let Some(port_file) = WineBridgeClient::stale_port_file(&root).await? {
+                    findings.push((
+                        format!(
+                            "WineBridge port file {} names no running WineBridge",
+                            port_file.display()
+                        ),
+                        Repair::RemoveFile(port_file),
+                    ));
+                }
+            }
+            Check::RegistryHives => {
+                let root = self.writable_prefix_path(state);
+                let required = matches!(state.storage, Prefix::Standard);
+                for name in HIVES {
+                    let path = root.join(name);
+                    let text = match async_fs::read(&path).await {
+                        Ok(data) => data,
+                        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                            if required {
+                                findings.push((
+                                    format!("registry hive {} is missing", path.display()),
+                                    Repair::RebuildPrefix,
+                                ));
+                            }
+                            continue;
+                        }
+                        Err(error) => return Err(error.into()),
+                    };
+                    if let Err(problem) = check_hive(path.clone(), name, &text).await {
+                        findings.push((
+                            format!("registry hive {} is damaged: {problem}", path.display()),
+                            Repair::RebuildPrefix,
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(findings)
+    }
+
+    /// Returns where the bottle's own prefix files are stored: the prefix for
+    /// Standard storage and the upper directory for Virgo storage.
+    pub(super) fn writable_prefix_path(&self, state: &BottleState) -> PathBuf {
+        match state.storage {
+            Prefix::Standard => self.prefix_path(),
+            #[cfg(feature = "fvs")]
+            Prefix::Virgo { .. } => self.bottle_path().join("upper"),
+        }
+    }
+}
+
+fn report_stage(progress: &watch::Sender<Option<Progress>>, check: Check) {
+    progress.send_replace(Some(Progress::new(Stage::Verifying {
+        file: check.label().into(),
+    })));
+}
+
+/// Checks that `data` is a registry hive, parsing it fully where the `fvs`
+/// feature provides a parser.
+async fn check_hive(path: PathBuf, name: &str, data: &[u8]) -> std::result::Result<(), String> {
+    let header = data.split(|&byte| byte == b'\n').next().unwrap_or_default();
+    if header.trim_ascii() != HIVE_HEADER.as_bytes() {
+        return Err("missing registry header".into());
+    }
+    #[cfg(feature = "fvs")]
+    {
+        let hive = match name {
+            "system.reg" => Some(Hive::LocalMachine),
+            "user.reg" => Some(Hive::CurrentUser),
+            _ => None,
+        };
+        if let Some(hive) = hive {
+            return blocking::unblock(move || {
+                Registry::try_from(&path, hive)
+                    .map(drop)
+                    .map_err(|error| error.to_string())
+            })
+            .await;
+        }
+    }
+    #[cfg(not(feature = "fvs"))]
+    let _ = (path, name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hives_without_the_wine_header_are_damaged() {
+        let path = std::env::temp_dir().join(format!("bottles-next-{}.reg", Uuid::new_v4()));
+        let check = |data: &[u8]| {
+            futures_lite::future::block_on(check_hive(path.clone(), "userdef.reg", data))
+        };
+
+        assert_eq!(check(b"WINE REGISTRY Version 2\r\n;; All keys\n"), Ok(()));
+        assert!(check(b"\0\0\0\0").is_err());
+        assert!(check(b"").is_err());
+    }
+}
@@ -0,0 +1,136 @@
+//! DOS drive mappings persisted in bottle state.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Context,
+    error::{Error, Result},
+    prefix::dosdevices,
+    proto::Drive,
+};
+
+use super::{
+    error::BottleError,
+    state::{Bottle, BottleState},
+};
+
+/// A drive letter mapped to a host directory through Wine's `dosdevices`.
+///
+/// Mappings are validated on construction: the letter is an ASCII letter other
+/// than `C`, which always addresses the prefix's own `drive_c`, and the host
+/// path is absolute. Letters are stored in lowercase, matching Wine's link
+/// names. Deserialized mappings are validated the same way.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(try_from = "RawDriveMapping")]
+pub struct DriveMapping {
+    letter: char,
+    path: PathBuf,
+}
+
+/// A mapping as persisted, before validation.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawDriveMapping {
+    letter: char,
+    path: PathBuf,
+}
+
+impl TryFrom<RawDriveMapping> for DriveMapping {
+    type Error = Error;
+
+    fn try_from(raw: RawDriveMapping) -> Result<Self> {
+        Self::new(raw.letter, raw.path)
+    }
+}
+
+impl DriveMapping {
+    /// Validates and normalizes a mapping of `letter` to `path`.
+    pub fn new(letter: char, path: impl Into<PathBuf>) -> Result<Self> {
+        let letter = letter.to_ascii_lowercase();
+        if !letter.is_ascii_lowercase() || letter == 'c' {
+            return Err(BottleError::InvalidDriveLetter(letter).into());
+        }
+        let path = path.into();
+        if !path.is_absolute() {
+            return Err(BottleError::InvalidDrivePath(path).into());
+        }
+        Ok(Self { letter, path })
+    }
+
+    /// Returns the lowercase drive letter.
+    pub fn letter(&self) -> char {
+        self.letter
+    }
+
+    /// Returns the host directory exposed through the drive.
+    ///
+    /// The directory need not exist; Wine reports a missing target as an
+    /// unavailable drive.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Bottle {
+    /// Returns the drives Wine currently exposes in the bottle.
+    ///
+    /// Unlike [`BottleState::drive_mappings`], this includes drives that Wine
+    /// created itself, such as `C:` and the default `Z:` mapping of the host
+    /// root. WineBridge starts if necessary, and persisted mappings are applied
+    /// before the query.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bottle was deleted, its prefix cannot be
+    /// prepared, WineBridge cannot start, or the request fails.
+    pub async fn drives(&self) -> Result<Vec<Drive>> {
+        self.with_bridge(|bridge| async move { bridge.list_drives().await })
+            .await
+    }
+
+    /// Applies drive mapping changes committed by [`super::BottleEdit`].
+    ///
+    /// Preparing storage mounts a Virgo prefix so that links are written to the
+    /// bottle's upper directory; it also re-applies every persisted mapping.
+    /// Explicitly unmapped letters are then removed unless a later change in
+    /// the same edit mapped them again.
+    pub(super) async fn apply_drive_changes(
+        state: &BottleState,
+        cx: &Context,
+        unmapped: &[char],
+    ) -> Result<()> {
+        let bottle_path = cx.directories().bottle(state.id);
+        state
+            .storage
+            .prepare(&bottle_path, &state.drives, cx)
+            .await?;
+        let unmapped = unmapped
+            .iter()
+            .copied()
+            .filter(|letter| state.drive_mapping(*letter).is_none())
+            .collect::<Vec<_>>();
+        dosdevices::unmap(&bottle_path.join("prefix"), &unmapped).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserializing_validates_mappings() {
+        let mapping: DriveMapping =
+            serde_json::from_str(r#"{"letter":"D","path":"/mnt/games"}"#).unwrap();
+        assert_eq!(mapping, DriveMapping::new('d', "/mnt/games").unwrap());
+
+        for invalid in [
+            r#"{"letter":"c","path":"/mnt/games"}"#,
+            r#"{"letter":"1","path":"/mnt/games"}"#,
+            r#"{"letter":"d","path":"games"}"#,
+        ] {
+            assert!(serde_json::from_str::<DriveMapping>(invalid).is_err());
+        }
+    }
+}
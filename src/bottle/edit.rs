@@ -1,8 +1,14 @@
 //! Batched edits to persisted bottle configuration.
 
+use std::{io, path::PathBuf};
+
 use uuid::Uuid;
 
+#[cfg(feature = "fvs")]
+use super::backup::BackupPolicy;
 use super::{
+    desktop,
+    drives::DriveMapping,
     error::BottleError,
     state::{Bottle, Program},
 };
@@ -33,6 +39,10 @@ enum Change {
     RemoveProgram(Uuid),
     SetGamescope(GamescopeConfig),
     SetMangoHud(MangoHudConfig),
+    MapDrive(char, PathBuf),
+    UnmapDrive(char),
+    #[cfg(feature = "fvs")]
+    SetBackupPolicy(Option<BackupPolicy>),
 }
 
 impl BottleEdit {
@@ -53,14 +63,15 @@ impl BottleEdit {
 
     /// Sets an environment variable for future WineBridge starts.
     ///
-    /// This does not change an already-running WineBridge. Call [`Bottle::stop`]
+    /// Programs launch through WineBridge and inherit its environment. This
+    /// does not change an already-running WineBridge. Call [`Bottle::stop`]
     /// before the next bridge-backed operation to apply it immediately.
     /// Values stored here are applied after runner-provided variables, so they
     /// can override values such as `WINEPREFIX`, `WINEARCH`, and `PROTONPATH`.
     ///
-    /// At commit time, names must be nonempty and contain neither `=` nor NUL;
-    /// values must not contain NUL. Case and whitespace are preserved, and
-    /// lookup is case-sensitive.
+    /// At commit time, names must be nonempty and contain neither `=`, NUL,
+    /// nor line breaks; values must contain neither NUL nor line breaks. Case
+    /// and whitespace are preserved, and lookup is case-sensitive.
     pub fn set_env(&mut self, key: &str, value: &str) -> &mut Self {
         self.changes
             .push(Change::SetEnv(key.to_owned(), value.to_owned()));
@@ -84,7 +95,9 @@ impl BottleEdit {
 
     /// Removes the program identified by `id`.
     ///
-    /// The edit fails to commit if the program is not registered.
+    /// The edit fails to commit if the program is not registered. Its launch
+    /// logs and any desktop entry from
+    /// [`Bottle::create_desktop_entry`] are deleted once the edit commits.
     pub fn remove_program(&mut self, id: Uuid) -> &mut Self {
         self.changes.push(Change::RemoveProgram(id));
         self
@@ -92,8 +105,9 @@ impl BottleEdit {
 
     /// Replaces the Gamescope configuration used for future WineBridge starts.
     ///
-    /// If WineBridge is already running, stop the bottle after committing so
-    /// that the next bridge-backed operation starts it with the new wrapper.
+    /// Programs launch through WineBridge and share its wrappers. If
+    /// WineBridge is already running, stop the bottle after committing so that
+    /// the next bridge-backed operation starts it with the new wrapper.
     pub fn set_gamescope(&mut self, config: GamescopeConfig) -> &mut Self {
         self.changes.push(Change::SetGamescope(config));
         self
@@ -101,13 +115,46 @@ impl BottleEdit {
 
     /// Replaces the MangoHud configuration used for future WineBridge starts.
     ///
-    /// If WineBridge is already running, stop the bottle after committing so
-    /// that the next bridge-backed operation starts it with the new wrapper.
+    /// Programs launch through WineBridge and share its wrappers. If
+    /// WineBridge is already running, stop the bottle after committing so that
+    /// the next bridge-backed operation starts it with the new wrapper.
     pub fn set_mangohud(&mut self, config: MangoHudConfig) -> &mut Self {
         self.changes.push(Change::SetMangoHud(config));
         self
     }
 
+    /// Maps the DOS drive `letter` to the host directory `host_path`.
+    ///
+    /// An existing mapping for the same letter is replaced. At commit time the
+    /// letter must be an ASCII letter other than `C` and the path must be
+    /// absolute; see [`DriveMapping::new`]. The link is created when the edit
+    /// commits and is re-applied whenever the prefix is prepared.
+    pub fn map_drive(&mut self, letter: char, host_path: impl Into<PathBuf>) -> &mut Self {
+        self.changes
+            .push(Change::MapDrive(letter, host_path.into()));
+        self
+    }
+
+    /// Removes the DOS drive link for `letter`.
+    ///
+    /// This also removes links that Wine created itself, such as `Z:`.
+    /// Unmapping a letter without a link succeeds, but `C:` cannot be unmapped.
+    pub fn unmap_drive(&mut self, letter: char) -> &mut Self {
+        self.changes.push(Change::UnmapDrive(letter));
+        self
+    }
+
+    /// Replaces the automatic backup policy, or turns automatic backups off
+    /// with `None`.
+    ///
+    /// Existing backups are kept; call [`Bottle::prune_backups`] to apply a
+    /// shorter retention to them right away.
+    #[cfg(feature = "fvs")]
+    pub fn set_backup_policy(&mut self, policy: Option<BackupPolicy>) -> &mut Self {
+        self.changes.push(Change::SetBackupPolicy(policy));
+        self
+    }
+
     /// Validates, persists, and publishes all queued changes.
     ///
     /// Changes are applied in call order, so a later change may supersede an
@@ -119,27 +166,30 @@ impl BottleEdit {
     /// # Errors
     ///
     /// Returns an error for a deleted bottle, a missing program removal, an
-    /// invalid environment variable, or a persistence failure.
+    /// invalid environment variable or drive mapping, a persistence failure,
+    /// or a failure to prepare the prefix when drive mappings changed.
     pub async fn commit(self) -> Result<()> {
         let BottleEdit { bottle, changes } = self;
+        let removed = changes
+            .iter()
+            .filter_map(|change| match change {
+                Change::RemoveProgram(id) => Some(*id),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
         bottle
-            .update(async move |state, _| {
+            .update(async move |state, cx| {
+                let mut drives_changed = false;
+                let mut unmapped = Vec::new();
                 for change in changes {
                     match change {
                         Change::Rename(name) => state.name = name,
                         Change::SetEnv(key, value) => {
-                            if key.is_empty() || key.contains('=') || key.contains('\0') {
-                                return Err(BottleError::InvalidEnvironmentName(key).into());
-                            }
-                            if value.contains('\0') {
-                                return Err(BottleError::InvalidEnvironmentValue(key).into());
-                            }
+                            validate_env(&key, Some(&value))?;
                             state.environment.insert(key, value);
                         }
                         Change::UnsetEnv(key) => {
-                            if key.is_empty() || key.contains('=') || key.contains('\0') {
-                                return Err(BottleError::InvalidEnvironmentName(key).into());
-                            }
+                            validate_env(&key, None)?;
                             state.environment.remove(&key);
                         }
                         Change::AddProgram(program) => {
@@ -153,10 +203,57 @@ impl BottleEdit {
                         }
                         Change::SetGamescope(config) => state.wrappers.gamescope = config,
                         Change::SetMangoHud(config) => state.wrappers.mangohud = config,
+                        Change::MapDrive(letter, path) => {
+                            let mapping = DriveMapping::new(letter, path)?;
+                            state
+                                .drives
+                                .retain(|drive| drive.letter() != mapping.letter());
+                            state.drives.push(mapping);
+                            state.drives.sort_by_key(DriveMapping::letter);
+                            drives_changed = true;
+                        }
+                        Change::UnmapDrive(letter) => {
+                            let letter = letter.to_ascii_lowercase();
+                            if !letter.is_ascii_lowercase() || letter == 'c' {
+                                return Err(BottleError::InvalidDriveLetter(letter).into());
+                            }
+                            state.drives.retain(|drive| drive.letter() != letter);
+                            unmapped.push(letter);
+                            drives_changed = true;
+                        }
+                        #[cfg(feature = "fvs")]
+                        Change::SetBackupPolicy(policy) => state.backup = policy,
                     }
                 }
+                if drives_changed {
+                    Bottle::apply_drive_changes(state, &cx, &unmapped).await?;
+                }
                 Ok(())
             })
-            .await
+            .await?;
+
+        for id in removed {
+            let logs = bottle.log_dir(&id.to_string());
+            match async_fs::remove_dir_all(&logs).await {
+                Ok(()) => {}
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                Err(error) => tracing::warn!("failed to remove {}: {error}", logs.display()),
+            }
+            if let Err(error) = desktop::remove(bottle.0.cx.directories(), bottle.0.id, id).await {
+                tracing::warn!("failed to remove desktop entry of program {id}: {error}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks an environment variable name and, when given, its value.
+pub(super) fn validate_env(key: &str, value: Option<&str>) -> Result<()> {
+    if key.is_empty() || key.contains(['=', '\0', '\r', '\n']) {
+        return Err(BottleError::InvalidEnvironmentName(key.to_owned()).into());
+    }
+    if value.is_some_and(|value| value.contains(['\0', '\r', '\n'])) {
+        return Err(BottleError::InvalidEnvironmentValue(key.to_owned()).into());
     }
+    Ok(())
 }
@@ -1,7 +1,6 @@
 //! Bottle-specific errors exposed through the crate's top-level error type.
 
-#[cfg(feature = "fvs")]
-use std::path::PathBuf;
+use std::{io, path::PathBuf, process::ExitStatus, time::Duration};
 
 use thiserror::Error;
 use uuid::Uuid;
@@ -28,33 +27,128 @@ pub enum BottleError {
         /// UUID found in the loaded metadata.
         actual: Uuid,
     },
+    /// An archive passed to import lacks `bottle.toml` or the prefix.
+    #[error("not a bottle archive: {0}")]
+    InvalidArchive(PathBuf),
+    /// A host path passed to a launch is relative or not exposed by any drive.
+    #[error("host path {0} is not reachable from the bottle")]
+    UnmappedHostPath(PathBuf),
+    /// A Windows launch directory has no drive letter or its drive has no link.
+    #[error("Windows path {0:?} is not on a mapped drive")]
+    UnmappedWindowsPath(String),
+    /// Copying a prefix for a duplicate bottle failed.
+    #[error("prefix copy exited unsuccessfully: {0}")]
+    CopyFailed(ExitStatus),
     /// A program definition is malformed.
     #[error("invalid program: {0}")]
     InvalidProgram(String),
-    /// An environment variable name is empty or contains `=` or NUL.
+    /// A program's Gamescope or MangoHud configuration differs from the
+    /// running WineBridge's wrappers.
+    #[error("WineBridge is running with other wrappers; stop the bottle to launch program {0}")]
+    WrappersInUse(Uuid),
+    /// A desktop entry launcher command is blank or spans several lines.
+    #[error("invalid desktop entry launcher {0:?}: it must be a single non-blank line")]
+    InvalidLauncher(String),
+    /// A repair was interrupted and left preserved user files behind.
+    #[error("an interrupted repair left preserved files in {0}; move them back or remove it")]
+    RepairInterrupted(PathBuf),
+    /// A snapshot state ID prefix matches more than one snapshot.
+    #[error("snapshot {0:?} is ambiguous")]
+    AmbiguousSnapshot(String),
+    /// A pinned snapshot cannot be deleted.
+    #[error("snapshot {0} is pinned")]
+    PinnedSnapshot(String),
+    /// A registry hive in a bottle or snapshot is missing or cannot be parsed.
+    #[error("registry hive {path} cannot be parsed: {reason}")]
+    InvalidRegistry {
+        /// Hive file that failed to parse.
+        path: PathBuf,
+        /// Parser failure.
+        reason: String,
+    },
+    /// An inspected file is not a Portable Executable image.
+    #[error("{0} is not a Windows executable")]
+    NotAnExecutable(PathBuf),
+    /// An environment variable name is empty or contains `=`, NUL, or a line
+    /// break.
     #[error(
-        "invalid environment variable name {0:?}: names must be non-empty and contain neither '=' nor NUL"
+        "invalid environment variable name {0:?}: names must be non-empty and contain neither '=', NUL, nor line breaks"
     )]
     InvalidEnvironmentName(String),
-    /// An environment variable value contains NUL.
-    #[error("environment variable {0:?} contains NUL in its value")]
+    /// An environment variable value contains NUL or a line break.
+    #[error("environment variable {0:?} contains NUL or a line break in its value")]
     InvalidEnvironmentValue(String),
-    /// A DLL name is empty or contains NUL.
+    /// A DLL name is empty or contains NUL, or a separator of
+    /// `WINEDLLOVERRIDES` in a [`crate::Program`] override.
     ///
-    /// This variant is reserved for local validation. The current DLL override
-    /// methods delegate validation to WineBridge and return
-    /// [`crate::error::Error::Status`] instead.
-    #[error("DLL name {0:?} must be non-empty and contain no NUL bytes")]
+    /// The bottle-wide DLL override methods delegate validation to WineBridge
+    /// and return [`crate::error::Error::Status`] instead.
+    #[error("DLL name {0:?} must be non-empty and contain no NUL bytes or override separators")]
     InvalidDllName(String),
     /// [`crate::DllOverrideMode::Unspecified`] was passed as an override mode.
     #[error("DLL override mode is required")]
     DllOverrideModeRequired,
+    /// [`crate::ServiceStartType::Unspecified`] was passed as a service start type.
+    #[error("service start type is required")]
+    ServiceStartTypeRequired,
+    /// No Windows service is registered with the requested name.
+    #[error("service {0:?} was not found")]
+    ServiceNotFound(String),
+    /// A drive letter is not an ASCII letter or addresses the prefix's `C:` drive.
+    #[error("drive letter {0:?} cannot be mapped")]
+    InvalidDriveLetter(char),
+    /// A drive mapping target is not an absolute host path.
+    #[error("drive target {0} must be an absolute path")]
+    InvalidDrivePath(PathBuf),
+    /// A Windows path is not an absolute `C:` path, escapes it with `..`, or
+    /// names the drive root where an entry is required.
+    #[error("Windows path {0:?} is not a valid location on the C: drive")]
+    InvalidWindowsPath(String),
+    /// A registry key path does not start with a supported hive.
+    #[error("registry key {0:?} is not below HKEY_CURRENT_USER or HKEY_LOCAL_MACHINE")]
+    InvalidRegistryKey(String),
+    /// A `C:` path passes through a symbolic link where the library only
+    /// works on files inside the prefix.
+    #[error("Windows path {0:?} passes through a symbolic link")]
+    LinkedPath(String),
     /// No program is registered with the requested UUID.
     #[error("program {0} was not found")]
     ProgramNotFound(Uuid),
+    /// A save-game rule is not a path below the user profile.
+    #[error("invalid save path rule {0:?}")]
+    InvalidSavePath(String),
+    /// A save key is blank, starts with `.`, or contains a path separator or
+    /// NUL.
+    #[error("invalid save key {0:?}")]
+    InvalidSaveKey(String),
+    /// The prefix has no user profile to resolve save-game rules against.
+    #[error("the prefix has no user profile")]
+    NoUserProfile,
+    /// No files match the save-game rules of a program.
+    #[error("no save files were found for program {0}")]
+    NoSaveFiles(Uuid),
+    /// A pre-launch [`crate::Hook`] failed, so the launch was aborted.
+    #[error("hook {} failed: {failure}", command.display())]
+    HookFailed {
+        /// Host executable of the failed hook.
+        command: PathBuf,
+        /// How the hook failed.
+        failure: HookFailure,
+    },
     /// No selected component occupies the requested slot.
     #[error("component slot {0:?} is not installed")]
     ComponentNotInstalled(Slot),
+    /// No installed dependency has the requested release identifier.
+    #[error("dependency {0} is not installed")]
+    DependencyNotInstalled(Uuid),
+    /// A dependency cannot be uninstalled while other installed addons require it.
+    #[error("dependency {dependency} is required by {required_by:?}")]
+    DependencyInUse {
+        /// Dependency whose removal was requested.
+        dependency: Uuid,
+        /// Installed addons with a requirement only this dependency satisfies.
+        required_by: Vec<Uuid>,
+    },
     /// One or more dependencies must be downloaded or installed before the operation.
     #[error("addon requirements are not satisfied: {requirements:?}")]
     RequiresAddon {
@@ -68,6 +162,21 @@ pub enum BottleError {
     InvalidComponentSlot { component: Uuid, required: Slot },
 }
 
+/// How a [`crate::Hook`] failed, carried by [`BottleError::HookFailed`].
+#[derive(Debug, Error)]
+pub enum HookFailure {
+    /// The command could not be started, waited for, or killed after its
+    /// timeout.
+    #[error("could not run: {0}")]
+    Spawn(io::Error),
+    /// The command exited unsuccessfully.
+    #[error("exited unsuccessfully: {0}")]
+    Exited(ExitStatus),
+    /// The command outlived its timeout and was killed.
+    #[error("timed out after {0:?}")]
+    TimedOut(Duration),
+}
+
 /// Virgo-specific failures carried by [`crate::error::Error::Virgo`].
 #[cfg(feature = "fvs")]
 #[derive(Debug, Error)]
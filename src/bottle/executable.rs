@@ -0,0 +1,200 @@
+//! Metadata read from executables inside a bottle.
+
+use crate::{
+    error::Result,
+    pe::{IconEntry, Image, VersionInfo},
+};
+
+use super::{error::BottleError, run, run::RunArg, state::Bottle};
+
+/// `IMAGE_FILE_DLL`: the image is a library rather than a program.
+const IMAGE_FILE_DLL: u16 = 0x2000;
+
+/// Instruction set an executable was built for.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ExecutableArchitecture {
+    /// 32-bit x86.
+    X86,
+    /// 64-bit x86.
+    X86_64,
+    /// 32-bit ARM, including Thumb-2.
+    Arm,
+    /// 64-bit ARM.
+    Arm64,
+    /// Any other PE machine type.
+    Unknown(u16),
+}
+
+impl ExecutableArchitecture {
+    fn from_machine(machine: u16) -> Self {
+        match machine {
+            0x014c => Self::X86,
+            0x8664 => Self::X86_64,
+            0x01c0 | 0x01c4 => Self::Arm,
+            0xaa64 => Self::Arm64,
+            machine => Self::Unknown(machine),
+        }
+    }
+
+    /// Returns whether the executable uses a 64-bit instruction set.
+    pub fn is_64_bit(self) -> bool {
+        matches!(self, Self::X86_64 | Self::Arm64)
+    }
+}
+
+/// Windows subsystem an executable starts in.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ExecutableSubsystem {
+    /// A graphical program that opens its own windows.
+    Gui,
+    /// A console program that expects a terminal.
+    Console,
+    /// Any other subsystem, such as a native driver or EFI application.
+    Other(u16),
+}
+
+impl ExecutableSubsystem {
+    fn from_raw(subsystem: u16) -> Self {
+        match subsystem {
+            2 => Self::Gui,
+            3 => Self::Console,
+            subsystem => Self::Other(subsystem),
+        }
+    }
+}
+
+/// Dimensions of one image in an executable's icon group.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct IconSize {
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+    /// Bits per pixel; 32 for images with an alpha channel.
+    pub bit_count: u16,
+}
+
+/// Headers, version resources, and icons of an executable.
+///
+/// Version strings are taken from the US English table when the executable
+/// has one and from its first table otherwise. Blank strings are `None`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExecutableInfo {
+    /// Instruction set the executable was built for.
+    pub architecture: ExecutableArchitecture,
+    /// Subsystem the executable starts in.
+    pub subsystem: ExecutableSubsystem,
+    /// Whether the image is a DLL rather than a program.
+    pub is_dll: bool,
+    /// The `ProductName` version string.
+    pub product_name: Option<String>,
+    /// The `FileDescription` version string.
+    pub file_description: Option<String>,
+    /// The `CompanyName` version string.
+    pub company_name: Option<String>,
+    /// The `FileVersion` version string, or the numeric file version as
+    /// `major.minor.build.revision` when the string is missing.
+    pub file_version: Option<String>,
+    /// Icon groups in resource order, each listing the sizes it provides.
+    /// Windows shows the first group as the executable's icon.
+    pub icon_groups: Vec<Vec<IconSize>>,
+}
+
+impl ExecutableInfo {
+    /// Returns a name suitable for [`Program::new`](super::Program::new): the
+    /// product name, or the file description when there is none.
+    pub fn display_name(&self) -> Option<&str> {
+        self.product_name
+            .as_deref()
+            .or(self.file_description.as_deref())
+    }
+
+    fn new(image: &Image) -> Self {
+        let version = image.version_info();
+        let string = |key: &str| {
+            let value = version.as_ref()?.strings.get(key)?.trim();
+            (!value.is_empty()).then(|| value.to_owned())
+        };
+        Self {
+            architecture: ExecutableArchitecture::from_machine(image.machine),
+            subsystem: ExecutableSubsystem::from_raw(image.subsystem),
+            is_dll: image.characteristics & IMAGE_FILE_DLL != 0,
+            product_name: string("ProductName"),
+            file_description: string("FileDescription"),
+            company_name: string("CompanyName"),
+            file_version: string("FileVersion").or_else(|| fixed_version(version.as_ref()?)),
+            icon_groups: image
+                .icon_groups()
+                .into_iter()
+                .map(|group| group.iter().map(icon_size).collect())
+                .collect(),
+        }
+    }
+}
+
+fn fixed_version(version: &VersionInfo) -> Option<String> {
+    let [major, minor, build, revision] = version.file_version?;
+    Some(format!("{major}.{minor}.{build}.{revision}"))
+}
+
+fn icon_size(entry: &IconEntry) -> IconSize {
+    IconSize {
+        width: entry.width,
+        height: entry.height,
+        bit_count: entry.bit_count,
+    }
+}
+
+impl Bottle {
+    /// Reads the headers, version resources, and icons of an executable.
+    ///
+    /// `path` is a Windows path such as `C:\Games\game.exe`, resolved through
+    /// the bottle's drives, or an absolute host path. The file is parsed, not
+    /// run, and only its headers and resources are read.
+    ///
+    /// Prefixes are 64-bit and run 32-bit x86 programs through WoW64, so
+    /// either x86 architecture launches; other architectures need an
+    /// emulating runner.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::UnmappedWindowsPath`] or
+    /// [`BottleError::UnmappedHostPath`] if `path` cannot be resolved,
+    /// [`BottleError::NotAnExecutable`] if the file is not a PE image, or an
+    /// error if the prefix cannot be prepared or the file cannot be read.
+    pub async fn inspect_executable(&self, path: impl Into<RunArg>) -> Result<ExecutableInfo> {
+        let path = path.into();
+        let path = self
+            .with_prefix(async |_, _| run::host_path(&self.prefix_path(), path).await)
+            .await?;
+        match Image::read(&path).await? {
+            Some(image) => Ok(ExecutableInfo::new(&image)),
+            None => Err(BottleError::NotAnExecutable(path).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn machine_types_map_to_architectures() {
+        assert_eq!(
+            ExecutableArchitecture::from_machine(0x014c),
+            ExecutableArchitecture::X86
+        );
+        assert!(ExecutableArchitecture::from_machine(0x8664).is_64_bit());
+        assert!(ExecutableArchitecture::from_machine(0xaa64).is_64_bit());
+        assert!(!ExecutableArchitecture::from_machine(0x01c4).is_64_bit());
+        assert_eq!(
+            ExecutableArchitecture::from_machine(0x0200),
+            ExecutableArchitecture::Unknown(0x0200)
+        );
+        assert_eq!(ExecutableSubsystem::from_raw(2), ExecutableSubsystem::Gui);
+        assert_eq!(
+            ExecutableSubsystem::from_raw(10),
+            ExecutableSubsystem::Other(10)
+        );
+    }
+}
@@ -0,0 +1,461 @@
+//! File access confined to a bottle's `C:` drive.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use futures_lite::StreamExt;
+
+use crate::{error::Result, proto::PathInfo};
+
+use super::{error::BottleError, registry::not_found_as_none, state::Bottle};
+
+/// A file browser over one bottle's `C:` drive.
+///
+/// Obtain this handle from [`Bottle::files`]. Paths are Windows paths such as
+/// `C:\users\steamuser\Documents`; either separator is accepted and the drive
+/// letter is case-insensitive. Paths on other drives and paths that use `..`
+/// are rejected with [`BottleError::InvalidWindowsPath`] before WineBridge is
+/// contacted, so mapped drives cannot be reached through this handle.
+///
+/// Requests go through WineBridge, which starts on demand, so Standard and
+/// Virgo storage behave the same. Clones share the underlying bottle.
+#[derive(Clone)]
+pub struct BottleFiles {
+    bottle: Bottle,
+}
+
+impl Bottle {
+    /// Returns a file browser confined to this bottle's `C:` drive.
+    pub fn files(&self) -> BottleFiles {
+        BottleFiles {
+            bottle: self.clone(),
+        }
+    }
+}
+
+impl BottleFiles {
+    /// Lists the entries of a directory in unspecified order.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::InvalidWindowsPath`] for a path outside `C:`.
+    /// Missing directories and other request failures are returned as
+    /// [`crate::error::Error::Status`]; prefix and bridge failures are also
+    /// returned.
+    pub async fn list(&self, path: &str) -> Result<Vec<PathInfo>> {
+        let path = CDrivePath::parse(path)?.windows();
+        self.bottle
+            .with_bridge(move |bridge| async move { bridge.list_directory(path).await })
+            .await
+    }
+
+    /// Returns metadata for a file or directory, or `None` when it is missing.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::InvalidWindowsPath`] for a path outside `C:`, or
+    /// an error if the prefix or bridge operation fails.
+    pub async fn info(&self, path: &str) -> Result<Option<PathInfo>> {
+        let path = CDrivePath::parse(path)?.windows();
+        self.bottle
+            .with_bridge(
+                move |bridge| async move { not_found_as_none(bridge.path_info(path).await) },
+            )
+            .await
+    }
+
+    /// Reports whether a file or directory exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::InvalidWindowsPath`] for a path outside `C:`, or
+    /// an error if the prefix or bridge operation fails.
+    pub async fn exists(&self, path: &str) -> Result<bool> {
+        let path = CDrivePath::parse(path)?.windows();
+        self.bottle
+            .with_bridge(move |bridge| async move { bridge.exists(path).await })
+            .await
+    }
+
+    /// Creates a directory and any missing parents.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::InvalidWindowsPath`] for a path outside `C:` or
+    /// for the drive root. Prefix and bridge failures are also returned.
+    pub async fn create_directory(&self, path: &str) -> Result<()> {
+        let path = CDrivePath::parse_entry(path)?.windows();
+        self.bottle
+            .with_bridge(move |bridge| async move { bridge.create_directory(path).await })
+            .await
+    }
+
+    /// Copies a file within the drive, replacing an existing destination.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::InvalidWindowsPath`] if either path is outside
+    /// `C:` or is the drive root. Prefix and bridge failures are also returned.
+    pub async fn copy(&self, source: &str, destination: &str) -> Result<()> {
+        let source = CDrivePath::parse_entry(source)?.windows();
+        let destination = CDrivePath::parse_entry(destination)?.windows();
+        self.bottle
+            .with_bridge(move |bridge| async move { bridge.copy_file(source, destination).await })
+            .await
+    }
+
+    /// Moves or renames a file or directory within the drive.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::InvalidWindowsPath`] if either path is outside
+    /// `C:` or is the drive root. Prefix and bridge failures are also returned.
+    pub async fn rename(&self, source: &str, destination: &str) -> Result<()> {
+        let source = CDrivePath::parse_entry(source)?.windows();
+        let destination = CDrivePath::parse_entry(destination)?.windows();
+        self.bottle
+            .with_bridge(move |bridge| async move { bridge.move_path(source, destination).await })
+            .await
+    }
+
+    /// Deletes a file or an empty directory.
+    ///
+    /// Deleting a missing path succeeds.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::InvalidWindowsPath`] for a path outside `C:` or
+    /// for the drive root. Prefix and bridge failures are also returned.
+    pub async fn delete(&self, path: &str) -> Result<()> {
+        let path = CDrivePath::parse_entry(path)?.windows();
+        self.bottle
+            .with_bridge(move |bridge| async move {
+                not_found_as_none(bridge.delete_file(path).await).map(drop)
+            })
+            .await
+    }
+
+    /// Recursively deletes a directory and all of its descendants.
+    ///
+    /// Deleting a missing directory succeeds.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::InvalidWindowsPath`] for a path outside `C:` or
+    /// for the drive root. Prefix and bridge failures are also returned.
+    pub async fn delete_tree(&self, path: &str) -> Result<()> {
+        let path = CDrivePath::parse_entry(path)?.windows();
+        self.bottle
+            .with_bridge(move |bridge| async move {
+                not_found_as_none(bridge.delete_directory_tree(path).await).map(drop)
+            })
+            .await
+    }
+
+    /// Copies a host file or directory tree to `destination` on the drive.
+    ///
+    /// `destination` names the copied entry itself, not its parent. Missing
+    /// parents are created and existing files are replaced. Directory trees
+    /// are copied recursively; symbolic links and special files inside them are
+    /// skipped. The copy is written through the prepared prefix on the host, so
+    /// it does not depend on WineBridge file transfer support.
+    ///
+    /// Symbolic links already on the drive are never followed, except the
+    /// links Wine creates from a user's shell folders, such as
+    /// `C:\users\steamuser\Documents`, to the matching host folders.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::InvalidWindowsPath`] if `destination` is outside
+    /// `C:` or is the drive root, and [`BottleError::LinkedPath`] if the copy
+    /// would write through any other symbolic link, whether at `destination`,
+    /// at one of its parents, or below it. Host I/O and prefix failures are
+    /// also returned.
+    pub async fn import(&self, source: impl AsRef<Path>, destination: &str) -> Result<()> {
+        let source = source.as_ref();
+        let destination = CDrivePath::parse_entry(destination)?;
+        let drive_c = self.bottle.c_drive_path();
+        self.bottle
+            .with_prefix(async |_, _| {
+                destination.ensure_importable(&drive_c).await?;
+                copy_tree(source, &drive_c, &destination).await
+            })
+            .await
+    }
+}
+
+/// A validated location on the `C:` drive.
+pub(crate) struct CDrivePath {
+    /// Path relative to `drive_c`, containing only normal components.
+    pub(crate) relative: PathBuf,
+}
+
+impl CDrivePath {
+    /// Parses an absolute `C:` path, allowing the drive root.
+    pub(crate) fn parse(path: &str) -> Result<Self> {
+        let invalid = || BottleError::InvalidWindowsPath(path.to_owned());
+        let rest = path
+            .strip_prefix("C:")
+            .or_else(|| path.strip_prefix("c:"))
+            .ok_or_else(invalid)?;
+        if !(rest.is_empty() || rest.starts_with(['\\', '/'])) {
+            return Err(invalid().into());
+        }
+        let mut relative = PathBuf::new();
+        for part in rest.split(['\\', '/']) {
+            match part {
+                "" | "." => {}
+                ".." => return Err(invalid().into()),
+                part if part.contains(['\0', ':']) => return Err(invalid().into()),
+                part => relative.push(part),
+            }
+        }
+        Ok(Self { relative })
+    }
+
+    /// Parses a `C:` path that names an entry below the drive root.
+    pub(crate) fn parse_entry(path: &str) -> Result<Self> {
+        let parsed = Self::parse(path)?;
+        if parsed.relative.as_os_str().is_empty() {
+            return Err(BottleError::InvalidWindowsPath(path.to_owned()).into());
+        }
+        Ok(parsed)
+    }
+
+    /// Returns the canonical Windows spelling passed to WineBridge.
+    pub(crate) fn windows(&self) -> String {
+        let parts = self
+            .relative
+            .iter()
+            .map(|part| part.to_string_lossy())
+            .collect::<Vec<_>>();
+        format!(r"C:\{}", parts.join("\\"))
+    }
+
+    /// Rejects the path if one of its parent directories below `drive_c` is a
+    /// symbolic link, which would make a write reach outside the prefix.
+    pub(crate) async fn ensure_unlinked(&self, drive_c: &Path) -> Result<()> {
+        let mut current = PathBuf::from(drive_c);
+        let parents = self.relative.parent().into_iter().flat_map(Path::iter);
+        for part in parents {
+            current.push(part);
+            match async_fs::symlink_metadata(&current).await {
+                Ok(metadata) if metadata.file_type().is_symlink() => {
+                    return Err(BottleError::LinkedPath(self.windows()).into());
+                }
+                Ok(_) => {}
+                Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+                Err(error) => return Err(error.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Rejects the path if it or one of its parents below `drive_c` is a
+    /// symbolic link other than a [shell folder link](is_shell_folder).
+    async fn ensure_importable(&self, drive_c: &Path) -> Result<()> {
+        let mut current = PathBuf::from(drive_c);
+        for (depth, part) in self.relative.iter().enumerate() {
+            current.push(part);
+            match async_fs::symlink_metadata(&current).await {
+                Ok(metadata)
+                    if metadata.file_type().is_symlink()
+                        && !is_shell_folder(&self.relative, depth) =>
+                {
+                    return Err(BottleError::LinkedPath(self.windows()).into());
+                }
+                Ok(_) => {}
+                Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+                Err(error) => return Err(error.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Folders in `users\<name>` that Wine links to the host user's folders.
+const SHELL_FOLDERS: [&str; 7] = [
+    "Desktop",
+    "Documents",
+    "Downloads",
+    "Music",
+    "Pictures",
+    "Templates",
+    "Videos",
+];
+
+/// Reports whether the component of `relative` at `depth` is a shell folder
+/// such as `users\steamuser\Documents`.
+fn is_shell_folder(relative: &Path, depth: usize) -> bool {
+    let mut parts = relative.iter();
+    depth == 2
+        && parts.next().is_some_and(|part| part == "users")
+        && parts
+            .nth(1)
+            .is_some_and(|part| SHELL_FOLDERS.iter().any(|folder| part == *folder))
+}
+
+/// Recursively copies regular files and directories, skipping other entries.
+///
+/// Entries below `destination` are inspected without following symbolic
+/// links, so an existing link there is rejected rather than written through.
+async fn copy_tree(source: &Path, drive_c: &Path, destination: &CDrivePath) -> Result<()> {
+    let metadata = async_fs::metadata(source).await?;
+    let root = drive_c.join(&destination.relative);
+    if metadata.is_file() {
+        if let Some(parent) = root.parent() {
+            async_fs::create_dir_all(parent).await?;
+        }
+        async_fs::copy(source, root).await?;
+        return Ok(());
+    }
+    if !metadata.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a file or directory", source.display()),
+        )
+        .into());
+    }
+
+    let mut pending = vec![(source.to_path_buf(), destination.relative.clone())];
+    while let Some((source, relative)) = pending.pop() {
+        let directory = drive_c.join(&relative);
+        async_fs::create_dir_all(&directory).await?;
+        let mut entries = async_fs::read_dir(&source).await?;
+        while let Some(entry) = entries.try_next().await? {
+            let file_type = entry.file_type().await?;
+            let relative = relative.join(entry.file_name());
+            if !(file_type.is_dir() || file_type.is_file()) {
+                tracing::debug!(path = %entry.path().display(), "skipping non-regular import entry");
+                continue;
+            }
+            let target = drive_c.join(&relative);
+            match async_fs::symlink_metadata(&target).await {
+                Ok(metadata) if metadata.file_type().is_symlink() => {
+                    return Err(BottleError::LinkedPath(CDrivePath { relative }.windows()).into());
+                }
+                Ok(_) => {}
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                Err(error) => return Err(error.into()),
+            }
+            if file_type.is_dir() {
+                pending.push((entry.path(), relative));
+            } else {
+                async_fs::copy(entry.path(), target).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn c_drive_paths_are_normalized_and_confined() {
+        let path = CDrivePath::parse(r"c:/users\steamuser//./Documents\").unwrap();
+        assert_eq!(path.relative, Path::new("users/steamuser/Documents"));
+        assert_eq!(path.windows(), r"C:\users\steamuser\Documents");
+        assert_eq!(CDrivePath::parse("C:").unwrap().windows(), r"C:\");
+
+        for invalid in [
+            r"D:\games",
+            r"C:relative",
+            r"C:\users\..\..\escape",
+            r"\\server\share",
+            r"C:\file:stream",
+            "users",
+        ] {
+            assert!(
+                CDrivePath::parse(invalid).is_err(),
+                "{invalid} should be rejected"
+            );
+        }
+        assert!(CDrivePath::parse_entry(r"C:\").is_err());
+    }
+
+    #[test]
+    fn import_copies_directory_trees() {
+        futures_lite::future::block_on(async {
+            let root = std::env::temp_dir().join(format!("bottles-next-{}", uuid::Uuid::new_v4()));
+            let source = root.join("source");
+            std::fs::create_dir_all(source.join("nested")).unwrap();
+            std::fs::write(source.join("top.txt"), "top").unwrap();
+            std::fs::write(source.join("nested/inner.txt"), "inner").unwrap();
+
+            let destination = root.join("drive_c/imported");
+            let entry = CDrivePath::parse_entry(r"C:\imported").unwrap();
+            copy_tree(&source, &root.join("drive_c"), &entry)
+                .await
+                .unwrap();
+
+            assert_eq!(
+                std::fs::read_to_string(destination.join("top.txt")).unwrap(),
+                "top"
+            );
+            assert_eq!(
+                std::fs::read_to_string(destination.join("nested/inner.txt")).unwrap(),
+                "inner"
+            );
+            std::fs::remove_dir_all(root).unwrap();
+        });
+    }
+
+    #[test]
+    fn linked_parents_are_rejected() {
+        futures_lite::future::block_on(async {
+            let root = std::env::temp_dir().join(format!("bottles-next-{}", uuid::Uuid::new_v4()));
+            let drive_c = root.join("drive_c");
+            std::fs::create_dir_all(drive_c.join("users")).unwrap();
+            std::os::unix::fs::symlink(&root, drive_c.join("users/linked")).unwrap();
+
+            let plain = CDrivePath::parse_entry(r"C:\users\missing\file.txt").unwrap();
+            assert!(plain.ensure_unlinked(&drive_c).await.is_ok());
+            let linked = CDrivePath::parse_entry(r"C:\users\linked\file.txt").unwrap();
+            assert!(matches!(
+                linked.ensure_unlinked(&drive_c).await,
+                Err(crate::error::Error::Bottle(BottleError::LinkedPath(_)))
+            ));
+            std::fs::remove_dir_all(root).unwrap();
+        });
+    }
+
+    #[test]
+    fn imports_follow_only_shell_folder_links() {
+        futures_lite::future::block_on(async {
+            let root = std::env::temp_dir().join(format!("bottles-next-{}", uuid::Uuid::new_v4()));
+            let drive_c = root.join("drive_c");
+            let home = root.join("home");
+            std::fs::create_dir_all(drive_c.join("users/steamuser")).unwrap();
+            std::fs::create_dir_all(home.join("Documents/saves")).unwrap();
+            std::os::unix::fs::symlink(
+                home.join("Documents"),
+                drive_c.join("users/steamuser/Documents"),
+            )
+            .unwrap();
+            std::os::unix::fs::symlink(&home, drive_c.join("users/steamuser/linked")).unwrap();
+
+            let documents = CDrivePath::parse_entry(r"C:\users\steamuser\Documents\game").unwrap();
+            assert!(documents.ensure_importable(&drive_c).await.is_ok());
+            let linked = CDrivePath::parse_entry(r"C:\users\steamuser\linked").unwrap();
+            assert!(matches!(
+                linked.ensure_importable(&drive_c).await,
+                Err(crate::error::Error::Bottle(BottleError::LinkedPath(_)))
+            ));
+
+            let source = root.join("source");
+            std::fs::create_dir_all(source.join("saves")).unwrap();
+            std::fs::write(source.join("saves/slot1.sav"), "save").unwrap();
+            std::os::unix::fs::symlink(&home, home.join("Documents/saves/slot1.sav")).unwrap();
+            let documents = CDrivePath::parse_entry(r"C:\users\steamuser\Documents").unwrap();
+            assert!(matches!(
+                copy_tree(&source, &drive_c, &documents).await,
+                Err(crate::error::Error::Bottle(BottleError::LinkedPath(_)))
+            ));
+            std::fs::remove_dir_all(root).unwrap();
+        });
+    }
+}
@@ -0,0 +1,301 @@
+//! Completed launches recorded in `<bottle>/history.jsonl`.
+//!
+//! Each launch appends one JSON line when it exits, so a crash can at worst
+//! truncate the final record. Only the newest launches are kept; older ones
+//! are folded into per-program totals in `history-totals.toml`, so playtime
+//! still covers every launch.
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use futures_lite::AsyncWriteExt;
+use next_config::Config;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    addons::{Addon, Component, Slot},
+    error::Result,
+};
+
+use super::state::Bottle;
+
+/// How many launches the history keeps; older ones are folded into totals.
+const RETENTION: usize = 1000;
+
+/// One completed launch in a bottle's history.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct LaunchRecord {
+    /// The registered program, or `None` for a [`Bottle::run`] launch.
+    pub program: Option<Uuid>,
+    /// The executable passed to the runner, as Windows text or a host path.
+    pub executable: String,
+    /// When the host process was spawned.
+    pub started_at: SystemTime,
+    /// When the host process exited.
+    pub exited_at: SystemTime,
+    /// The exit code; see [`crate::ProgramExit::exit_code`].
+    pub exit_code: Option<i32>,
+    /// Exact component releases, including the runner, the launch ran with.
+    pub components: HashMap<Slot, Addon<Component>>,
+}
+
+impl LaunchRecord {
+    /// Returns how long the launch ran.
+    pub fn duration(&self) -> Duration {
+        self.exited_at
+            .duration_since(self.started_at)
+            .unwrap_or_default()
+    }
+}
+
+/// Cumulative playtime of one program, as returned by [`Bottle::playtimes`]
+/// and [`Program::playtime`](crate::Program::playtime).
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Playtime {
+    /// How long completed launches ran in total. Launches of the same program
+    /// that overlap are each counted in full.
+    pub total: Duration,
+    /// When the latest completed launch started.
+    pub last_played: Option<SystemTime>,
+}
+
+impl Playtime {
+    fn add(&mut self, record: &LaunchRecord) {
+        self.total = self.total.saturating_add(record.duration());
+        self.last_played = Some(
+            self.last_played
+                .map_or(record.started_at, |last| last.max(record.started_at)),
+        );
+    }
+}
+
+/// Playtime of launches rotated out of the history, as persisted.
+#[derive(Debug, Default, Deserialize, Serialize, Config)]
+#[config(version = 1)]
+#[serde(default, deny_unknown_fields)]
+struct HistoryTotals {
+    programs: HashMap<Uuid, Playtime>,
+}
+
+impl HistoryTotals {
+    /// Loads totals, treating a missing file as no rotated launches.
+    async fn load(path: &Path) -> Result<Self> {
+        match next_config::load::<Self>(path).await {
+            Ok(totals) => Ok(totals),
+            Err(next_config::error::Error::Io(error))
+                if error.kind() == io::ErrorKind::NotFound =>
+            {
+                Ok(Self::default())
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn add(&mut self, records: &[LaunchRecord]) {
+        for record in records {
+            if let Some(program) = record.program {
+                self.programs.entry(program).or_default().add(record);
+            }
+        }
+    }
+}
+
+impl Bottle {
+    /// Returns completed launches, most recent first.
+    ///
+    /// Launches that are still running are not listed, and only the newest
+    /// thousand launches are kept. Records that cannot be parsed, such as a
+    /// line truncated by a crash, are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bottle was deleted or its history cannot be
+    /// read.
+    pub async fn history(&self) -> Result<Vec<LaunchRecord>> {
+        self.state()?;
+        let mut records = parse(&read_history(&self.history_path()).await?);
+        records.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+        Ok(records)
+    }
+
+    /// Returns the playtime of every program with a completed launch, keyed
+    /// by program UUID.
+    ///
+    /// Playtime is aggregated from the history and includes launches that
+    /// were rotated out of it. Programs that were removed keep their entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bottle was deleted or its history cannot be
+    /// read.
+    pub async fn playtimes(&self) -> Result<HashMap<Uuid, Playtime>> {
+        self.state()?;
+        let _history = self.0.history.lock().await;
+        let mut totals = HistoryTotals::load(&self.history_totals_path()).await?;
+        totals.add(&parse(&read_history(&self.history_path()).await?));
+        Ok(totals.programs)
+    }
+
+    /// Appends `record` to the history and refreshes the published
+    /// [`Program::playtime`](crate::Program::playtime).
+    ///
+    /// Failures are logged rather than returned because nobody awaits them.
+    pub(super) async fn record_launch(&self, record: LaunchRecord) {
+        if let Err(error) = self.append_history(&record).await {
+            tracing::warn!("failed to record launch of {}: {error}", record.executable);
+        }
+        self.refresh_playtimes().await;
+    }
+
+    /// Fills the published [`Program::playtime`](crate::Program::playtime)
+    /// from the history.
+    ///
+    /// Failures are logged and leave the previous playtime published.
+    pub(crate) async fn refresh_playtimes(&self) {
+        match self.playtimes().await {
+            Ok(playtimes) => self.publish_playtimes(playtimes),
+            Err(error) => {
+                tracing::warn!("failed to read playtime of bottle {}: {error}", self.0.id)
+            }
+        }
+    }
+
+    /// Appends `record`, then folds the oldest launches into the totals once
+    /// the history holds more than it keeps.
+    async fn append_history(&self, record: &LaunchRecord) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let path = self.history_path();
+        let _history = self.0.history.lock().await;
+        let mut file = async_fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        file.write_all(&line).await?;
+        file.flush().await?;
+        drop(file);
+
+        let text = read_history(&path).await?;
+        let Some((rotated, kept)) = rotate(&text) else {
+            return Ok(());
+        };
+        let totals_path = self.history_totals_path();
+        let mut totals = HistoryTotals::load(&totals_path).await?;
+        totals.add(&rotated);
+        next_config::save(&totals_path, &totals).await?;
+        let staged = path.with_extension("jsonl.tmp");
+        async_fs::write(&staged, kept).await?;
+        async_fs::rename(&staged, &path).await?;
+        Ok(())
+    }
+
+    fn history_path(&self) -> PathBuf {
+        self.bottle_path().join("history.jsonl")
+    }
+
+    fn history_totals_path(&self) -> PathBuf {
+        self.bottle_path().join("history-totals.toml")
+    }
+}
+
+/// Reads the history, treating a missing file as empty.
+async fn read_history(path: &Path) -> Result<String> {
+    match async_fs::read_to_string(path).await {
+        Ok(text) => Ok(text),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Parses history lines, skipping unreadable ones.
+fn parse(text: &str) -> Vec<LaunchRecord> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str::<LaunchRecord>(line) {
+            Ok(record) => Some(record),
+            Err(error) => {
+                tracing::warn!("skipping unreadable launch record: {error}");
+                None
+            }
+        })
+        .collect()
+}
+
+/// Splits a history holding more than [`RETENTION`] launches into the oldest
+/// records and the text of the newest lines it keeps.
+fn rotate(text: &str) -> Option<(Vec<LaunchRecord>, String)> {
+    let lines = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>();
+    let excess = lines
+        .len()
+        .checked_sub(RETENTION)
+        .filter(|excess| *excess > 0)?;
+    let (rotated, kept) = lines.split_at(excess);
+    let kept = kept.iter().map(|line| format!("{line}\n")).collect();
+    Some((parse(&rotated.join("\n")), kept))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    fn record(program: Uuid, started: u64, seconds: u64) -> LaunchRecord {
+        let started_at = UNIX_EPOCH + Duration::from_secs(started);
+        LaunchRecord {
+            program: Some(program),
+            executable: r"C:\game.exe".into(),
+            started_at,
+            exited_at: started_at + Duration::from_secs(seconds),
+            exit_code: Some(0),
+            components: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn rotated_launches_still_count_towards_playtime() {
+        let program = Uuid::new_v4();
+        let text = (0..RETENTION as u64 + 2)
+            .map(|index| serde_json::to_string(&record(program, index * 100, 10)).unwrap() + "\n")
+            .collect::<String>();
+
+        let (rotated, kept) = rotate(&text).unwrap();
+        assert_eq!(rotated.len(), 2);
+        assert_eq!(parse(&kept).len(), RETENTION);
+        assert!(rotate(&kept).is_none());
+
+        let mut totals = HistoryTotals::default();
+        totals.add(&rotated);
+        totals.add(&parse(&kept));
+        let playtime = totals.programs[&program];
+        assert_eq!(
+            playtime.total,
+            Duration::from_secs(10 * (RETENTION as u64 + 2))
+        );
+        assert_eq!(
+            playtime.last_played,
+            Some(UNIX_EPOCH + Duration::from_secs(100 * (RETENTION as u64 + 1)))
+        );
+    }
+
+    #[test]
+    fn records_round_trip_through_json_lines() {
+        let record = record(Uuid::new_v4(), 1_000, 60);
+
+        let line = serde_json::to_string(&record).unwrap();
+
+        assert!(!line.contains('\n'));
+        assert_eq!(serde_json::from_str::<LaunchRecord>(&line).unwrap(), record);
+        assert_eq!(record.duration(), Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,198 @@
+//! Host commands run around launches of a [`Program`](super::Program).
+
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use async_io::Timer;
+use futures_lite::future;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Result, utils::environment::Environment};
+
+use super::error::{BottleError, HookFailure};
+
+/// How long a hook may run unless [`Hook::with_timeout`] says otherwise.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A host command run before a program launches or after it exits.
+///
+/// Hooks run directly on the host, not through the runner, with the host
+/// environment extended by the launch environment: the bottle's variables and
+/// the program's overrides. `WINEPREFIX` names the prefix,
+/// `BOTTLES_PROGRAM_ID` names the program, and post-exit hooks also receive
+/// `BOTTLES_EXIT_CODE` when the exit code is known. Their output is appended
+/// to the launch log.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hook {
+    /// Host executable, resolved through `PATH` when it has no separator.
+    command: PathBuf,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default = "default_timeout_secs")]
+    timeout_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    DEFAULT_TIMEOUT.as_secs()
+}
+
+impl Hook {
+    /// Creates a hook that runs `command` without arguments and is killed
+    /// after 30 seconds.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::InvalidProgram`] if `command` is empty.
+    pub fn new(command: impl Into<PathBuf>) -> Result<Self> {
+        let command = command.into();
+        if command.as_os_str().is_empty() {
+            return Err(
+                BottleError::InvalidProgram("hook command must not be empty".into()).into(),
+            );
+        }
+        Ok(Self {
+            command,
+            args: Vec::new(),
+            timeout_secs: default_timeout_secs(),
+        })
+    }
+
+    /// Replaces the arguments passed to the command verbatim.
+    pub fn with_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets how long the hook may run before it is killed, rounded up to
+    /// whole seconds and to at least one second.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+        self.timeout_secs = secs.max(1);
+        self
+    }
+
+    /// Returns the host executable.
+    pub fn command(&self) -> &Path {
+        &self.command
+    }
+
+    /// Returns the arguments passed to the command.
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Returns how long the hook may run before it is killed.
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+
+    /// Runs the hook to completion with `environment`, appending its output
+    /// to `log`.
+    ///
+    /// A hook that outlives its timeout is killed. Processes it started in
+    /// the background are not tracked and keep running.
+    pub(super) async fn run(&self, environment: &Environment, log: &File) -> Result<()> {
+        let failed = |failure| BottleError::HookFailed {
+            command: self.command.clone(),
+            failure,
+        };
+        let spawned = log.try_clone().and_then(|stdout| {
+            let stderr = log.try_clone()?;
+            async_process::Command::new(&self.command)
+                .args(&self.args)
+                .envs(environment.iter())
+                .stdin(Stdio::null())
+                .stdout(stdout)
+                .stderr(stderr)
+                .spawn()
+        });
+        let mut child = spawned.map_err(|error| failed(HookFailure::Spawn(error)))?;
+
+        let timeout = self.timeout();
+        let status = future::or(async { Some(child.status().await) }, async {
+            Timer::after(timeout).await;
+            None
+        })
+        .await;
+        let status = match status {
+            Some(status) => status.map_err(|error| failed(HookFailure::Spawn(error)))?,
+            None => {
+                if let Err(error) = child.kill()
+                    && error.kind() != io::ErrorKind::InvalidInput
+                {
+                    return Err(failed(HookFailure::Spawn(error)).into());
+                }
+                child
+                    .status()
+                    .await
+                    .map_err(|error| failed(HookFailure::Spawn(error)))?;
+                return Err(failed(HookFailure::TimedOut(timeout)).into());
+            }
+        };
+        if !status.success() {
+            return Err(failed(HookFailure::Exited(status)).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    fn run(hook: &Hook) -> Result<String> {
+        let path = std::env::temp_dir().join(format!("bottles-next-{}.log", uuid::Uuid::new_v4()));
+        let log = File::create(&path).unwrap();
+        let mut environment = Environment::default();
+        environment.insert("BOTTLES_HOOK_TEST".to_owned(), "visible".to_owned());
+
+        let result = future::block_on(hook.run(&environment, &log));
+        let output = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        result.map(|()| output)
+    }
+
+    #[test]
+    fn hooks_see_the_launch_environment() {
+        let hook = Hook::new("sh")
+            .unwrap()
+            .with_args(["-c", "echo $BOTTLES_HOOK_TEST"]);
+
+        assert_eq!(run(&hook).unwrap(), "visible\n");
+    }
+
+    #[test]
+    fn failing_and_slow_hooks_are_reported() {
+        let failing = Hook::new("sh").unwrap().with_args(["-c", "exit 3"]);
+        let slow = Hook::new("sh")
+            .unwrap()
+            .with_args(["-c", "sleep 30"])
+            .with_timeout(Duration::from_millis(10));
+
+        assert!(matches!(
+            run(&failing),
+            Err(Error::Bottle(BottleError::HookFailed {
+                failure: HookFailure::Exited(status),
+                ..
+            })) if status.code() == Some(3)
+        ));
+        assert!(matches!(
+            run(&slow),
+            Err(Error::Bottle(BottleError::HookFailed {
+                failure: HookFailure::TimedOut(timeout),
+                ..
+            })) if timeout == Duration::from_secs(1)
+        ));
+    }
+}
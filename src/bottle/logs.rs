@@ -0,0 +1,227 @@
+//! Per-launch output logs under `<bottle>/logs`.
+//!
+//! Each program writes into `logs/<program UUID>/`, and ad hoc launches share
+//! `logs/adhoc/`, and each WineBridge start writes into `logs/winebridge/`. A
+//! log is named after the millisecond its launch started, so
+//! names sort chronologically and carry the start time.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::PoisonError,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures_core::Stream;
+use futures_lite::{AsyncBufReadExt, StreamExt, io::BufReader};
+use uuid::Uuid;
+
+use crate::error::Result;
+
+use super::{
+    error::BottleError,
+    state::{Bottle, BottleInner},
+};
+
+/// How many logs each directory keeps; older logs are deleted as launches start.
+const RETENTION: usize = 10;
+
+/// Directory shared by launches that are not registered programs.
+pub(super) const ADHOC: &str = "adhoc";
+
+/// Directory of the logs written by WineBridge and the Wine processes it hosts.
+pub(super) const BRIDGE: &str = "winebridge";
+
+/// Captured standard output and standard error of one launch.
+///
+/// Wine writes its debug channels, including the `WINEDEBUG` output selected
+/// by [`crate::Program::with_wine_debug`], to the host standard error that
+/// every process launched through WineBridge shares. Lines written there are
+/// appended to the log of each launch running at the time, so launches that
+/// run side by side also see each other's Wine output. Only a WineBridge
+/// started by the same bottle handle is forwarded; otherwise the output stays
+/// in the WineBridge logs listed by
+/// [`Bottle::bridge_logs`](crate::Bottle::bridge_logs).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LaunchLog {
+    path: PathBuf,
+    started_at: SystemTime,
+}
+
+impl LaunchLog {
+    /// Returns the log file's host path, for attaching to bug reports.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns when the launch that wrote this log started.
+    pub fn started_at(&self) -> SystemTime {
+        self.started_at
+    }
+
+    /// Streams the lines written so far, without trailing newlines.
+    ///
+    /// The stream ends at the current end of the file; use
+    /// [`ProgramRun::follow_log`](crate::ProgramRun::follow_log) to keep
+    /// reading while a launch runs. Invalid UTF-8 is replaced rather than
+    /// reported.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log cannot be opened, for example because it
+    /// was rotated away.
+    pub async fn lines(&self) -> Result<impl Stream<Item = Result<String>> + Send + 'static> {
+        let file = async_fs::File::open(&self.path).await?;
+        Ok(BufReader::new(file)
+            .split(b'\n')
+            .map(|line| Ok(decode(&line?))))
+    }
+
+    fn from_path(path: PathBuf) -> Option<Self> {
+        let millis = path
+            .file_name()?
+            .to_str()?
+            .strip_suffix(".log")?
+            .parse::<u64>()
+            .ok()?;
+        Some(Self {
+            path,
+            started_at: UNIX_EPOCH + Duration::from_millis(millis),
+        })
+    }
+}
+
+impl Bottle {
+    /// Lists the retained launch logs of a registered program, newest first.
+    ///
+    /// Only the ten most recent launches keep their logs.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::ProgramNotFound`] if `program_id` is not
+    /// registered, or an error if the log directory cannot be read.
+    pub async fn logs(&self, program_id: Uuid) -> Result<Vec<LaunchLog>> {
+        if self.state()?.program(program_id).is_none() {
+            return Err(BottleError::ProgramNotFound(program_id).into());
+        }
+        let mut logs = list(&self.log_dir(&program_id.to_string())).await?;
+        logs.reverse();
+        Ok(logs)
+    }
+
+    /// Lists the retained logs of WineBridge starts, newest first.
+    ///
+    /// Each log covers one WineBridge run, including the Wine debug output of
+    /// every program launched through it. Only the ten most recent starts keep
+    /// their logs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bottle was deleted or the log directory cannot
+    /// be read.
+    pub async fn bridge_logs(&self) -> Result<Vec<LaunchLog>> {
+        self.ensure_exists()?;
+        let mut logs = list(&self.log_dir(BRIDGE)).await?;
+        logs.reverse();
+        Ok(logs)
+    }
+
+    pub(super) fn log_dir(&self, name: &str) -> PathBuf {
+        self.bottle_path().join("logs").join(name)
+    }
+}
+
+/// Rotates `dir` and creates the log for a launch starting now.
+pub(super) async fn create(dir: &Path) -> Result<(File, LaunchLog)> {
+    async_fs::create_dir_all(dir).await?;
+    let existing = list(dir).await?;
+    let excess = (existing.len() + 1).saturating_sub(RETENTION);
+    for log in &existing[..excess] {
+        match async_fs::remove_file(&log.path).await {
+            Ok(()) => {}
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    let mut millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    loop {
+        let path = dir.join(format!("{millis}.log"));
+        match OpenOptions::new().append(true).create_new(true).open(&path) {
+            Ok(file) => {
+                let log = LaunchLog {
+                    path,
+                    started_at: UNIX_EPOCH + Duration::from_millis(millis),
+                };
+                return Ok((file, log));
+            }
+            // Another launch started within the same millisecond.
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => millis += 1,
+            Err(error) => return Err(error.into()),
+        }
+    }
+}
+
+/// Appends a line of WineBridge's standard error to the logs of running
+/// launches.
+pub(super) fn forward_to_launches(bottle: &BottleInner, line: &[u8]) {
+    let outputs = bottle
+        .launch_output
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    for mut output in outputs.values() {
+        if let Err(error) = output.write_all(line) {
+            tracing::warn!("failed to copy Wine output into a launch log: {error}");
+        }
+    }
+}
+
+/// Lists logs in `dir` oldest first; a missing directory has none.
+async fn list(dir: &Path) -> Result<Vec<LaunchLog>> {
+    let mut entries = match async_fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error.into()),
+    };
+    let mut logs = Vec::new();
+    while let Some(entry) = entries.try_next().await? {
+        logs.extend(LaunchLog::from_path(entry.path()));
+    }
+    logs.sort_by_key(LaunchLog::started_at);
+    Ok(logs)
+}
+
+/// Decodes one captured line, dropping a trailing carriage return.
+pub(super) fn decode(line: &[u8]) -> String {
+    String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line)).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creating_logs_keeps_only_the_newest() {
+        futures_lite::future::block_on(async {
+            let dir = std::env::temp_dir().join(format!("bottles-next-{}", Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            for millis in 0..RETENTION as u64 + 2 {
+                std::fs::write(dir.join(format!("{millis}.log")), []).unwrap();
+            }
+            std::fs::write(dir.join("notes.txt"), []).unwrap();
+
+            let (_, log) = create(&dir).await.unwrap();
+            let logs = list(&dir).await.unwrap();
+
+            assert_eq!(logs.len(), RETENTION);
+            assert_eq!(logs[0].started_at(), UNIX_EPOCH + Duration::from_millis(3));
+            assert_eq!(logs.last(), Some(&log));
+            assert!(dir.join("notes.txt").exists());
+            std::fs::remove_dir_all(dir).unwrap();
+        });
+    }
+}
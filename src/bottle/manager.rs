@@ -3,13 +3,14 @@
 use std::{
     collections::{HashMap, HashSet},
     io,
+    path::{Path, PathBuf},
     pin::Pin,
     sync::Arc,
 };
 
 use async_fs as fs;
 use futures_core::Stream;
-use futures_lite::{StreamExt, stream};
+use futures_lite::{StreamExt, future, stream};
 use futures_util::stream::SelectAll;
 use tokio::sync::watch;
 use tokio_stream::wrappers::WatchStream;
@@ -22,9 +23,11 @@ use crate::{
     addons::{Addon, Addons, Requirement, Slot},
     error::{Error, Result},
     prefix::Prefix,
+    utils::archive,
 };
 
 use super::{
+    desktop,
     error::BottleError,
     state::{Bottle, BottleState, Storage},
 };
@@ -260,6 +263,8 @@ impl BottleManager {
     /// and their state streams end. Previously obtained [`BottleState`]
     /// snapshots remain usable. The registry is changed only after recursive
     /// removal succeeds; partial filesystem removal is not rolled back.
+    /// Desktop entries of its programs are then removed on a best-effort
+    /// basis.
     ///
     /// # Errors
     ///
@@ -279,12 +284,291 @@ impl BottleManager {
             progress.send_replace(Some(Progress::new(Stage::Removing)));
             let path = manager.context.directories().bottle(id);
             fs::remove_dir_all(path).await?;
+            #[cfg(feature = "fvs")]
+            let _ = fs::remove_file(bottle.snapshot_tags_path()).await;
             manager.registry.remove(id);
             bottle.mark_deleted();
+            for program in state.programs() {
+                if let Err(error) =
+                    desktop::remove(manager.context.directories(), id, program.id()).await
+                {
+                    tracing::warn!(
+                        "failed to remove desktop entry of program {}: {error}",
+                        program.id()
+                    );
+                }
+            }
             Ok(())
         })
     }
 
+    /// Writes the bottle identified by `id` to a portable archive.
+    ///
+    /// The archive contains `bottle.toml` and the complete prefix. Virgo
+    /// layers are machine-local, so a Virgo prefix is exported as its mounted
+    /// contents and recorded as [`Storage::Standard`]. Drive mappings point at
+    /// host directories, so they are dropped along with their links. Snapshot
+    /// history is not exported. `destination` is either the archive path, ending in
+    /// `.tar.zst` or `.tar.xz`, or an existing directory that receives
+    /// `<id>.tar.zst`. The bottle is stopped first and stays locked until the
+    /// archive is written. Failures and cancellation remove the partial
+    /// archive on a best-effort basis.
+    ///
+    /// # Errors
+    ///
+    /// The operation fails if the bottle does not exist, cannot be stopped or
+    /// prepared, the destination suffix is unsupported, or archiving fails.
+    pub fn export(&self, id: Uuid, destination: impl Into<PathBuf>) -> Operation<PathBuf> {
+        let destination = destination.into();
+        let manager = self.clone();
+        Operation::new(move |progress, cancellation| async move {
+            let bottle = manager.open(id).await?;
+            let _write = bottle.0.write_lock.write().await;
+            let state = bottle.state()?;
+            let cx = &manager.context;
+            let archive_path = if fs::metadata(&destination)
+                .await
+                .is_ok_and(|entry| entry.is_dir())
+            {
+                destination.join(format!("{id}.tar.zst"))
+            } else {
+                destination
+            };
+            progress.send_replace(Some(Progress::new(Stage::Stopping)));
+            Bottle::stop_state(&state, cx).await?;
+            if cancellation.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+
+            progress.send_replace(Some(Progress::new(Stage::Preparing)));
+            let bottle_path = cx.directories().bottle(id);
+            let staging = cx
+                .directories()
+                .data_dir()
+                .join(".staging")
+                .join(format!("export-{}", Uuid::new_v4()));
+            let result = async {
+                state
+                    .storage
+                    .prepare(&bottle_path, &state.drives, cx)
+                    .await?;
+                fs::create_dir_all(&staging).await?;
+                let config = staging.join("bottle.toml");
+                let mut exported = state.as_ref().clone();
+                exported.storage = Prefix::Standard;
+                exported.drives.clear();
+                next_config::save(&config, &exported).await?;
+
+                progress.send_replace(Some(Progress::new(Stage::Archiving)));
+                let prefix = bottle_path.join("prefix");
+                let members: [(&str, &Path); 2] = [("bottle.toml", &config), ("prefix", &prefix)];
+                future::or(
+                    async { Ok(archive::pack(&archive_path, &members).await?) },
+                    async {
+                        cancellation.cancelled().await;
+                        Err(Error::Cancelled)
+                    },
+                )
+                .await
+            }
+            .await;
+
+            let _ = fs::remove_dir_all(&staging).await;
+            let stopped = state.storage.stop(&bottle_path, cx).await;
+            if result.is_err() {
+                let _ = fs::remove_file(&archive_path).await;
+            }
+            result?;
+            stopped?;
+            Ok(archive_path)
+        })
+    }
+
+    /// Imports a bottle archive written by [`export`](Self::export).
+    ///
+    /// The imported bottle receives a new UUID and uses [`Storage::Standard`].
+    /// Its pinned components and dependencies are re-resolved against the
+    /// addons downloaded on this machine before any bottle files are created.
+    /// With the default `fvs` feature, a new snapshot repository is created as
+    /// for [`create`](Self::create). Failures and cancellation remove the
+    /// staged and partially-imported files on a best-effort basis.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::RequiresAddon`] listing the identifier of every
+    /// pinned addon that is not downloaded. Returns
+    /// [`BottleError::InvalidArchive`] when the archive lacks `bottle.toml` or
+    /// the prefix. Archive, configuration, service, and I/O failures are also
+    /// returned.
+    pub fn import(&self, archive_path: impl Into<PathBuf>) -> Operation<Bottle> {
+        let archive_path = archive_path.into();
+        let cx = self.context.clone();
+        let addons = self.addons.clone();
+        let registry = self.registry.clone();
+        Operation::new(move |progress, cancellation| async move {
+            progress.send_replace(Some(Progress::new(Stage::Extracting)));
+            let staging = cx
+                .directories()
+                .data_dir()
+                .join(".staging")
+                .join(format!("import-{}", Uuid::new_v4()));
+            let id = Uuid::new_v4();
+            let bottle_path = cx.directories().bottle(id);
+            let result = async {
+                fs::create_dir_all(&staging).await?;
+                future::or(
+                    async { Ok(archive::extract(&archive_path, &staging).await?) },
+                    async {
+                        cancellation.cancelled().await;
+                        Err(Error::Cancelled)
+                    },
+                )
+                .await?;
+                let config = staging.join("bottle.toml");
+                let prefix = staging.join("prefix");
+                if !fs::metadata(&config)
+                    .await
+                    .is_ok_and(|entry| entry.is_file())
+                    || !fs::metadata(&prefix)
+                        .await
+                        .is_ok_and(|entry| entry.is_dir())
+                {
+                    return Err(BottleError::InvalidArchive(archive_path.clone()).into());
+                }
+
+                progress.send_replace(Some(Progress::new(Stage::Configuring)));
+                let mut state: BottleState = next_config::load(&config).await?;
+                let mut missing = Vec::new();
+                for component in state.components.values_mut() {
+                    match addons.component(component.id()) {
+                        Some(entry) => *component = Addon::from(entry.as_ref()),
+                        None => missing.push(Requirement::Id(component.id())),
+                    }
+                }
+                for dependency in &mut state.dependencies {
+                    match addons.dependency(dependency.id()) {
+                        Some(entry) => *dependency = Addon::from(entry.as_ref()),
+                        None => missing.push(Requirement::Id(dependency.id())),
+                    }
+                }
+                if !missing.is_empty() {
+                    return Err(BottleError::RequiresAddon {
+                        required_by: None,
+                        requirements: missing,
+                    }
+                    .into());
+                }
+                state.id = id;
+                state.storage = Prefix::Standard;
+                let bottle = Bottle::from_state(state, cx.clone(), addons.clone())?;
+                if cancellation.is_cancelled() {
+                    return Err(Error::Cancelled);
+                }
+
+                fs::create_dir_all(&bottle_path).await?;
+                fs::rename(&prefix, bottle_path.join("prefix")).await?;
+                Bottle::save_state(&bottle.state()?, &cx).await?;
+                #[cfg(feature = "fvs")]
+                cx.fvs()
+                    .await?
+                    .new_repository(&bottle_path, FVS_BLOCK_SIZE)
+                    .await?;
+                Ok(registry.intern(bottle))
+            }
+            .await;
+
+            let _ = fs::remove_dir_all(&staging).await;
+            if result.is_err() {
+                let _ = fs::remove_dir_all(&bottle_path).await;
+            }
+            result
+        })
+    }
+
+    /// Creates an independent copy of the bottle identified by `id`.
+    ///
+    /// The copy receives a new UUID, the display name `name`, and new UUIDs
+    /// for every registered program; all other configuration is copied as is.
+    /// A Standard prefix is copied with reflinks where the filesystem supports
+    /// them and falls back to a regular copy otherwise. A Virgo copy shares the
+    /// source's cached layer stack and copies only its writable upper
+    /// directory. Snapshot history is not copied; with the default `fvs`
+    /// feature the copy starts with an empty repository. The source is stopped
+    /// first and stays locked until copying finishes. Failures and cancellation
+    /// remove the partial copy on a best-effort basis.
+    ///
+    /// # Errors
+    ///
+    /// The operation fails if the source does not exist or cannot be stopped,
+    /// cancellation is requested, or copying fails; a failed copy command is
+    /// reported as [`BottleError::CopyFailed`].
+    pub fn duplicate(&self, id: Uuid, name: impl Into<String>) -> Operation<Bottle> {
+        let name = name.into();
+        let manager = self.clone();
+        Operation::new(move |progress, cancellation| async move {
+            let source = manager.open(id).await?;
+            let _write = source.0.write_lock.write().await;
+            let state = source.state()?;
+            let cx = manager.context.clone();
+            progress.send_replace(Some(Progress::new(Stage::Stopping)));
+            Bottle::stop_state(&state, &cx).await?;
+            if cancellation.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+
+            let copy_id = Uuid::new_v4();
+            let source_path = cx.directories().bottle(id);
+            let bottle_path = cx.directories().bottle(copy_id);
+            let result = async {
+                progress.send_replace(Some(Progress::new(Stage::CreatingPrefix)));
+                fs::create_dir_all(&bottle_path).await?;
+                let directory = match state.storage {
+                    Prefix::Standard => "prefix",
+                    #[cfg(feature = "fvs")]
+                    Prefix::Virgo { .. } => "upper",
+                };
+                future::or(
+                    copy_with_reflinks(&source_path.join(directory), &bottle_path.join(directory)),
+                    async {
+                        cancellation.cancelled().await;
+                        Err(Error::Cancelled)
+                    },
+                )
+                .await?;
+
+                progress.send_replace(Some(Progress::new(Stage::Configuring)));
+                let mut copied = state.as_ref().clone();
+                copied.id = copy_id;
+                copied.name = name;
+                copied.programs = copied
+                    .programs
+                    .into_values()
+                    .map(|program| {
+                        let program = program.with_new_id();
+                        (program.id(), program)
+                    })
+                    .collect();
+                let bottle = Bottle::from_state(copied, cx.clone(), manager.addons.clone())?;
+                Bottle::save_state(&bottle.state()?, &cx).await?;
+                #[cfg(feature = "fvs")]
+                cx.fvs()
+                    .await?
+                    .new_repository(&bottle_path, FVS_BLOCK_SIZE)
+                    .await?;
+                if cancellation.is_cancelled() {
+                    return Err(Error::Cancelled);
+                }
+                Ok(manager.registry.intern(bottle))
+            }
+            .await;
+
+            if result.is_err() {
+                let _ = fs::remove_dir_all(&bottle_path).await;
+            }
+            result
+        })
+    }
+
     /// Opens the bottle identified by `id`.
     ///
     /// Repeated calls through this manager or its clones return handles to the
@@ -316,6 +600,7 @@ impl BottleManager {
             .into());
         }
         let bottle = Bottle::from_state(state, self.context.clone(), self.addons.clone())?;
+        bottle.refresh_playtimes().await;
         Ok(self.registry.intern(bottle))
     }
 
@@ -394,7 +679,10 @@ impl BottleManager {
             match next_config::load::<BottleState>(path).await {
                 Ok(state) => {
                     match Bottle::from_state(state, self.context.clone(), self.addons.clone()) {
-                        Ok(bottle) => bottles.push(bottle),
+                        Ok(bottle) => {
+                            bottle.refresh_playtimes().await;
+                            bottles.push(bottle);
+                        }
                         Err(error) => {
                             tracing::warn!("skipping bottle with invalid runtime: {error}")
                         }
@@ -406,3 +694,21 @@ impl BottleManager {
         Ok(bottles)
     }
 }
+
+/// Copies a directory tree, sharing extents through reflinks when possible.
+///
+/// `cp` falls back to a regular copy on filesystems without reflink support
+/// and preserves modes, timestamps, and symbolic links as they are.
+pub(super) async fn copy_with_reflinks(source: &Path, destination: &Path) -> Result<()> {
+    let status = async_process::Command::new("cp")
+        .args(["-a", "--reflink=auto", "--"])
+        .arg(source)
+        .arg(destination)
+        .kill_on_drop(true)
+        .status()
+        .await?;
+    if !status.success() {
+        return Err(BottleError::CopyFailed(status).into());
+    }
+    Ok(())
+}
@@ -20,29 +20,79 @@
 //! [`crate::Operation`] values and serialize with edits, stopping, snapshots,
 //! and deletion. WineBridge-backed requests may run concurrently.
 
+#[cfg(feature = "fvs")]
+mod backup;
+mod desktop;
+#[cfg(feature = "fvs")]
+mod diff;
+mod discover;
+mod doctor;
+mod drives;
 mod edit;
 pub(crate) mod error;
+mod executable;
+pub(crate) mod files;
+mod history;
+mod hooks;
+mod logs;
 mod manager;
+mod registry;
+mod repair;
+#[cfg(feature = "fvs")]
+mod restore;
+mod run;
+mod saves;
+mod services;
 #[cfg(feature = "fvs")]
 mod snapshot;
 mod software;
 mod state;
+#[cfg(feature = "fvs")]
+mod tags;
+mod template;
+mod usage;
 
 #[cfg(test)]
 mod tests;
 
 pub use crate::proto::DllOverride;
 pub use crate::proto::DllOverrideMode;
+pub use crate::proto::Drive;
+pub use crate::proto::PathInfo;
 pub use crate::proto::Process;
 pub use crate::proto::RegistryHive;
+pub use crate::proto::RegistryKey;
+pub use crate::proto::Service;
+pub use crate::proto::ServiceStartType;
 pub use crate::wrapper::{
     Wrappers,
     gamescope::{Filter as GamescopeFilter, GamescopeConfig, Scaler as GamescopeScaler},
     mangohud::MangoHudConfig,
 };
+#[cfg(feature = "fvs")]
+pub use backup::{BackupPolicy, BackupSchedule, Retention};
+#[cfg(feature = "fvs")]
+pub use diff::{ChangeKind, FileChange, RegistryChange, SnapshotDiff};
+pub use doctor::{Check, Finding, Repair, Report};
+pub use drives::DriveMapping;
 pub use edit::BottleEdit;
-pub use error::BottleError;
+pub use error::{BottleError, HookFailure};
+pub use executable::{ExecutableArchitecture, ExecutableInfo, ExecutableSubsystem, IconSize};
+pub use files::BottleFiles;
 #[cfg(feature = "fvs")]
 pub use fvs_rs::{Commit as Snapshot, CommitSummary as SnapshotSummary};
+pub use history::{LaunchRecord, Playtime};
+pub use hooks::Hook;
+pub use logs::LaunchLog;
 pub use manager::BottleManager;
+pub use registry::RegistryData;
+pub use repair::RepairMode;
+#[cfg(feature = "fvs")]
+pub use restore::RestorePath;
+pub use run::{ProcessEvent, ProgramExit, ProgramRun, RunArg, RunOptions};
+pub use saves::{SaveBackup, SaveSync, SyncTarget};
 pub use state::{Bottle, BottleState, Program, Storage};
+#[cfg(feature = "fvs")]
+pub use tags::{SnapshotQuery, SnapshotTag};
+pub use template::{BottleTemplate, TemplateRegistryValue};
+pub use usage::{DiskUsage, DiskUsageSummary};
@@ -0,0 +1,276 @@
+//! Registry editing on [`Bottle`] through WineBridge.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    proto::{self, RegistryHive, RegistryKey, registry_value::Value as RegistryValue},
+};
+
+use super::state::Bottle;
+
+/// A typed Windows registry value.
+///
+/// Conversions to and from WineBridge's wire representation are lossless for
+/// every supported registry type.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "kebab-case")]
+pub enum RegistryData {
+    /// `REG_SZ`: a string.
+    String(String),
+    /// `REG_EXPAND_SZ`: a string that may reference `%VARIABLES%`.
+    ExpandString(String),
+    /// `REG_DWORD`: a 32-bit little-endian integer.
+    Dword(u32),
+    /// `REG_QWORD`: a 64-bit little-endian integer.
+    Qword(u64),
+    /// `REG_MULTI_SZ`: an ordered list of strings.
+    MultiString(Vec<String>),
+    /// `REG_BINARY`: raw bytes.
+    Binary(Vec<u8>),
+}
+
+impl From<RegistryValue> for RegistryData {
+    fn from(value: RegistryValue) -> Self {
+        match value {
+            RegistryValue::StringValue(value) => Self::String(value),
+            RegistryValue::ExpandStringValue(value) => Self::ExpandString(value),
+            RegistryValue::DwordValue(value) => Self::Dword(value),
+            RegistryValue::QwordValue(value) => Self::Qword(value),
+            RegistryValue::MultiStringValue(value) => Self::MultiString(value.values),
+            RegistryValue::BinaryValue(value) => Self::Binary(value),
+        }
+    }
+}
+
+impl From<RegistryData> for RegistryValue {
+    fn from(data: RegistryData) -> Self {
+        match data {
+            RegistryData::String(value) => Self::StringValue(value),
+            RegistryData::ExpandString(value) => Self::ExpandStringValue(value),
+            RegistryData::Dword(value) => Self::DwordValue(value),
+            RegistryData::Qword(value) => Self::QwordValue(value),
+            RegistryData::MultiString(values) => {
+                Self::MultiStringValue(proto::MultiString { values })
+            }
+            RegistryData::Binary(value) => Self::BinaryValue(value),
+        }
+    }
+}
+
+impl From<String> for RegistryData {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<&str> for RegistryData {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_owned())
+    }
+}
+
+impl From<u32> for RegistryData {
+    fn from(value: u32) -> Self {
+        Self::Dword(value)
+    }
+}
+
+impl From<u64> for RegistryData {
+    fn from(value: u64) -> Self {
+        Self::Qword(value)
+    }
+}
+
+impl From<Vec<String>> for RegistryData {
+    fn from(values: Vec<String>) -> Self {
+        Self::MultiString(values)
+    }
+}
+
+impl From<Vec<u8>> for RegistryData {
+    fn from(value: Vec<u8>) -> Self {
+        Self::Binary(value)
+    }
+}
+
+impl Bottle {
+    /// Returns a registry key and its values, or `None` when it does not exist.
+    ///
+    /// This starts WineBridge if necessary. Reading registry state does not
+    /// publish a new [`crate::BottleState`] or notify
+    /// [`Bottle::watch`](Self::watch).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bottle was deleted, its prefix cannot be
+    /// prepared, WineBridge cannot start, or the request fails for a reason
+    /// other than a missing key.
+    pub async fn registry_key(
+        &self,
+        hive: RegistryHive,
+        subkey: impl Into<String>,
+    ) -> Result<Option<RegistryKey>> {
+        let subkey = subkey.into();
+        self.with_bridge(move |bridge| async move {
+            not_found_as_none(bridge.get_registry_key(hive, subkey).await)
+        })
+        .await
+    }
+
+    /// Returns one typed registry value, or `None` when it does not exist.
+    ///
+    /// A missing key and a missing value under an existing key are both
+    /// reported as `None`. An empty `name` addresses the key's default value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bottle was deleted, its prefix cannot be
+    /// prepared, WineBridge cannot start, or the request fails for a reason
+    /// other than a missing key or value.
+    pub async fn registry_value(
+        &self,
+        hive: RegistryHive,
+        subkey: impl Into<String>,
+        name: impl Into<String>,
+    ) -> Result<Option<RegistryData>> {
+        let subkey = subkey.into();
+        let name = name.into();
+        self.with_bridge(move |bridge| async move {
+            not_found_as_none(bridge.get_registry_value(hive, subkey, name).await)
+                .map(|value| value.map(RegistryData::from))
+        })
+        .await
+    }
+
+    /// Creates a registry key, including any missing parent keys.
+    ///
+    /// Creating an existing key succeeds. The registry change does not publish
+    /// a new [`crate::BottleState`] or notify [`Bottle::watch`](Self::watch).
+    ///
+    /// # Errors
+    ///
+    /// Invalid key paths are rejected by WineBridge as [`Error::Status`].
+    /// Prefix and bridge failures are also returned.
+    pub async fn create_registry_key(
+        &self,
+        hive: RegistryHive,
+        subkey: impl Into<String>,
+    ) -> Result<()> {
+        let subkey = subkey.into();
+        self.with_bridge(
+            move |bridge| async move { bridge.create_registry_key(hive, subkey).await },
+        )
+        .await
+    }
+
+    /// Creates or replaces a registry value.
+    ///
+    /// The key is created when it does not exist. An empty `name` addresses
+    /// the key's default value. The registry change does not publish a new
+    /// [`crate::BottleState`] or notify [`Bottle::watch`](Self::watch).
+    ///
+    /// # Errors
+    ///
+    /// Invalid key paths are rejected by WineBridge as [`Error::Status`].
+    /// Prefix and bridge failures are also returned.
+    pub async fn set_registry_value(
+        &self,
+        hive: RegistryHive,
+        subkey: impl Into<String>,
+        name: impl Into<String>,
+        data: impl Into<RegistryData>,
+    ) -> Result<()> {
+        let subkey = subkey.into();
+        let name = name.into();
+        let value = RegistryValue::from(data.into());
+        self.with_bridge(move |bridge| async move {
+            bridge.set_registry_value(hive, subkey, name, value).await
+        })
+        .await
+    }
+
+    /// Deletes a registry value.
+    ///
+    /// Deleting a value that is not present, or whose key is missing, succeeds.
+    /// The registry change does not publish a new [`crate::BottleState`] or
+    /// notify [`Bottle::watch`](Self::watch).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the prefix or bridge operation fails.
+    pub async fn delete_registry_value(
+        &self,
+        hive: RegistryHive,
+        subkey: impl Into<String>,
+        name: impl Into<String>,
+    ) -> Result<()> {
+        let subkey = subkey.into();
+        let name = name.into();
+        self.with_bridge(move |bridge| async move {
+            not_found_as_none(bridge.delete_registry_value(hive, subkey, name).await).map(drop)
+        })
+        .await
+    }
+
+    /// Recursively deletes a registry key and all of its descendants.
+    ///
+    /// Deleting a key that is not present succeeds. The registry change does
+    /// not publish a new [`crate::BottleState`] or notify
+    /// [`Bottle::watch`](Self::watch).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the prefix or bridge operation fails.
+    pub async fn delete_registry_tree(
+        &self,
+        hive: RegistryHive,
+        subkey: impl Into<String>,
+    ) -> Result<()> {
+        let subkey = subkey.into();
+        self.with_bridge(move |bridge| async move {
+            not_found_as_none(bridge.delete_registry_tree(hive, subkey).await).map(drop)
+        })
+        .await
+    }
+}
+
+/// Maps WineBridge's `NOT_FOUND` status to an absent result.
+pub(super) fn not_found_as_none<T>(result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::Status(status)) if status.code() == tonic::Code::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_data_round_trips_through_wire_values() {
+        for data in [
+            RegistryData::String("value".into()),
+            RegistryData::ExpandString("%SystemRoot%".into()),
+            RegistryData::Dword(u32::MAX),
+            RegistryData::Qword(u64::MAX),
+            RegistryData::MultiString(vec!["first".into(), String::new(), "last".into()]),
+            RegistryData::Binary(vec![0, 1, 254, 255]),
+        ] {
+            assert_eq!(RegistryData::from(RegistryValue::from(data.clone())), data);
+        }
+    }
+
+    #[test]
+    fn only_not_found_statuses_become_absent() {
+        assert!(matches!(
+            not_found_as_none::<()>(Err(tonic::Status::not_found("missing").into())),
+            Ok(None)
+        ));
+        assert!(matches!(
+            not_found_as_none::<()>(Err(tonic::Status::internal("failed").into())),
+            Err(Error::Status(_))
+        ));
+    }
+}
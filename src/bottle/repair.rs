@@ -0,0 +1,169 @@
+//! Recreating a bottle's prefix from its recorded state.
+
+use strum::IntoEnumIterator;
+
+use crate::{
+    AddonError, Operation, Progress, Stage,
+    addons::{Artifact, InstallInputs, Slot, execute, replay_environment},
+    error::{Error, Result},
+};
+
+use super::state::{Bottle, BottleState};
+
+/// `drive_c` directories kept by [`RepairMode::KeepUserData`].
+const USER_DATA: [&str; 3] = ["users", "Program Files", "Program Files (x86)"];
+
+/// What [`Bottle::repair`] keeps from the damaged prefix.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum RepairMode {
+    /// Replace the whole prefix. Programs installed into it and files users
+    /// saved in it are lost.
+    Reset,
+    /// Keep `drive_c/users`, `drive_c/Program Files`, and
+    /// `drive_c/Program Files (x86)`, so user profiles and installed
+    /// programs survive.
+    #[default]
+    KeepUserData,
+}
+
+impl RepairMode {
+    fn preserved(self) -> &'static [&'static str] {
+        match self {
+            Self::Reset => &[],
+            Self::KeepUserData => &USER_DATA,
+        }
+    }
+}
+
+impl Bottle {
+    /// Recreates the prefix from the bottle's recorded configuration.
+    ///
+    /// The bottle is stopped, its prefix is deleted and initialized again
+    /// with the current runner, and the recipes of every non-runtime
+    /// component and then every dependency are re-run in recorded order.
+    /// Programs, environment, hooks, and drive mappings live in
+    /// `bottle.toml` and are kept as they are; drive links are applied again
+    /// when the prefix is next prepared. Use `mode` to keep user data from
+    /// the old prefix; kept directories replace the freshly created ones.
+    ///
+    /// With the default `fvs` feature, a failed or cancelled repair restores
+    /// the rollback checkpoint taken before the prefix was deleted. For
+    /// Virgo storage, recipes whose cached layers are still available are
+    /// not run again.
+    ///
+    /// # Errors
+    ///
+    /// The operation returns [`AddonError::NotFound`] if a recorded
+    /// dependency is no longer downloaded, and
+    /// [`BottleError::RepairInterrupted`](crate::BottleError::RepairInterrupted)
+    /// if an earlier repair could not move kept directories back. Stop,
+    /// runner, prefix, and recipe failures are also returned.
+    pub fn repair(&self, mode: RepairMode) -> Operation<()> {
+        let bottle = self.clone();
+        let addons = self.0.addons.clone();
+        Operation::new(move |progress, cancellation| async move {
+            bottle
+                .update(async |state, cx| {
+                    let mut recipes = Slot::iter()
+                        .filter(|slot| !slot.is_runtime())
+                        .filter_map(|slot| state.component(slot))
+                        .map(|component| {
+                            (component.id(), vec![component.artifact(cx.directories())])
+                        })
+                        .collect::<Vec<_>>();
+                    for installed in &state.dependencies {
+                        let dependency = addons
+                            .dependency(installed.id())
+                            .ok_or(AddonError::NotFound(installed.id()))?;
+                        let resources = dependency
+                            .artifacts()
+                            .iter()
+                            .map(|artifact| {
+                                Artifact::new(
+                                    dependency.path(cx.directories()).join(&artifact.path),
+                                    artifact.steps.clone(),
+                                )
+                            })
+                            .collect();
+                        recipes.push((dependency.id(), resources));
+                    }
+
+                    progress.send_replace(Some(Progress::new(Stage::Stopping)));
+                    Self::stop_state(state, &cx).await?;
+                    if cancellation.is_cancelled() {
+                        return Err(Error::Cancelled);
+                    }
+
+                    progress.send_replace(Some(Progress::new(Stage::CreatingPrefix)));
+                    let runner = state
+                        .runner()
+                        .load_runner(cx.directories(), state.umu())
+                        .await?;
+                    let runner_key = state.runner().id().to_string();
+                    let winebridge = state.winebridge().path(cx.directories());
+                    let bottle_path = cx.directories().bottle(state.id);
+                    let installed = recipes.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+                    let BottleState {
+                        storage,
+                        environment,
+                        ..
+                    } = state;
+                    storage
+                        .reset(
+                            &bottle_path,
+                            runner.as_ref(),
+                            &runner_key,
+                            mode.preserved(),
+                            &installed,
+                            async |prefix, id| {
+                                let resources = recipes
+                                    .iter()
+                                    .find(|(recipe, _)| *recipe == id)
+                                    .map(|(_, resources)| resources.as_slice())
+                                    .unwrap_or_default();
+                                execute(
+                                    InstallInputs {
+                                        prefix,
+                                        runner: runner.as_ref(),
+                                        winebridge: &winebridge,
+                                        environment: &mut *environment,
+                                    },
+                                    resources,
+                                    &cancellation,
+                                    |_| {
+                                        progress
+                                            .send_replace(Some(Progress::new(Stage::Configuring)));
+                                    },
+                                )
+                                .await
+                            },
+                            &cx,
+                            &cancellation,
+                            |event| {
+                                progress.send_replace(Some(event));
+                            },
+                        )
+                        .await?;
+                    for (_, resources) in &recipes {
+                        replay_environment(environment, resources);
+                    }
+                    Ok(())
+                })
+                .await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_keeping_user_data_preserves_directories() {
+        assert!(RepairMode::Reset.preserved().is_empty());
+        assert_eq!(
+            RepairMode::KeepUserData.preserved(),
+            ["users", "Program Files", "Program Files (x86)"]
+        );
+    }
+}
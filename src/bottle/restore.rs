@@ -0,0 +1,388 @@
+//! Restoring chosen files and registry keys from a snapshot.
+
+use std::{io, ops::AsyncFnOnce, path::Path};
+
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    Context, Operation, Progress, Stage, Transfer,
+    error::{Error, Result},
+    prefix::{Prefix, resolve_snapshot, transact, with_snapshot},
+};
+
+use super::{error::BottleError, files::CDrivePath, manager::copy_with_reflinks, state::Bottle};
+
+/// First line of every registry file Wine writes.
+const HIVE_HEADER: &str = "WINE REGISTRY Version 2";
+
+/// A location restored by [`Bottle::restore_paths`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum RestorePath {
+    /// A file or directory on `C:`, such as
+    /// `C:\users\steamuser\Saved Games\Game`, with everything below it.
+    File(String),
+    /// A registry key with its values and subkeys, such as
+    /// `HKEY_CURRENT_USER\Software\Game`. Keys must be below
+    /// `HKEY_CURRENT_USER` or `HKEY_LOCAL_MACHINE`, which may be spelled
+    /// `HKCU` and `HKLM`.
+    RegistryKey(String),
+}
+
+/// A validated [`RestorePath`].
+enum Target {
+    File(CDrivePath),
+    Key {
+        /// Registry file holding the key, relative to the prefix.
+        hive: &'static str,
+        /// Key path as Wine escapes it in section headers, in lowercase.
+        /// Empty for the whole hive.
+        key: String,
+    },
+}
+
+impl Target {
+    fn parse(path: &RestorePath) -> Result<Self> {
+        match path {
+            RestorePath::File(path) => Ok(Self::File(CDrivePath::parse_entry(path)?)),
+            RestorePath::RegistryKey(path) => {
+                let (root, subkey) = path.split_once('\\').unwrap_or((path, ""));
+                let hive = match root.to_ascii_uppercase().as_str() {
+                    "HKEY_CURRENT_USER" | "HKCU" => "user.reg",
+                    "HKEY_LOCAL_MACHINE" | "HKLM" => "system.reg",
+                    _ => return Err(BottleError::InvalidRegistryKey(path.clone()).into()),
+                };
+                let key = subkey
+                    .split('\\')
+                    .filter(|part| !part.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\\\\")
+                    .to_ascii_lowercase();
+                Ok(Self::Key { hive, key })
+            }
+        }
+    }
+}
+
+impl Bottle {
+    /// Restores chosen files and registry keys from a snapshot selected by
+    /// full state ID or prefix, leaving everything else as it is.
+    ///
+    /// Each file or directory is replaced by its copy in the snapshot, or
+    /// removed if the snapshot does not have it. Each registry key is
+    /// replaced together with its values and subkeys. Pair this with
+    /// [`diff_snapshots`](Self::diff_snapshots) to find what a snapshot
+    /// would bring back. For Virgo storage, snapshots hold only what the
+    /// bottle changed on top of its layers, so paths the snapshot does not
+    /// change are restored to what the layers provide, and a registry file
+    /// the bottle never changed is read from the layers.
+    ///
+    /// The operation takes exclusive bottle access, stops the bottle, and
+    /// takes a rollback checkpoint that is restored if the operation fails
+    /// or is cancelled. Progress is reported as [`Stage::Restoring`] with
+    /// the number of paths restored so far.
+    ///
+    /// # Errors
+    ///
+    /// The operation returns [`BottleError::InvalidWindowsPath`] or
+    /// [`BottleError::InvalidRegistryKey`] for a malformed path,
+    /// [`BottleError::LinkedPath`] for a file reached through a symbolic
+    /// link, such as a profile folder linked to the host, and
+    /// [`BottleError::InvalidRegistry`] if a registry file is missing or
+    /// malformed. Stop, snapshot, copy, and cancellation failures are also
+    /// returned.
+    pub fn restore_paths(
+        &self,
+        snapshot: &str,
+        paths: impl IntoIterator<Item = RestorePath>,
+    ) -> Operation<()> {
+        let bottle = self.clone();
+        let snapshot = snapshot.to_owned();
+        let paths = paths.into_iter().collect::<Vec<_>>();
+        Operation::new(move |progress, cancellation| async move {
+            let targets = paths
+                .iter()
+                .map(Target::parse)
+                .collect::<Result<Vec<_>>>()?;
+            let _write = bottle.0.write_lock.write().await;
+            let state = bottle.state()?;
+            let bottle_path = bottle.bottle_path();
+            let cx = &bottle.0.cx;
+            let commit = resolve_snapshot(&bottle_path, &snapshot, cx).await?;
+            progress.send_replace(Some(Progress::new(Stage::Stopping)));
+            Bottle::stop_state(&state, cx).await?;
+            if cancellation.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+
+            let prefix = state.storage.writable_dir();
+            let head = bottle_path.join(prefix);
+            transact(
+                &bottle_path,
+                cx,
+                with_snapshot(&bottle_path, &commit.state_id, cx, async |root| {
+                    restore(
+                        &root.join(prefix),
+                        &head,
+                        &state.storage,
+                        cx,
+                        &targets,
+                        &progress,
+                        &cancellation,
+                    )
+                    .await
+                }),
+                &cancellation,
+                |event| {
+                    progress.send_replace(Some(event));
+                },
+            )
+            .await
+        })
+    }
+}
+
+/// Restores `targets` from the snapshot prefix `saved` into the live prefix
+/// `head`, files first and then one registry file at a time.
+///
+/// A registry file missing from either prefix is read from the layers of
+/// `storage`, which hold it until the bottle first changes it.
+async fn restore(
+    saved: &Path,
+    head: &Path,
+    storage: &Prefix,
+    cx: &Context,
+    targets: &[Target],
+    progress: &watch::Sender<Option<Progress>>,
+    cancellation: &CancellationToken,
+) -> Result<()> {
+    let total = targets.len() as u64;
+    let mut restored = 0;
+    let report = |restored: u64| {
+        progress.send_replace(Some(Progress::transferring(
+            Stage::Restoring,
+            Transfer {
+                current: restored,
+                total: Some(total),
+            },
+        )));
+    };
+    report(restored);
+    for target in targets {
+        let Target::File(path) = target else {
+            continue;
+        };
+        if cancellation.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        restore_file(&saved.join("drive_c"), &head.join("drive_c"), path).await?;
+        restored += 1;
+        report(restored);
+    }
+    for hive in ["user.reg", "system.reg"] {
+        let keys = targets
+            .iter()
+            .filter_map(|target| match target {
+                Target::Key { hive: other, key } if *other == hive => Some(key.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if keys.is_empty() {
+            continue;
+        }
+        if cancellation.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        let lower = async || storage.read_lower(Path::new(hive), cx).await;
+        let current = read_hive(&head.join(hive), &lower).await?;
+        let snapshot = read_hive(&saved.join(hive), &lower).await?;
+        let staged = head.join(format!("{hive}.restore"));
+        async_fs::write(&staged, splice(&current, &snapshot, &keys)).await?;
+        async_fs::rename(&staged, head.join(hive)).await?;
+        restored += keys.len() as u64;
+        report(restored);
+    }
+    Ok(())
+}
+
+/// Replaces `path` below the live `drive_c` with its copy below the
+/// snapshot's, or removes it when the snapshot lacks it.
+async fn restore_file(saved: &Path, head: &Path, path: &CDrivePath) -> Result<()> {
+    path.ensure_unlinked(saved).await?;
+    path.ensure_unlinked(head).await?;
+    let target = head.join(&path.relative);
+    match async_fs::symlink_metadata(&target).await {
+        Ok(metadata) if metadata.is_dir() => async_fs::remove_dir_all(&target).await?,
+        Ok(_) => async_fs::remove_file(&target).await?,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => return Err(error.into()),
+    }
+    let source = saved.join(&path.relative);
+    match async_fs::symlink_metadata(&source).await {
+        Ok(metadata)
+            if metadata.is_dir() || metadata.is_file() || metadata.file_type().is_symlink() => {}
+        Ok(_) => return Ok(()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error.into()),
+    }
+    if let Some(parent) = target.parent() {
+        async_fs::create_dir_all(parent).await?;
+    }
+    copy_with_reflinks(&source, &target).await
+}
+
+/// Reads a Wine registry file, or the copy `lower` provides when it is
+/// missing.
+async fn read_hive(
+    path: &Path,
+    lower: impl AsyncFnOnce() -> Result<Option<Vec<u8>>>,
+) -> Result<String> {
+    let invalid = |reason: &str| BottleError::InvalidRegistry {
+        path: path.to_path_buf(),
+        reason: reason.to_owned(),
+    };
+    let contents = match async_fs::read(path).await {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => match lower().await? {
+            Some(contents) => contents,
+            None => return Err(invalid("the registry file is missing").into()),
+        },
+        Err(error) => return Err(error.into()),
+    };
+    let contents =
+        String::from_utf8(contents).map_err(|_| invalid("the registry file is not UTF-8"))?;
+    if !contents.starts_with(HIVE_HEADER) {
+        return Err(invalid("not a Wine registry file").into());
+    }
+    Ok(contents)
+}
+
+/// Replaces the sections of `current` that `keys` cover with those of
+/// `saved`.
+///
+/// Both inputs are Wine registry files. Sections of `saved` are appended
+/// after the kept sections of `current`; Wine sorts keys when it next saves
+/// the file.
+fn splice(current: &str, saved: &str, keys: &[&str]) -> String {
+    let covered = |section: &str| {
+        section_key(section).is_some_and(|key| keys.iter().any(|target| covers(target, &key)))
+    };
+    let mut output = sections(current)
+        .filter(|section| !covered(section))
+        .collect::<String>();
+    for section in sections(saved).filter(|section| covered(section)) {
+        if !output.is_empty() && !output.ends_with('\n') {
+            output.push('\n');
+        }
+        output.push_str(section);
+    }
+    output
+}
+
+/// Splits a registry file before every line that opens a key, so the first
+/// item is the file header and each other item is one key with its values.
+fn sections(text: &str) -> impl Iterator<Item = &str> {
+    let mut starts = vec![0];
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        if offset > 0 && line.starts_with('[') {
+            starts.push(offset);
+        }
+        offset += line.len();
+    }
+    starts.push(text.len());
+    (0..starts.len() - 1).map(move |index| &text[starts[index]..starts[index + 1]])
+}
+
+/// Returns the lowercase key opened by a section, or `None` for the header.
+fn section_key(section: &str) -> Option<String> {
+    let line = section.lines().next()?.strip_prefix('[')?;
+    let end = line.rfind(']')?;
+    Some(line[..end].to_ascii_lowercase())
+}
+
+/// Returns whether restoring `target` replaces `key`: the key itself or one
+/// of its subkeys.
+fn covers(target: &str, key: &str) -> bool {
+    target.is_empty()
+        || key
+            .strip_prefix(target)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with("\\\\"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_keys_are_escaped_like_wine_section_headers() {
+        let Target::Key { hive, key } =
+            Target::parse(&RestorePath::RegistryKey(r"HKCU\Software\\Game\".into())).unwrap()
+        else {
+            panic!("expected a registry key");
+        };
+        assert_eq!((hive, key.as_str()), ("user.reg", r"software\\game"));
+        assert!(Target::parse(&RestorePath::RegistryKey(r"HKEY_USERS\S-1-5".into())).is_err());
+        assert!(Target::parse(&RestorePath::File(r"C:\".into())).is_err());
+    }
+
+    #[test]
+    fn missing_hives_are_read_from_the_layers() {
+        futures_lite::future::block_on(async {
+            let upper = std::env::temp_dir().join(format!("bottles-next-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&upper).unwrap();
+            let path = upper.join("user.reg");
+            let layered = format!("{HIVE_HEADER}\n\n[Software\\\\Game] 1700000001\n");
+
+            let hive = read_hive(&path, async || Ok(Some(layered.clone().into_bytes())))
+                .await
+                .unwrap();
+            assert_eq!(hive, layered);
+            assert!(matches!(
+                read_hive(&path, async || Ok(None)).await,
+                Err(Error::Bottle(BottleError::InvalidRegistry { .. }))
+            ));
+
+            std::fs::write(&path, format!("{HIVE_HEADER}\n")).unwrap();
+            let hive = read_hive(&path, async || panic!("the upper hive exists"))
+                .await
+                .unwrap();
+            assert_eq!(hive, format!("{HIVE_HEADER}\n"));
+            std::fs::remove_dir_all(upper).unwrap();
+        });
+    }
+
+    #[test]
+    fn splicing_replaces_only_covered_keys() {
+        let current = "WINE REGISTRY Version 2\n\
+                       ;; All keys relative to \\\\User\\\\S-1-5-21-0-0-0-1000\n\
+                       \n\
+                       [Software\\\\Game] 1700000002\n\
+                       \"Volume\"=dword:00000009\n\
+                       \n\
+                       [Software\\\\Game\\\\New] 1700000002\n\
+                       \n\
+                       [Software\\\\GameTwo] 1700000002\n\
+                       \"Volume\"=dword:00000002\n";
+        let saved = "WINE REGISTRY Version 2\n\
+                     \n\
+                     [Software\\\\GAME] 1700000001\n\
+                     \"Volume\"=dword:00000005\n\
+                     \n\
+                     [Software\\\\GameTwo] 1700000001\n\
+                     \"Volume\"=dword:00000001\n";
+
+        assert_eq!(
+            splice(current, saved, &[r"software\\game"]),
+            "WINE REGISTRY Version 2\n\
+             ;; All keys relative to \\\\User\\\\S-1-5-21-0-0-0-1000\n\
+             \n\
+             [Software\\\\GameTwo] 1700000002\n\
+             \"Volume\"=dword:00000002\n\
+             [Software\\\\GAME] 1700000001\n\
+             \"Volume\"=dword:00000005\n\
+             \n"
+        );
+        assert_eq!(splice(current, saved, &[""]).lines().count(), 8);
+    }
+}
@@ -0,0 +1,931 @@
+//! Launched Windows processes: bridge launches, exit tracking, and ad hoc runs.
+//!
+//! Programs and ad hoc executables launch through WineBridge in a process
+//! group keyed by the program's UUID, or by a new UUID for an ad hoc launch.
+//! WineBridge cannot pass an environment or capture output, so each launch
+//! runs a generated batch script under `cmd.exe` that sets the launch
+//! environment, appends the executable's output to the launch log, and writes
+//! its exit code to a file. Each launch's [`ProgramRun`] shares a future that
+//! polls WineBridge until the process is gone, then reads the exit code and
+//! publishes the exit; it runs on whichever executor awaits the run.
+
+use std::{
+    collections::BTreeMap,
+    io, mem,
+    path::{Path, PathBuf},
+    sync::{Arc, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use async_io::Timer;
+use futures_core::Stream;
+use futures_lite::{AsyncBufReadExt, future, io::BufReader, stream};
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use tokio::sync::broadcast;
+use tokio_stream::{StreamExt as _, wrappers::BroadcastStream};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::{
+    error::Result,
+    prefix::dosdevices,
+    proto::DllOverrideMode,
+    utils::environment::Environment,
+    winebridge::WineBridgeClient,
+    wrapper::{Wrappers, gamescope::GamescopeConfig, mangohud::MangoHudConfig},
+};
+
+use super::{
+    error::BottleError,
+    history::LaunchRecord,
+    hooks::Hook,
+    logs::{self, LaunchLog},
+    state::Bottle,
+};
+
+/// An executable, argument, or directory passed to [`Bottle::run`].
+///
+/// Strings are passed to Windows verbatim. Host paths are translated to the
+/// Windows path of the drive that exposes them when the launch happens.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RunArg {
+    /// Windows text such as `winecfg`, `C:\setup.exe`, or `/S`.
+    Windows(String),
+    /// An absolute host path such as `/home/user/Downloads/setup.exe`.
+    Host(PathBuf),
+}
+
+impl From<String> for RunArg {
+    fn from(value: String) -> Self {
+        Self::Windows(value)
+    }
+}
+
+impl From<&str> for RunArg {
+    fn from(value: &str) -> Self {
+        Self::Windows(value.to_owned())
+    }
+}
+
+impl From<PathBuf> for RunArg {
+    fn from(path: PathBuf) -> Self {
+        Self::Host(path)
+    }
+}
+
+impl From<&Path> for RunArg {
+    fn from(path: &Path) -> Self {
+        Self::Host(path.to_path_buf())
+    }
+}
+
+/// Launch options for [`Bottle::run`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RunOptions {
+    working_directory: Option<RunArg>,
+    new_console: bool,
+}
+
+impl RunOptions {
+    /// Sets the working directory, as a Windows or host path.
+    pub fn with_working_directory(mut self, working_directory: impl Into<RunArg>) -> Self {
+        self.working_directory = Some(working_directory.into());
+        self
+    }
+
+    /// Gives the launch a console window of its own. Output written to that
+    /// console is not captured in the launch log.
+    pub fn with_new_console(mut self, new_console: bool) -> Self {
+        self.new_console = new_console;
+        self
+    }
+}
+
+/// One host launch assembled by [`Bottle::launch_program`] or [`Bottle::run`].
+pub(super) struct Launch {
+    /// The registered program, or `None` for an ad hoc launch.
+    pub(super) program: Option<Uuid>,
+    pub(super) executable: RunArg,
+    /// Command-line fragments joined with spaces.
+    pub(super) arguments: Vec<RunArg>,
+    pub(super) working_directory: Option<RunArg>,
+    pub(super) new_console: bool,
+    /// Variables applied over the environment inherited from WineBridge.
+    pub(super) environment: Environment,
+    /// Loading modes merged into `WINEDLLOVERRIDES`.
+    pub(super) dll_overrides: BTreeMap<String, DllOverrideMode>,
+    /// Replaces the bottle's Gamescope configuration if the launch starts
+    /// WineBridge, and must otherwise match the running WineBridge's.
+    pub(super) gamescope: Option<GamescopeConfig>,
+    /// Replaces the bottle's MangoHud configuration if the launch starts
+    /// WineBridge, and must otherwise match the running WineBridge's.
+    pub(super) mangohud: Option<MangoHudConfig>,
+    /// Hooks run before spawning; a failure aborts the launch.
+    pub(super) pre_launch: Vec<Hook>,
+    /// Hooks run after the process exits.
+    pub(super) post_exit: Vec<Hook>,
+}
+
+/// How often WineBridge is polled for a launch's exit, and a followed log for
+/// new output.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How many launch events a slow [`Bottle::watch_processes`] stream may fall
+/// behind before it misses the oldest.
+pub(super) const EVENTS_CAPACITY: usize = 64;
+
+/// A process started by [`Bottle::launch_program`] or [`Bottle::run`].
+///
+/// The process runs inside the bottle, launched by WineBridge. Clones refer
+/// to the same launch.
+///
+/// Like an [`Operation`](crate::Operation), a run does not require a
+/// particular async runtime: its exit is tracked while [`wait`](Self::wait)
+/// or [`follow_log`](Self::follow_log) is polled on any clone. Spawn `wait`
+/// on an executor to record the exit and run post-exit hooks without
+/// awaiting it. A launch nobody waits for keeps running, but its exit is
+/// neither recorded nor reported to [`Bottle::watch_processes`].
+#[derive(Clone)]
+pub struct ProgramRun {
+    group: Uuid,
+    pid: u32,
+    started_at: SystemTime,
+    log: LaunchLog,
+    exit: Shared<BoxFuture<'static, ProgramExit>>,
+    kill: CancellationToken,
+}
+
+/// How a launched process ended, as reported by [`ProgramRun::wait`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ProgramExit {
+    /// When the launch started.
+    pub started_at: SystemTime,
+    /// When the launch was seen to have exited, at most half a second after
+    /// it did.
+    pub exited_at: SystemTime,
+    /// The Windows exit code of the launched executable.
+    ///
+    /// This is `None` when the launch was killed, for example through
+    /// [`ProgramRun::kill`], ended because the bottle stopped, or did not
+    /// record its exit code.
+    pub exit_code: Option<i32>,
+}
+
+/// A launch starting or exiting, as reported by [`Bottle::watch_processes`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProcessEvent {
+    /// A launch started, or was running when watching started.
+    Started {
+        /// The launched program's UUID, or a UUID unique to an ad hoc launch,
+        /// as returned by [`ProgramRun::group`].
+        group: Uuid,
+        /// The Windows process ID of the launch, as returned by
+        /// [`ProgramRun::pid`].
+        pid: u32,
+        /// When the launch started.
+        started_at: SystemTime,
+    },
+    /// A launch exited and its post-exit hooks finished.
+    Exited {
+        /// The group of the launch, as for [`ProcessEvent::Started`].
+        group: Uuid,
+        /// The Windows process ID of the launch.
+        pid: u32,
+        /// How the launch ended, as [`ProgramRun::wait`] reports it.
+        exit: ProgramExit,
+    },
+}
+
+impl ProgramRun {
+    /// Returns the launched program's UUID, or a UUID unique to an ad hoc
+    /// launch.
+    pub fn group(&self) -> Uuid {
+        self.group
+    }
+
+    /// Returns the Windows process ID of the launch, as listed by
+    /// [`Bottle::processes`].
+    ///
+    /// This is the `cmd.exe` process that runs the executable and records its
+    /// exit code.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Returns when the launch started.
+    pub fn started_at(&self) -> SystemTime {
+        self.started_at
+    }
+
+    /// Returns the log capturing this launch's output.
+    pub fn log(&self) -> &LaunchLog {
+        &self.log
+    }
+
+    /// Returns the exit once the process has exited and post-exit hooks have
+    /// finished.
+    pub fn try_exit(&self) -> Option<ProgramExit> {
+        self.exit.peek().copied()
+    }
+
+    /// Waits until the launched process exits.
+    ///
+    /// The launch is in [`Bottle::history`] and the program's post-exit
+    /// hooks have finished by the time this returns. Processes that the
+    /// launched process spawned are not awaited. Stopping the bottle ends
+    /// every Wine process and therefore the wait. Waiting on several clones
+    /// at once is allowed; they share one tracker.
+    pub async fn wait(&self) -> ProgramExit {
+        self.exit.clone().await
+    }
+
+    /// Requests termination of the launch's process group through WineBridge.
+    ///
+    /// Launches of the same program share its group, so this ends all of
+    /// them, as [`Bottle::kill_program`] does. Killing a launch that already
+    /// exited has no effect. The request is sent while the exit is tracked,
+    /// so await [`wait`](Self::wait) to deliver it and observe the exit.
+    pub fn kill(&self) {
+        self.kill.cancel();
+    }
+
+    /// Streams the log line by line until the launch exits.
+    ///
+    /// Output is read from the start of the log. Reaching the end of the file
+    /// while the process runs waits for more output rather than ending the
+    /// stream. The stream ends after yielding an error.
+    pub fn follow_log(&self) -> impl Stream<Item = Result<String>> + Send + 'static {
+        let run = self.clone();
+        stream::unfold(Some((run, None, Vec::new())), |state| async move {
+            let (run, reader, mut line) = state?;
+            let mut reader = match reader {
+                Some(reader) => reader,
+                None => match async_fs::File::open(run.log.path()).await {
+                    Ok(file) => BufReader::new(file),
+                    Err(error) => return Some((Err(error.into()), None)),
+                },
+            };
+            loop {
+                // Checked before reading so output flushed at exit is not lost.
+                let exited = run.try_exit().is_some();
+                match reader.read_until(b'\n', &mut line).await {
+                    Err(error) => return Some((Err(error.into()), None)),
+                    Ok(_) if line.ends_with(b"\n") => {
+                        let text = logs::decode(&line[..line.len() - 1]);
+                        return Some((Ok(text), Some((run, Some(reader), Vec::new()))));
+                    }
+                    Ok(_) if exited => {
+                        if line.is_empty() {
+                            return None;
+                        }
+                        return Some((Ok(logs::decode(&line)), None));
+                    }
+                    Ok(_) => {
+                        // Tracks the exit too, so following alone ends the stream.
+                        future::or(
+                            async {
+                                Timer::after(POLL_INTERVAL).await;
+                            },
+                            async {
+                                run.exit.clone().await;
+                            },
+                        )
+                        .await
+                    }
+                }
+            }
+        })
+    }
+}
+
+impl Bottle {
+    /// Watches launches of programs and ad hoc executables starting and
+    /// exiting in the bottle.
+    ///
+    /// The stream first reports every launch that is still running as
+    /// started, then reports launches as they start and exit, each keyed by
+    /// its program group and carrying the exit code of the launch. An exit is
+    /// reported once the launch's [`ProgramRun`] observes it. Processes
+    /// started by other means, such as by a launched program, are not
+    /// reported; list them with [`processes`](Self::processes). Watching does
+    /// not start WineBridge. A stream that falls more than 64 events behind
+    /// misses the oldest ones. The stream ends when every handle to the bottle
+    /// has been dropped.
+    pub fn watch_processes(&self) -> impl Stream<Item = ProcessEvent> + Send + 'static {
+        let launches = self
+            .0
+            .launches
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let running = launches
+            .iter()
+            .map(|run| ProcessEvent::Started {
+                group: run.group,
+                pid: run.pid,
+                started_at: run.started_at,
+            })
+            .collect();
+        process_events(running, self.0.process_events.subscribe())
+    }
+
+    /// Launches an executable without registering a [`super::Program`].
+    ///
+    /// This suits installers and one-off tools such as `winecfg` or
+    /// `regedit`. The launch gets a process group of its own, so killing it
+    /// does not affect other launches. A host executable, argument, or working
+    /// directory is translated through the prefix's drive links, preferring
+    /// the most specific drive, so a path inside `drive_c` becomes a `C:`
+    /// path. Arguments are joined with spaces, as for programs; Windows text
+    /// must include any quoting it needs, while translated host paths are
+    /// quoted automatically when they contain whitespace. Output is captured
+    /// in a log shared by ad hoc launches and available through
+    /// [`ProgramRun::log`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::UnmappedHostPath`] for a host path that is
+    /// relative or not exposed by any drive, including the bottle's log
+    /// directory. Prefix, bridge, and launch failures are also returned.
+    pub async fn run<I, A>(
+        &self,
+        executable: impl Into<RunArg>,
+        args: I,
+        options: RunOptions,
+    ) -> Result<ProgramRun>
+    where
+        I: IntoIterator<Item = A>,
+        A: Into<RunArg>,
+    {
+        self.spawn_launch(Launch {
+            program: None,
+            executable: executable.into(),
+            arguments: args.into_iter().map(Into::into).collect(),
+            working_directory: options.working_directory,
+            new_console: options.new_console,
+            environment: Environment::default(),
+            dll_overrides: BTreeMap::new(),
+            gamescope: None,
+            mangohud: None,
+            pre_launch: Vec::new(),
+            post_exit: Vec::new(),
+        })
+        .await
+    }
+
+    /// Launches `launch` through WineBridge and starts tracking it.
+    ///
+    /// Pre-launch hooks run once the command line is resolved, so an invalid
+    /// launch fails before any hook runs. They run without shared bottle
+    /// access, so a slow hook does not hold up edits, and see the environment
+    /// of the state the launch was resolved against. A backup that the
+    /// bottle's policy asks for is taken before anything else.
+    pub(super) async fn spawn_launch(&self, mut launch: Launch) -> Result<ProgramRun> {
+        let prefix = self.prefix_path();
+        let program = launch.program;
+        let group = program.unwrap_or_else(Uuid::new_v4);
+        let log_dir = program.map_or_else(|| logs::ADHOC.to_owned(), |id| id.to_string());
+        let executable = match &launch.executable {
+            RunArg::Windows(executable) => executable.clone(),
+            RunArg::Host(path) => path.display().to_string(),
+        };
+        let pre_launch = mem::take(&mut launch.pre_launch);
+        let post_exit = mem::take(&mut launch.post_exit);
+        let gamescope = launch.gamescope.take();
+        let mangohud = launch.mangohud.take();
+        #[cfg(feature = "fvs")]
+        self.back_up_before_launch().await;
+        let script = LaunchScript::new(&prefix);
+        let (contents, working_directory, wrappers, log, file, hook_environment, components) = self
+            .with_prefix(async |state, _| {
+                let mut line = vec![format!(
+                    "\"{}\"",
+                    windows_arg(&prefix, launch.executable, false)
+                        .await?
+                        .trim_matches('"')
+                )];
+                for arg in launch.arguments {
+                    line.push(windows_arg(&prefix, arg, true).await?);
+                }
+                let working_directory = match launch.working_directory {
+                    Some(directory) => Some(windows_arg(&prefix, directory, false).await?),
+                    None => None,
+                };
+
+                let mut environment = launch.environment;
+                if !launch.dll_overrides.is_empty() {
+                    let existing = environment
+                        .get("WINEDLLOVERRIDES")
+                        .or_else(|| state.environment.get("WINEDLLOVERRIDES"));
+                    let merged = merge_dll_overrides(existing, &launch.dll_overrides);
+                    environment.insert("WINEDLLOVERRIDES".to_owned(), merged);
+                }
+                let wrappers = Wrappers {
+                    gamescope: gamescope
+                        .clone()
+                        .unwrap_or_else(|| state.wrappers.gamescope.clone()),
+                    mangohud: mangohud
+                        .clone()
+                        .unwrap_or_else(|| state.wrappers.mangohud.clone()),
+                };
+                let (file, log) = logs::create(&self.log_dir(&log_dir)).await?;
+                let output = if launch.new_console {
+                    None
+                } else {
+                    let windows = dosdevices::windows_path(&prefix, log.path())
+                        .await?
+                        .ok_or_else(|| BottleError::UnmappedHostPath(log.path().to_path_buf()))?;
+                    Some(windows)
+                };
+                let contents = launch_script(
+                    &environment,
+                    &line.join(" "),
+                    output.as_deref(),
+                    &script.windows_exit(),
+                )?;
+
+                let mut hook_environment = state.environment.clone();
+                hook_environment.extend(environment.clone());
+                hook_environment.insert("WINEPREFIX".to_owned(), prefix.display().to_string());
+                if let Some(id) = program {
+                    hook_environment.insert("BOTTLES_PROGRAM_ID".to_owned(), id.to_string());
+                }
+                Ok((
+                    contents,
+                    working_directory,
+                    wrappers,
+                    log,
+                    file,
+                    hook_environment,
+                    state.components.clone(),
+                ))
+            })
+            .await?;
+
+        for hook in &pre_launch {
+            hook.run(&hook_environment, &file).await?;
+        }
+        let output = file.try_clone()?;
+
+        let pid = self
+            .with_prefix(async |state, runner| {
+                let bridge = self.start_bridge(state, runner, &wrappers).await?;
+                // The program runs inside WineBridge, so it cannot use other
+                // wrappers than those WineBridge was started with.
+                let running = self.bridge_wrappers(state);
+                if gamescope.is_some_and(|config| config != running.gamescope)
+                    || mangohud.is_some_and(|config| config != running.mangohud)
+                {
+                    return Err(BottleError::WrappersInUse(group).into());
+                }
+                script.write(&contents).await?;
+                let pid = bridge
+                    .launch_process(
+                        group,
+                        CMD.to_owned(),
+                        vec!["/c".to_owned(), script.windows_script()],
+                        working_directory,
+                        launch.new_console,
+                    )
+                    .await;
+                let pid = match pid {
+                    Ok(pid) => pid,
+                    Err(error) => {
+                        script.remove().await;
+                        return Err(error);
+                    }
+                };
+                Ok(pid)
+            })
+            .await?;
+
+        let kill = CancellationToken::new();
+        let started_at = log.started_at();
+        // Weak, so a launch nobody waits for does not keep the bottle alive
+        // through its entry in `launches`.
+        let bottle = Arc::downgrade(&self.0);
+        let track = {
+            let kill = kill.clone();
+            async move {
+                let exit_code = reap(&prefix, group, pid, &kill, &script).await;
+                let exited_at = SystemTime::now();
+                let bottle = bottle.upgrade().map(Bottle);
+                if let Some(bottle) = &bottle {
+                    bottle
+                        .record_launch(LaunchRecord {
+                            program,
+                            executable,
+                            started_at,
+                            exited_at,
+                            exit_code,
+                            components,
+                        })
+                        .await;
+                }
+                if !post_exit.is_empty() {
+                    let mut environment = hook_environment;
+                    if let Some(code) = exit_code {
+                        environment.insert("BOTTLES_EXIT_CODE".to_owned(), code.to_string());
+                    }
+                    for hook in &post_exit {
+                        if let Err(error) = hook.run(&environment, &file).await {
+                            tracing::warn!(
+                                "post-exit hook of launched process {pid} failed: {error}"
+                            );
+                        }
+                    }
+                }
+                let exit = ProgramExit {
+                    started_at,
+                    exited_at,
+                    exit_code,
+                };
+                if let Some(bottle) = &bottle {
+                    bottle.finish_launch(group, pid, exit);
+                }
+                exit
+            }
+        };
+        let run = ProgramRun {
+            group,
+            pid,
+            started_at,
+            log,
+            exit: track.boxed().shared(),
+            kill,
+        };
+        {
+            let mut launches = self
+                .0
+                .launches
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            launches.push(run.clone());
+            self.0
+                .launch_output
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(pid, output);
+            // Sent under the lock so a new watcher sees the launch exactly once.
+            let _ = self.0.process_events.send(ProcessEvent::Started {
+                group,
+                pid,
+                started_at: run.started_at,
+            });
+        }
+
+        Ok(run)
+    }
+
+    /// Stops tracking a launch and reports its exit to process watchers.
+    fn finish_launch(&self, group: Uuid, pid: u32, exit: ProgramExit) {
+        let mut launches = self
+            .0
+            .launches
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        launches.retain(|run| run.pid != pid);
+        self.0
+            .launch_output
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&pid);
+        let _ = self
+            .0
+            .process_events
+            .send(ProcessEvent::Exited { group, pid, exit });
+    }
+}
+
+/// Reports `running` launches as started, then the events sent to `receiver`.
+fn process_events(
+    running: Vec<ProcessEvent>,
+    receiver: broadcast::Receiver<ProcessEvent>,
+) -> impl Stream<Item = ProcessEvent> + Send + 'static {
+    let live = BroadcastStream::new(receiver).filter_map(|event| event.ok());
+    stream::iter(running).chain(live)
+}
+
+/// The Windows command interpreter that runs launch scripts.
+const CMD: &str = r"C:\windows\system32\cmd.exe";
+
+/// The batch script and exit code file of one launch, kept in the prefix's
+/// `C:\windows\temp`.
+struct LaunchScript {
+    /// Host path without extension.
+    host: PathBuf,
+    /// File name without extension.
+    name: String,
+}
+
+impl LaunchScript {
+    fn new(prefix: &Path) -> Self {
+        let name = format!("bottles-launch-{}", Uuid::new_v4());
+        Self {
+            host: prefix.join("drive_c/windows/temp").join(&name),
+            name,
+        }
+    }
+
+    fn windows_script(&self) -> String {
+        format!(r"C:\windows\temp\{}.cmd", self.name)
+    }
+
+    fn windows_exit(&self) -> String {
+        format!(r"C:\windows\temp\{}.exit", self.name)
+    }
+
+    async fn write(&self, contents: &str) -> Result<()> {
+        if let Some(parent) = self.host.parent() {
+            async_fs::create_dir_all(parent).await?;
+        }
+        async_fs::write(self.host.with_extension("cmd"), contents).await?;
+        Ok(())
+    }
+
+    /// Reads the recorded exit code and removes both files.
+    async fn exit_code(&self) -> Option<i32> {
+        let code = async_fs::read_to_string(self.host.with_extension("exit"))
+            .await
+            .ok()
+            .and_then(|code| code.trim().parse().ok());
+        self.remove().await;
+        code
+    }
+
+    async fn remove(&self) {
+        for extension in ["cmd", "exit"] {
+            match async_fs::remove_file(self.host.with_extension(extension)).await {
+                Ok(()) => {}
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                Err(error) => tracing::warn!("failed to remove launch script file: {error}"),
+            }
+        }
+    }
+}
+
+/// Waits until WineBridge no longer lists the launch's process, forwarding a
+/// kill request to its group, and returns the exit code the script recorded.
+///
+/// A WineBridge that stopped or cannot be reached ends the wait, since
+/// stopping the bottle ends every Wine process.
+async fn reap(
+    prefix: &Path,
+    group: Uuid,
+    pid: u32,
+    kill: &CancellationToken,
+    script: &LaunchScript,
+) -> Option<i32> {
+    let mut killed = false;
+    loop {
+        if killed {
+            Timer::after(POLL_INTERVAL).await;
+        } else {
+            future::or(
+                async {
+                    Timer::after(POLL_INTERVAL).await;
+                },
+                kill.cancelled(),
+            )
+            .await;
+        }
+        let bridge = match WineBridgeClient::try_connect(prefix).await {
+            Ok(Some(bridge)) => bridge,
+            Ok(None) => break,
+            Err(error) => {
+                tracing::warn!("failed to reach WineBridge for launched process {pid}: {error}");
+                break;
+            }
+        };
+        if kill.is_cancelled() && !killed {
+            killed = true;
+            if let Err(error) = bridge.kill_process(group).await {
+                tracing::warn!("failed to kill launched process {pid}: {error}");
+            }
+        }
+        match bridge.list_processes().await {
+            Ok(processes) if processes.iter().any(|process| process.pid == pid) => {}
+            Ok(_) => break,
+            Err(error) => {
+                tracing::warn!("failed to list processes for launched process {pid}: {error}");
+                break;
+            }
+        }
+    }
+    script.exit_code().await
+}
+
+/// Renders the batch script that runs one launch under `cmd.exe`.
+///
+/// The script sets `environment`, runs `command` with its standard output and
+/// standard error appended to the Windows path `log` when one is given, and
+/// writes the exit code to the Windows path `exit_file`. Text is escaped so
+/// that `cmd.exe` passes it on literally.
+fn launch_script(
+    environment: &Environment,
+    command: &str,
+    log: Option<&str>,
+    exit_file: &str,
+) -> Result<String> {
+    let mut script = String::from("@echo off\r\nchcp 65001 >nul\r\n");
+    for (key, value) in environment.iter() {
+        if key.contains(['\r', '\n']) {
+            return Err(BottleError::InvalidEnvironmentName(key.to_owned()).into());
+        }
+        if value.contains(['\r', '\n']) {
+            return Err(BottleError::InvalidEnvironmentValue(key.to_owned()).into());
+        }
+        script.push_str(&format!(
+            "set \"{}={}\"\r\n",
+            key.replace('%', "%%"),
+            value.replace('%', "%%")
+        ));
+    }
+    if command.contains(['\r', '\n']) {
+        return Err(BottleError::InvalidProgram(
+            "command line must not contain line breaks".into(),
+        )
+        .into());
+    }
+    script.push_str(&escape_batch(command));
+    if let Some(log) = log {
+        script.push_str(&format!(" >>\"{}\" 2>&1", log.replace('%', "%%")));
+    }
+    script.push_str(&format!(
+        "\r\n>\"{}\" echo %errorlevel%\r\n",
+        exit_file.replace('%', "%%")
+    ));
+    Ok(script)
+}
+
+/// Escapes a Windows command line for a batch file: `%` is doubled, and
+/// outside double quotes `cmd.exe` operators are prefixed with `^`.
+fn escape_batch(line: &str) -> String {
+    let mut escaped = String::with_capacity(line.len());
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '%' => escaped.push('%'),
+            '^' | '&' | '|' | '<' | '>' if !quoted => escaped.push('^'),
+            _ => {}
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Renders one launch value as Windows text.
+///
+/// Only command-line arguments are quoted; the executable and working
+/// directory are passed separately.
+async fn windows_arg(prefix: &Path, arg: RunArg, quote: bool) -> Result<String> {
+    let path = match arg {
+        RunArg::Windows(value) => return Ok(value),
+        RunArg::Host(path) => path,
+    };
+    if !path.is_absolute() {
+        return Err(BottleError::UnmappedHostPath(path).into());
+    }
+    let windows = dosdevices::windows_path(prefix, &path)
+        .await?
+        .ok_or(BottleError::UnmappedHostPath(path))?;
+    if quote && windows.contains(char::is_whitespace) {
+        Ok(format!("\"{windows}\""))
+    } else {
+        Ok(windows)
+    }
+}
+
+/// Resolves a launch path, such as a working directory, to its host path.
+pub(super) async fn host_path(prefix: &Path, path: RunArg) -> Result<PathBuf> {
+    match path {
+        RunArg::Host(path) if path.is_absolute() => Ok(path),
+        RunArg::Host(path) => Err(BottleError::UnmappedHostPath(path).into()),
+        RunArg::Windows(path) => match dosdevices::host_path(prefix, &path).await? {
+            Some(host) => Ok(host),
+            None => Err(BottleError::UnmappedWindowsPath(path).into()),
+        },
+    }
+}
+
+/// Builds a `WINEDLLOVERRIDES` value giving `overrides` precedence over the
+/// entries of `existing` that name the same DLLs.
+///
+/// DLL names are compared case-insensitively, as Wine does. Entries of
+/// `existing` that list several DLLs keep only the DLLs not overridden.
+fn merge_dll_overrides(
+    existing: Option<&str>,
+    overrides: &BTreeMap<String, DllOverrideMode>,
+) -> String {
+    let overridden = |dll: &str| overrides.keys().any(|key| key.eq_ignore_ascii_case(dll));
+    let mut entries = overrides
+        .iter()
+        .filter_map(|(dll, mode)| Some(format!("{dll}={}", dll_override_mode(*mode)?)))
+        .collect::<Vec<_>>();
+    for entry in existing.unwrap_or_default().split(';') {
+        let (dlls, mode) = entry.split_once('=').unwrap_or((entry, ""));
+        let dlls = dlls
+            .split(',')
+            .filter(|dll| !dll.is_empty() && !overridden(dll))
+            .collect::<Vec<_>>();
+        if !dlls.is_empty() {
+            entries.push(format!("{}={mode}", dlls.join(",")));
+        }
+    }
+    entries.join(";")
+}
+
+/// Renders `mode` in `WINEDLLOVERRIDES` syntax, where an empty mode disables
+/// the DLL.
+fn dll_override_mode(mode: DllOverrideMode) -> Option<&'static str> {
+    match mode {
+        DllOverrideMode::Unspecified => None,
+        DllOverrideMode::Native => Some("n"),
+        DllOverrideMode::Builtin => Some("b"),
+        DllOverrideMode::NativeBuiltin => Some("n,b"),
+        DllOverrideMode::BuiltinNative => Some("b,n"),
+        DllOverrideMode::Disabled => Some(""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn launch_scripts_pass_text_through_cmd_literally() {
+        let mut environment = Environment::default();
+        environment.insert("WINEDEBUG".to_owned(), "+relay,-heap".to_owned());
+        environment.insert("GAME_PATH".to_owned(), "%APPDATA%\\Game".to_owned());
+
+        let script = launch_script(
+            &environment,
+            r#""C:\Program Files\Game\game.exe" -x a&b "c&d" 50%"#,
+            Some(r"Z:\home\user\1.log"),
+            r"C:\windows\temp\launch.exit",
+        )
+        .unwrap();
+
+        assert!(script.starts_with("@echo off\r\n"));
+        assert!(script.contains("set \"WINEDEBUG=+relay,-heap\"\r\n"));
+        assert!(script.contains("set \"GAME_PATH=%%APPDATA%%\\Game\"\r\n"));
+        assert!(script.contains(
+            r#""C:\Program Files\Game\game.exe" -x a^&b "c&d" 50%% >>"Z:\home\user\1.log" 2>&1"#
+        ));
+        assert!(script.ends_with(">\"C:\\windows\\temp\\launch.exit\" echo %errorlevel%\r\n"));
+
+        environment.insert("BROKEN".to_owned(), "a\nb".to_owned());
+        assert!(launch_script(&environment, "game.exe", None, "exit").is_err());
+    }
+
+    #[test]
+    fn watchers_see_the_recorded_exit_code_of_a_launch() {
+        futures_lite::future::block_on(async {
+            let prefix = std::env::temp_dir().join(format!("bottles-next-{}", Uuid::new_v4()));
+            let script = LaunchScript::new(&prefix);
+            script.write("@echo off\r\n").await.unwrap();
+            std::fs::write(script.host.with_extension("exit"), "3 \r\n").unwrap();
+            let exit_code = script.exit_code().await;
+            assert_eq!(exit_code, Some(3));
+            assert!(!script.host.with_extension("cmd").exists());
+            assert!(!script.host.with_extension("exit").exists());
+
+            let (group, started_at) = (Uuid::new_v4(), SystemTime::now());
+            let started = ProcessEvent::Started {
+                group,
+                pid: 32,
+                started_at,
+            };
+            let exited = ProcessEvent::Exited {
+                group,
+                pid: 32,
+                exit: ProgramExit {
+                    started_at,
+                    exited_at: SystemTime::now(),
+                    exit_code,
+                },
+            };
+            let (sender, receiver) = broadcast::channel(EVENTS_CAPACITY);
+            let events = process_events(vec![started], receiver);
+            sender.send(exited).unwrap();
+            drop(sender);
+
+            assert_eq!(events.collect::<Vec<_>>().await, [started, exited]);
+            std::fs::remove_dir_all(prefix).unwrap();
+        });
+    }
+
+    #[test]
+    fn program_dll_overrides_take_precedence() {
+        let overrides = BTreeMap::from([
+            ("d3d11".to_owned(), DllOverrideMode::Native),
+            ("xinput1_3".to_owned(), DllOverrideMode::Disabled),
+        ]);
+
+        assert_eq!(merge_dll_overrides(None, &overrides), "d3d11=n;xinput1_3=");
+        assert_eq!(
+            merge_dll_overrides(Some("D3D11,dxgi=b;winemenubuilder.exe=d"), &overrides),
+            "d3d11=n;xinput1_3=;dxgi=b;winemenubuilder.exe=d"
+        );
+    }
+}
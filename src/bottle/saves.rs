@@ -0,0 +1,570 @@
+//! Game saves located by per-program rules and kept in an archive store.
+
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    env, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures_lite::StreamExt;
+use uuid::Uuid;
+
+use crate::{
+    Operation, Progress, Stage, Transfer,
+    error::{Error, Result},
+    utils::archive,
+};
+
+use super::{error::BottleError, files::CDrivePath, manager::BottleManager, state::Bottle};
+
+/// Suffix of save backup archives, which selects their compression.
+const EXTENSION: &str = ".tar.zst";
+
+/// A save backup in the archive store, as returned by
+/// [`Bottle::back_up_saves`] and [`BottleManager::save_backups`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct SaveBackup {
+    /// The [save key](crate::Program::save_key) of the program whose saves
+    /// the backup holds.
+    pub key: String,
+    /// When the backup was taken, to whole seconds.
+    pub created: SystemTime,
+    /// The archive, with paths relative to the user profile.
+    pub path: PathBuf,
+}
+
+/// Where [`BottleManager::sync_saves`] mirrors save backups.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum SyncTarget {
+    /// A host directory, such as a folder that another tool synchronizes.
+    /// Backups are kept in a subdirectory named after the save key.
+    Directory(PathBuf),
+}
+
+/// What [`BottleManager::sync_saves`] copied.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct SaveSync {
+    /// Backups copied from the archive store to the target.
+    pub uploaded: u32,
+    /// Backups copied from the target to the archive store.
+    pub downloaded: u32,
+}
+
+impl Bottle {
+    /// Returns the Windows paths of the files and directories that a
+    /// program's save-game rules match, sorted.
+    ///
+    /// Rules are resolved against the user profile of the prefix: the one
+    /// named after the current user, else `steamuser`, else the first by
+    /// name. Profile folders that Wine links to host directories, such as
+    /// `Documents`, are followed. A matched directory is listed instead of
+    /// anything below it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::ProgramNotFound`] if `program_id` is not
+    /// registered, [`BottleError::NoUserProfile`] if the prefix has no user
+    /// profile, and an error if the prefix cannot be prepared or a directory
+    /// cannot be read.
+    pub async fn locate_saves(&self, program_id: Uuid) -> Result<Vec<String>> {
+        self.with_prefix(async |state, _| {
+            let program = state
+                .program(program_id)
+                .ok_or(BottleError::ProgramNotFound(program_id))?;
+            let users = self.c_drive_path().join("users");
+            let user = user_profile(&users).await?;
+            let saves = locate(&users.join(&user), program.save_paths()).await?;
+            Ok(saves
+                .into_iter()
+                .map(|path| {
+                    CDrivePath {
+                        relative: Path::new("users").join(&user).join(path),
+                    }
+                    .windows()
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// Archives the files that a program's save-game rules match into the
+    /// archive store.
+    ///
+    /// Files are located as [`locate_saves`](Self::locate_saves) locates
+    /// them and stored relative to the user profile, so the backup can be
+    /// restored into a bottle whose profile has another name. Backups are
+    /// kept under the program's [save key](crate::Program::save_key), and
+    /// [`BottleManager::save_backups`] lists them by that key. The operation
+    /// holds shared bottle access, so the program may keep running; close
+    /// it first for a consistent backup. A backup taken in the same second
+    /// as an earlier one under the same key replaces it. Cancellation is
+    /// not observed.
+    ///
+    /// # Errors
+    ///
+    /// The operation returns [`BottleError::NoSaveFiles`] if no file
+    /// matches, and an error as `locate_saves` does or if the archive cannot
+    /// be written.
+    pub fn back_up_saves(&self, program_id: Uuid) -> Operation<SaveBackup> {
+        let bottle = self.clone();
+        Operation::new(move |progress, _cancellation| async move {
+            bottle
+                .with_prefix(async |state, _| {
+                    let program = state
+                        .program(program_id)
+                        .ok_or(BottleError::ProgramNotFound(program_id))?;
+                    let users = bottle.c_drive_path().join("users");
+                    let profile = users.join(user_profile(&users).await?);
+                    let saves = locate(&profile, program.save_paths()).await?;
+                    if saves.is_empty() {
+                        return Err(BottleError::NoSaveFiles(program_id).into());
+                    }
+
+                    progress.send_replace(Some(Progress::new(Stage::Archiving)));
+                    let store = bottle.0.cx.directories().saves();
+                    store_backup(&store, program.save_key(), &profile, &saves).await
+                })
+                .await
+        })
+    }
+
+    /// Extracts a save backup into the user profile of this bottle.
+    ///
+    /// The backup may come from any bottle, so saves follow a game moved
+    /// between bottles. Files in the backup replace those in the profile;
+    /// other files are kept. The profile is chosen as
+    /// [`locate_saves`](Self::locate_saves) chooses it. Close the program
+    /// first, since it may overwrite restored files. The bottle is held
+    /// exclusively while extracting, so no backup reads a half-restored
+    /// profile. Cancellation is not observed.
+    ///
+    /// # Errors
+    ///
+    /// The operation returns [`BottleError::NoUserProfile`] if the prefix
+    /// has no user profile, and an error if the prefix cannot be prepared
+    /// or the archive cannot be extracted.
+    pub fn restore_saves(&self, backup: &SaveBackup) -> Operation<()> {
+        let bottle = self.clone();
+        let archive_path = backup.path.clone();
+        Operation::new(move |progress, _cancellation| async move {
+            let _write = bottle.0.write_lock.write().await;
+            let state = bottle.state()?;
+            state
+                .storage
+                .prepare(&bottle.bottle_path(), &state.drives, &bottle.0.cx)
+                .await?;
+            let users = bottle.c_drive_path().join("users");
+            let profile = users.join(user_profile(&users).await?);
+            progress.send_replace(Some(Progress::new(Stage::Extracting)));
+            archive::extract(&archive_path, &profile).await?;
+            Ok(())
+        })
+    }
+}
+
+impl BottleManager {
+    /// Lists the save backups stored under a
+    /// [save key](crate::Program::save_key) in the archive store,
+    /// newest-first.
+    ///
+    /// Backups outlive the bottle they were taken from, so they can be
+    /// restored after it is deleted or into a bottle whose program has the
+    /// same key.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::InvalidSaveKey`] for a malformed key, or an
+    /// error if the archive store cannot be read.
+    pub async fn save_backups(&self, key: &str) -> Result<Vec<SaveBackup>> {
+        validate_key(key)?;
+        let store = self.context.directories().saves();
+        backups(&store.join(key), key).await
+    }
+
+    /// Copies the save backups stored under a
+    /// [save key](crate::Program::save_key) that only one side has between
+    /// the archive store and `target`, so both end up with every backup.
+    ///
+    /// Backups are never deleted or replaced, and backups copied from the
+    /// target can be restored with [`Bottle::restore_saves`]. The operation
+    /// reports the backups copied so far with [`Stage::Syncing`] and
+    /// observes cancellation between backups.
+    ///
+    /// # Errors
+    ///
+    /// The operation returns [`BottleError::InvalidSaveKey`] for a malformed
+    /// key, or an error if cancellation is requested or a backup cannot be
+    /// read or written.
+    pub fn sync_saves(&self, key: &str, target: SyncTarget) -> Operation<SaveSync> {
+        let key = key.to_owned();
+        let store = self.context.directories().saves().join(&key);
+        Operation::new(move |progress, cancellation| async move {
+            validate_key(&key)?;
+            let SyncTarget::Directory(target) = target;
+            let remote = target.join(&key);
+            let local_backups = backups(&store, &key).await?;
+            let remote_backups = backups(&remote, &key).await?;
+            let names = |backups: &[SaveBackup]| {
+                backups
+                    .iter()
+                    .filter_map(|backup| backup.path.file_name().map(ToOwned::to_owned))
+                    .collect::<HashSet<_>>()
+            };
+            let (local_names, remote_names) = (names(&local_backups), names(&remote_backups));
+            let mut copies = Vec::new();
+            for (backups, others, destination, upload) in [
+                (&local_backups, &remote_names, &remote, true),
+                (&remote_backups, &local_names, &store, false),
+            ] {
+                for backup in backups.iter() {
+                    if let Some(name) = backup.path.file_name()
+                        && !others.contains(name)
+                    {
+                        copies.push((backup.path.clone(), destination.join(name), upload));
+                    }
+                }
+            }
+
+            let total = copies.len() as u64;
+            let mut sync = SaveSync::default();
+            for (current, (source, destination, upload)) in copies.into_iter().enumerate() {
+                if cancellation.is_cancelled() {
+                    return Err(Error::Cancelled);
+                }
+                progress.send_replace(Some(Progress::transferring(
+                    Stage::Syncing,
+                    Transfer {
+                        current: current as u64,
+                        total: Some(total),
+                    },
+                )));
+                copy_backup(&source, &destination).await?;
+                if upload {
+                    sync.uploaded += 1;
+                } else {
+                    sync.downloaded += 1;
+                }
+            }
+            Ok(sync)
+        })
+    }
+}
+
+/// Checks that a save key can name a directory of the archive store.
+pub(super) fn validate_key(key: &str) -> Result<()> {
+    if key.trim().is_empty() || key.starts_with('.') || key.contains(['/', '\\', '\0']) {
+        return Err(BottleError::InvalidSaveKey(key.to_owned()).into());
+    }
+    Ok(())
+}
+
+/// Archives `saves`, relative to `profile`, as a new backup stored under
+/// `key` in `store`.
+async fn store_backup(
+    store: &Path,
+    key: String,
+    profile: &Path,
+    saves: &[PathBuf],
+) -> Result<SaveBackup> {
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let store = store.join(&key);
+    async_fs::create_dir_all(&store).await?;
+    let names = saves
+        .iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    let sources = saves
+        .iter()
+        .map(|path| profile.join(path))
+        .collect::<Vec<_>>();
+    let members = names
+        .iter()
+        .map(String::as_str)
+        .zip(sources.iter().map(PathBuf::as_path))
+        .collect::<Vec<_>>();
+    let partial = store.join(format!(".{created}{EXTENSION}"));
+    let path = store.join(format!("{created}{EXTENSION}"));
+    if let Err(error) = archive::pack(&partial, &members).await {
+        let _ = async_fs::remove_file(&partial).await;
+        return Err(error.into());
+    }
+    async_fs::rename(&partial, &path).await?;
+    Ok(SaveBackup {
+        key,
+        created: UNIX_EPOCH + Duration::from_secs(created),
+        path,
+    })
+}
+
+/// Splits a save-game rule into components relative to the user profile.
+pub(super) fn parse_rule(rule: &str) -> Result<Vec<String>> {
+    let invalid = || BottleError::InvalidSavePath(rule.to_owned());
+    let mut parts = rule.split(['/', '\\']).peekable();
+    let mut components = Vec::new();
+    if let Some(first) = parts.next_if(|part| part.len() > 1 && part.starts_with('%')) {
+        let folder: &[&str] = match first.to_ascii_uppercase().as_str() {
+            "%USERPROFILE%" => &[],
+            "%APPDATA%" => &["AppData", "Roaming"],
+            "%LOCALAPPDATA%" => &["AppData", "Local"],
+            _ => return Err(invalid().into()),
+        };
+        components.extend(folder.iter().map(|part| (*part).to_owned()));
+    }
+    let start = components.len();
+    for (index, part) in parts.enumerate() {
+        match part {
+            "" if index == 0 && start == 0 => return Err(invalid().into()),
+            "" | "." => {}
+            ".." => return Err(invalid().into()),
+            part if part.contains(['\0', ':', '%']) => return Err(invalid().into()),
+            part => components.push(part.to_owned()),
+        }
+    }
+    if components.len() == start {
+        return Err(invalid().into());
+    }
+    Ok(components)
+}
+
+/// Returns the name of the profile below `users` that save-game rules
+/// resolve against.
+async fn user_profile(users: &Path) -> Result<String> {
+    let mut names = Vec::new();
+    let mut entries = match async_fs::read_dir(users).await {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            return Err(BottleError::NoUserProfile.into());
+        }
+        Err(error) => return Err(error.into()),
+    };
+    while let Some(entry) = entries.try_next().await? {
+        if let Ok(name) = entry.file_name().into_string()
+            && !name.eq_ignore_ascii_case("Public")
+            && entry.metadata().await?.is_dir()
+        {
+            names.push(name);
+        }
+    }
+    names.sort();
+    let preferred = [env::var("USER").ok(), Some("steamuser".to_owned())];
+    preferred
+        .into_iter()
+        .flatten()
+        .find(|name| names.contains(name))
+        .or_else(|| names.into_iter().next())
+        .ok_or_else(|| BottleError::NoUserProfile.into())
+}
+
+/// Returns the paths below `profile` that `rules` match, relative to it,
+/// sorted and without paths below another match.
+async fn locate(profile: &Path, rules: &[String]) -> Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    for rule in rules {
+        let mut matched = vec![PathBuf::new()];
+        for pattern in parse_rule(rule)? {
+            let mut next = Vec::new();
+            for relative in matched {
+                let mut entries = match async_fs::read_dir(profile.join(&relative)).await {
+                    Ok(entries) => entries,
+                    Err(error)
+                        if matches!(
+                            error.kind(),
+                            io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+                        ) =>
+                    {
+                        continue;
+                    }
+                    Err(error) => return Err(error.into()),
+                };
+                while let Some(entry) = entries.try_next().await? {
+                    if let Ok(name) = entry.file_name().into_string()
+                        && wildcard_match(&pattern, &name)
+                    {
+                        next.push(relative.join(name));
+                    }
+                }
+            }
+            matched = next;
+        }
+        found.extend(matched);
+    }
+    found.sort();
+    let mut saves: Vec<PathBuf> = Vec::new();
+    for path in found {
+        if !saves.last().is_some_and(|last| path.starts_with(last)) {
+            saves.push(path);
+        }
+    }
+    Ok(saves)
+}
+
+/// Matches a name against a pattern with `*` and `?` wildcards, ignoring
+/// ASCII case.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut p, mut n) = (0, 0);
+    let mut star = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p].eq_ignore_ascii_case(&name[n])) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Lists the backups stored under `key` in `dir` newest-first, or nothing if
+/// it is missing.
+async fn backups(dir: &Path, key: &str) -> Result<Vec<SaveBackup>> {
+    let mut entries = match async_fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error.into()),
+    };
+    let mut backups = Vec::new();
+    while let Some(entry) = entries.try_next().await? {
+        let Some(created) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_suffix(EXTENSION))
+            .and_then(|secs| secs.parse().ok())
+        else {
+            continue;
+        };
+        backups.push(SaveBackup {
+            key: key.to_owned(),
+            created: UNIX_EPOCH + Duration::from_secs(created),
+            path: entry.path(),
+        });
+    }
+    backups.sort_by_key(|backup| Reverse(backup.created));
+    Ok(backups)
+}
+
+/// Copies a backup next to `destination` first, so a partial copy never
+/// appears as a backup.
+async fn copy_backup(source: &Path, destination: &Path) -> Result<()> {
+    let Some(dir) = destination.parent() else {
+        return Ok(());
+    };
+    async_fs::create_dir_all(dir).await?;
+    let partial = dir.join(format!(".{}.partial", Uuid::new_v4()));
+    if let Err(error) = async_fs::copy(source, &partial).await {
+        let _ = async_fs::remove_file(&partial).await;
+        return Err(error.into());
+    }
+    async_fs::rename(&partial, destination).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_resolve_below_the_profile() {
+        assert_eq!(
+            parse_rule(r"%AppData%\Game/Saves\").unwrap(),
+            ["AppData", "Roaming", "Game", "Saves"]
+        );
+        assert_eq!(
+            parse_rule("Documents/My Games/Game/*.sav").unwrap(),
+            ["Documents", "My Games", "Game", "*.sav"]
+        );
+        for invalid in [
+            "",
+            "%APPDATA%",
+            "/home/user",
+            r"C:\Saves",
+            "%WINDIR%/Saves",
+            "../Saves",
+        ] {
+            assert!(parse_rule(invalid).is_err(), "{invalid}");
+        }
+
+        assert!(wildcard_match("*.SAV", "slot1.sav"));
+        assert!(wildcard_match("slot?.*", "Slot2.bak"));
+        assert!(!wildcard_match("*.sav", "slot1.sav.bak"));
+    }
+
+    #[test]
+    fn rules_match_entries_once() {
+        let profile = std::env::temp_dir().join(format!("bottles-next-{}", Uuid::new_v4()));
+        let saves = profile.join("AppData/Roaming/Game/Saves");
+        std::fs::create_dir_all(&saves).unwrap();
+        std::fs::write(saves.join("slot1.sav"), "1").unwrap();
+        std::fs::write(saves.join("options.ini"), "").unwrap();
+
+        let rules = [
+            "%APPDATA%/game/*.sav".to_owned(),
+            "%APPDATA%/Game".to_owned(),
+            "%APPDATA%/Game/Saves/*.sav".to_owned(),
+            "Documents/Game".to_owned(),
+        ];
+        let (all, slots) = futures_lite::future::block_on(async {
+            (
+                locate(&profile, &rules).await.unwrap(),
+                locate(&profile, &rules[2..]).await.unwrap(),
+            )
+        });
+        std::fs::remove_dir_all(&profile).unwrap();
+
+        assert_eq!(all, [PathBuf::from("AppData/Roaming/Game")]);
+        assert_eq!(
+            slots,
+            [PathBuf::from("AppData/Roaming/Game/Saves/slot1.sav")]
+        );
+    }
+
+    #[test]
+    fn backups_restore_into_another_profile() {
+        futures_lite::future::block_on(async {
+            let root = std::env::temp_dir().join(format!("bottles-next-{}", Uuid::new_v4()));
+            let (first, second, store) = (
+                root.join("steamuser"),
+                root.join("user"),
+                root.join("saves"),
+            );
+            let saves = "AppData/Roaming/Game/Saves";
+            std::fs::create_dir_all(first.join(saves)).unwrap();
+            std::fs::create_dir_all(second.join(saves)).unwrap();
+            std::fs::write(first.join(saves).join("slot1.sav"), "progress").unwrap();
+            std::fs::write(second.join(saves).join("slot1.sav"), "old").unwrap();
+            std::fs::write(second.join(saves).join("slot2.sav"), "kept").unwrap();
+
+            let located = locate(&first, &["%APPDATA%/Game/Saves".to_owned()])
+                .await
+                .unwrap();
+            let backup = store_backup(&store, "game".to_owned(), &first, &located)
+                .await
+                .unwrap();
+            assert_eq!(
+                backups(&store.join("game"), "game").await.unwrap(),
+                [backup.clone()]
+            );
+            archive::extract(&backup.path, &second).await.unwrap();
+
+            let read = |name: &str| std::fs::read_to_string(second.join(saves).join(name)).unwrap();
+            assert_eq!(read("slot1.sav"), "progress");
+            assert_eq!(read("slot2.sav"), "kept");
+            assert!(validate_key("..").is_err());
+            assert!(validate_key("games/game").is_err());
+            std::fs::remove_dir_all(root).unwrap();
+        });
+    }
+}
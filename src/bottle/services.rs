@@ -0,0 +1,217 @@
+//! Windows service management on [`Bottle`] through WineBridge.
+
+use crate::{
+    error::{Error, Result},
+    proto::{RegistryHive, Service, ServiceStartType, registry_value::Value as RegistryValue},
+};
+
+use super::{error::BottleError, registry::not_found_as_none, state::Bottle};
+
+/// Registry key under `HKLM` holding one subkey per installed service.
+const SERVICES_KEY: &str = r"System\CurrentControlSet\Services";
+
+impl Bottle {
+    /// Lists Windows services registered in the bottle.
+    ///
+    /// This starts WineBridge if necessary, and no ordering guarantee is made.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bottle was deleted, its prefix cannot be
+    /// prepared, WineBridge cannot start, or the request fails.
+    pub async fn services(&self) -> Result<Vec<Service>> {
+        self.with_bridge(|bridge| async move { bridge.list_services().await })
+            .await
+    }
+
+    /// Returns the service named `name`, or `None` when it is not registered.
+    ///
+    /// Service names are matched by WineBridge using Windows' case-insensitive
+    /// rules.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the prefix or bridge operation fails for a reason
+    /// other than a missing service.
+    pub async fn service(&self, name: impl Into<String>) -> Result<Option<Service>> {
+        let name = name.into();
+        self.with_bridge(
+            move |bridge| async move { not_found_as_none(bridge.get_service(name).await) },
+        )
+        .await
+    }
+
+    /// Starts a registered service.
+    ///
+    /// The service keeps running until it stops itself, is stopped with
+    /// [`stop_service`](Self::stop_service), or the bottle is stopped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::ServiceNotFound`] if no such service is
+    /// registered. Start failures reported by the service control manager are
+    /// returned as [`Error::Status`]; prefix and bridge failures are also
+    /// returned.
+    pub async fn start_service(&self, name: impl Into<String>) -> Result<()> {
+        let name = name.into();
+        self.with_bridge(move |bridge| async move {
+            not_found_as_service(bridge.start_service(name.clone()).await, name)
+        })
+        .await
+    }
+
+    /// Stops a running service.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::ServiceNotFound`] if no such service is
+    /// registered. Stop failures reported by the service control manager are
+    /// returned as [`Error::Status`]; prefix and bridge failures are also
+    /// returned.
+    pub async fn stop_service(&self, name: impl Into<String>) -> Result<()> {
+        let name = name.into();
+        self.with_bridge(move |bridge| async move {
+            not_found_as_service(bridge.stop_service(name.clone()).await, name)
+        })
+        .await
+    }
+
+    /// Registers a new service that runs `binary_path`.
+    ///
+    /// `binary_path` is a Windows command line, including any quoting the
+    /// service control manager requires. Registering a service does not start
+    /// it, even with [`ServiceStartType::Automatic`]; automatic services start
+    /// on the next prefix boot.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::ServiceStartTypeRequired`] for
+    /// [`ServiceStartType::Unspecified`]. Duplicate or invalid services are
+    /// rejected by WineBridge as [`Error::Status`]. Prefix and bridge failures
+    /// are also returned.
+    pub async fn create_service(
+        &self,
+        name: impl Into<String>,
+        display_name: impl Into<String>,
+        binary_path: impl Into<String>,
+        start_type: ServiceStartType,
+    ) -> Result<()> {
+        if start_type == ServiceStartType::Unspecified {
+            return Err(BottleError::ServiceStartTypeRequired.into());
+        }
+        let name = name.into();
+        let display_name = display_name.into();
+        let binary_path = binary_path.into();
+        self.with_bridge(move |bridge| async move {
+            bridge
+                .create_service(name, display_name, binary_path, start_type)
+                .await
+        })
+        .await
+    }
+
+    /// Deletes a service registration.
+    ///
+    /// Deleting a service that is not registered succeeds. A running service
+    /// is marked for deletion and removed once it stops.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the prefix or bridge operation fails.
+    pub async fn delete_service(&self, name: impl Into<String>) -> Result<()> {
+        let name = name.into();
+        self.with_bridge(move |bridge| async move {
+            not_found_as_none(bridge.delete_service(name).await).map(drop)
+        })
+        .await
+    }
+
+    /// Changes when a registered service starts.
+    ///
+    /// The change is written to the service's registry configuration and takes
+    /// effect on the next prefix boot; a running service is not stopped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::ServiceStartTypeRequired`] for
+    /// [`ServiceStartType::Unspecified`] and [`BottleError::ServiceNotFound`]
+    /// if no such service is registered. Prefix and bridge failures are also
+    /// returned.
+    pub async fn set_service_start_type(
+        &self,
+        name: impl Into<String>,
+        start_type: ServiceStartType,
+    ) -> Result<()> {
+        let start = start_value(start_type)?;
+        let name = name.into();
+        self.with_bridge(move |bridge| async move {
+            let service = not_found_as_service(bridge.get_service(name.clone()).await, name)?;
+            bridge
+                .set_registry_value(
+                    RegistryHive::LocalMachine,
+                    format!(r"{SERVICES_KEY}\{}", service.name),
+                    "Start",
+                    RegistryValue::DwordValue(start),
+                )
+                .await
+        })
+        .await
+    }
+}
+
+/// Returns the `Start` registry value the service control manager uses for
+/// `start_type`.
+fn start_value(start_type: ServiceStartType) -> Result<u32> {
+    Ok(match start_type {
+        ServiceStartType::Unspecified => {
+            return Err(BottleError::ServiceStartTypeRequired.into());
+        }
+        ServiceStartType::Boot => 0,
+        ServiceStartType::System => 1,
+        ServiceStartType::Automatic => 2,
+        ServiceStartType::Manual => 3,
+        ServiceStartType::Disabled => 4,
+    })
+}
+
+/// Reports WineBridge's `NOT_FOUND` status as a missing service.
+fn not_found_as_service<T>(result: Result<T>, name: String) -> Result<T> {
+    match result {
+        Err(Error::Status(status)) if status.code() == tonic::Code::NotFound => {
+            Err(BottleError::ServiceNotFound(name).into())
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_types_map_to_registry_values() {
+        assert_eq!(start_value(ServiceStartType::Automatic).unwrap(), 2);
+        assert_eq!(start_value(ServiceStartType::Disabled).unwrap(), 4);
+        assert!(matches!(
+            start_value(ServiceStartType::Unspecified),
+            Err(Error::Bottle(BottleError::ServiceStartTypeRequired))
+        ));
+    }
+
+    #[test]
+    fn only_not_found_statuses_become_missing_services() {
+        let missing = not_found_as_service::<()>(
+            Err(tonic::Status::not_found("no service").into()),
+            "Spooler".into(),
+        );
+        assert!(matches!(
+            missing,
+            Err(Error::Bottle(BottleError::ServiceNotFound(name))) if name == "Spooler"
+        ));
+        let denied = not_found_as_service::<()>(
+            Err(tonic::Status::permission_denied("access denied").into()),
+            "Spooler".into(),
+        );
+        assert!(matches!(denied, Err(Error::Status(_))));
+    }
+}
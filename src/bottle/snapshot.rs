@@ -7,10 +7,14 @@ use fvs_rs::{Repository, RestoreResponse};
 use crate::{
     Operation, Progress, Stage, Transfer,
     error::{Error, Result},
-    prefix::{AUTO_CHECKPOINT_MESSAGE, FVS_BLOCK_SIZE, finish_commit, finish_restore},
+    prefix::{
+        AUTO_CHECKPOINT_MESSAGE, FVS_BLOCK_SIZE, finish_commit, finish_restore, resolve_snapshot,
+    },
 };
 
-use super::{Bottle, Snapshot, SnapshotSummary, error::BottleError, state::BottleState};
+use super::{
+    Bottle, Snapshot, SnapshotSummary, error::BottleError, state::BottleState, tags::SnapshotTags,
+};
 
 impl Bottle {
     /// Saves the bottle's current files and configuration in snapshot history.
@@ -62,7 +66,10 @@ impl Bottle {
     ///
     /// Results are newest-first. Every commit whose message is exactly
     /// `bottles-next:auto-checkpoint` is excluded because that value is reserved
-    /// for internal mutation checkpoints.
+    /// for internal mutation checkpoints. Automatic backups taken under a
+    /// [`BackupPolicy`](crate::BackupPolicy) are included.
+    /// Use [`find_snapshots`](Self::find_snapshots) to select snapshots by
+    /// tag or creation time.
     ///
     /// Listing holds shared bottle access: WineBridge requests may continue,
     /// while edits, stop, snapshot mutation, and deletion wait.
@@ -92,7 +99,8 @@ impl Bottle {
     /// The operation takes exclusive bottle access. It stops the bottle, then
     /// replaces the complete bottle tree with the target;
     /// files absent from that snapshot are removed. The state being replaced is
-    /// not saved automatically. On success, the returned string is the resolved
+    /// not saved automatically. Use [`restore_paths`](Self::restore_paths) to
+    /// restore only some files or registry keys. On success, the returned string is the resolved
     /// full state ID and the restored `bottle.toml` is published as a new
     /// [`BottleState`] snapshot.
     ///
@@ -152,6 +160,45 @@ impl Bottle {
         })
     }
 
+    /// Deletes a snapshot selected by full state ID or prefix from the
+    /// bottle's history and returns the resolved full state ID.
+    ///
+    /// The bottle's files are not changed, so the bottle keeps running. Its
+    /// tag is removed with it.
+    ///
+    /// # Errors
+    ///
+    /// The operation returns [`BottleError::PinnedSnapshot`] if the snapshot
+    /// is pinned, and an error if the bottle was deleted, the snapshot is
+    /// missing or ambiguous, the FVS service is unavailable, or the snapshot
+    /// cannot be deleted.
+    pub fn delete_snapshot(&self, state_id_or_prefix: &str) -> Operation<String> {
+        let bottle = self.clone();
+        let repository = self.snapshot_repository();
+        let bottle_path = self.bottle_path();
+        let cx = self.0.cx.clone();
+        let state_id_or_prefix = state_id_or_prefix.to_owned();
+        Operation::new(move |progress, _cancellation| async move {
+            let _write = bottle.0.write_lock.write().await;
+            bottle.ensure_exists()?;
+            let commit = resolve_snapshot(&bottle_path, &state_id_or_prefix, &cx).await?;
+            let path = bottle.snapshot_tags_path();
+            let mut tags = SnapshotTags::load(&path).await?;
+            if tags.is_pinned(&commit.state_id) {
+                return Err(BottleError::PinnedSnapshot(commit.state_id).into());
+            }
+            progress.send_replace(Some(Progress::new(Stage::Removing)));
+            cx.fvs()
+                .await?
+                .delete_commit(&repository, &commit.state_id)
+                .await?;
+            if tags.forget(&commit.state_id) {
+                tags.save(&path).await?;
+            }
+            Ok(commit.state_id)
+        })
+    }
+
     /// Addresses the history repository that every bottle owns independently
     /// of its prefix storage strategy.
     fn snapshot_repository(&self) -> Repository {
@@ -1,6 +1,10 @@
 //! Runtime, process, runner, and addon operations on [`Bottle`].
 
-use std::future::Future;
+use std::{
+    future::Future,
+    ops::AsyncFnOnce,
+    sync::{Arc, PoisonError},
+};
 
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
@@ -8,16 +12,22 @@ use uuid::Uuid;
 use crate::{
     Context, Operation, Progress, Stage,
     addons::{
-        Addon, Artifact, InstallInputs, Requirement, Slot, execute, replay_environment, uninstall,
+        Addon, Artifact, Dependency, InstallInputs, Requirement, Slot, execute, replay_environment,
+        uninstall,
     },
     error::{Error, Result},
     proto::{DllOverride, DllOverrideMode, Process},
-    runner::shutdown_prefix,
+    runner::{Runner, shutdown_prefix},
     winebridge::WineBridgeClient,
+    wrapper::Wrappers,
 };
 
+#[cfg(feature = "fvs")]
+use super::backup::{Trigger, back_up_if_due};
 use super::{
     error::BottleError,
+    logs,
+    run::{Launch, ProgramRun, RunArg},
     state::{Bottle, BottleState},
 };
 
@@ -92,34 +102,69 @@ impl Bottle {
         .await
     }
 
-    /// Launches a registered program and returns its Windows process ID.
+    /// Launches a registered program and returns a handle to the process.
     ///
     /// The program definition is copied before this call waits for shared
     /// bottle access. A concurrent edit therefore does not change or cancel
-    /// this launch. WineBridge starts on demand, and the returned ID identifies
-    /// the initially launched Windows process. Repeated launches with the same
-    /// program UUID share the process group targeted by
-    /// [`kill_program`](Self::kill_program).
+    /// this launch. WineBridge starts on demand and launches the program in
+    /// the process group keyed by its UUID. The program inherits the
+    /// WineBridge environment with its own variables, `WINEDEBUG` channels,
+    /// and DLL overrides applied over it. The program shares WineBridge's
+    /// wrappers: if this launch starts WineBridge, a Gamescope or MangoHud
+    /// configuration set on the program replaces the bottle's, and otherwise
+    /// it must match the running WineBridge's. Standard output, standard
+    /// error, and Wine's debug output are captured in a new log listed by
+    /// [`logs`](Self::logs); older logs are rotated away. The
+    /// returned handle can wait for the process to exit, and
+    /// [`kill_program`](Self::kill_program) terminates the program's process
+    /// group. Once the handle sees the process exit, the launch is added to
+    /// [`history`](Self::history) and counts towards the program's
+    /// [`playtimes`](Self::playtimes) entry; see [`ProgramRun`] for how the
+    /// exit is tracked.
+    ///
+    /// The program's [pre-launch hooks](crate::Program::with_pre_launch_hook)
+    /// run before the process is spawned and its
+    /// [post-exit hooks](crate::Program::with_post_exit_hook) after it exits.
+    /// Both see the launch environment and write to the launch log.
     ///
     /// # Errors
     ///
-    /// Returns [`BottleError::ProgramNotFound`] if `id` is not registered, or an
-    /// error if the prefix cannot be prepared, WineBridge cannot start, or the
-    /// process cannot be launched.
-    pub async fn launch_program(&self, id: Uuid) -> Result<u32> {
+    /// Returns [`BottleError::ProgramNotFound`] if `id` is not registered,
+    /// [`BottleError::UnmappedHostPath`] if no drive exposes the bottle's log
+    /// directory, [`BottleError::HookFailed`] if a pre-launch hook fails or
+    /// times out, [`BottleError::WrappersInUse`] if the program's wrappers
+    /// differ from the running WineBridge's, or an error if the prefix cannot
+    /// be prepared, WineBridge cannot start, or the process cannot be
+    /// launched.
+    pub async fn launch_program(&self, id: Uuid) -> Result<ProgramRun> {
         let program = self
             .state()?
             .program(id)
             .cloned()
             .ok_or(BottleError::ProgramNotFound(id))?;
-        let executable = program.executable().to_owned();
-        let arguments = program.args().to_vec();
-        let working_directory = program.working_directory().map(str::to_owned);
-        let new_console = program.new_console();
-        self.with_bridge(move |bridge| async move {
-            bridge
-                .launch_process(id, executable, arguments, working_directory, new_console)
-                .await
+        let mut environment = program.environment().clone();
+        if let Some(channels) = program.wine_debug() {
+            environment.insert("WINEDEBUG".to_owned(), channels.to_owned());
+        }
+        self.spawn_launch(Launch {
+            program: Some(id),
+            executable: RunArg::Windows(program.executable().to_owned()),
+            arguments: program
+                .args()
+                .iter()
+                .cloned()
+                .map(RunArg::Windows)
+                .collect(),
+            working_directory: program
+                .working_directory()
+                .map(|directory| RunArg::Windows(directory.to_owned())),
+            new_console: program.new_console(),
+            environment,
+            dll_overrides: program.dll_overrides().clone(),
+            gamescope: program.gamescope().cloned(),
+            mangohud: program.mangohud().cloned(),
+            pre_launch: program.pre_launch_hooks().to_vec(),
+            post_exit: program.post_exit_hooks().to_vec(),
         })
         .await
     }
@@ -142,7 +187,8 @@ impl Bottle {
     ///
     /// Every running process assigned to the UUID-keyed group is terminated.
     /// This starts WineBridge if necessary; if the program is registered but
-    /// has no running group members, the request succeeds.
+    /// has no running group members, the request succeeds. Wait on the
+    /// launches' [`ProgramRun`] handles to observe their exits.
     ///
     /// # Errors
     ///
@@ -163,8 +209,8 @@ impl Bottle {
     /// continues after a failure and the first error is returned. No
     /// configuration state is changed or published.
     ///
-    /// After a successful stop, the next bridge-backed operation applies the
-    /// latest environment and wrapper configuration.
+    /// Stopping wineserver also ends launched programs. After a successful
+    /// stop, the next bridge-backed operation applies the latest environment.
     pub async fn stop(&self) -> Result<()> {
         let _write = self.0.write_lock.write().await;
         let state = self.state()?;
@@ -222,6 +268,8 @@ impl Bottle {
                         if cancellation.is_cancelled() {
                             return Err(Error::Cancelled);
                         }
+                        #[cfg(feature = "fvs")]
+                        back_up_if_due(state, &cx, Trigger::AddonChange, Some(&progress)).await?;
                         let rebuild = component.slot() == Slot::Runner;
                         *state = candidate;
                         if rebuild {
@@ -285,55 +333,17 @@ impl Bottle {
                     let mut candidate = state.clone();
                     candidate.components.remove(&slot);
                     candidate.validate_requirements()?;
-                    let item_id = component.id();
                     let resources = vec![component.artifact(cx.directories())];
-                    let winebridge = state.winebridge().path(cx.directories());
-                    let prefix_progress = progress.clone();
-                    Self::stop_state(state, &cx).await?;
-                    if cancellation.is_cancelled() {
-                        return Err(Error::Cancelled);
-                    }
-                    *state = candidate;
-                    let runner = state
-                        .runner()
-                        .load_runner(cx.directories(), state.umu())
-                        .await?;
-                    let bottle_path = cx.directories().bottle(state.id);
-                    let context = cx.clone();
-                    let BottleState {
-                        storage,
-                        environment,
-                        ..
-                    } = state;
-                    storage
-                        .uninstall(
-                            &bottle_path,
-                            item_id,
-                            async |prefix, restore_files| {
-                                uninstall(
-                                    InstallInputs {
-                                        prefix,
-                                        runner: runner.as_ref(),
-                                        winebridge: &winebridge,
-                                        environment,
-                                    },
-                                    &resources,
-                                    restore_files,
-                                    item_id,
-                                    &cancellation,
-                                    move |_| {
-                                        progress.send_replace(Some(Progress::new(Stage::Removing)));
-                                    },
-                                )
-                                .await
-                            },
-                            &context,
-                            &cancellation,
-                            move |event| {
-                                prefix_progress.send_replace(Some(event));
-                            },
-                        )
-                        .await
+                    Self::remove_item(
+                        state,
+                        &cx,
+                        component.id(),
+                        resources,
+                        |state| *state = candidate,
+                        progress,
+                        &cancellation,
+                    )
+                    .await
                 })
                 .await
         })
@@ -342,8 +352,9 @@ impl Bottle {
     /// Permanently installs one downloaded dependency into this bottle.
     ///
     /// Reinstalling the same release is idempotent. Dependencies remain
-    /// recorded for the bottle's lifetime and cannot be uninstalled separately.
-    /// The current downloaded dependency with the supplied UUID is authoritative.
+    /// recorded until [`uninstall_dependency`](Self::uninstall_dependency)
+    /// removes them. The current downloaded dependency with the supplied UUID
+    /// is authoritative.
     pub fn install(&self, id: Uuid) -> Operation<()> {
         let bottle = self.clone();
         let addons = self.0.addons.clone();
@@ -387,6 +398,77 @@ impl Bottle {
         })
     }
 
+    /// Uninstalls one dependency from this bottle.
+    ///
+    /// The operation is rejected with [`BottleError::DependencyInUse`] when an
+    /// installed component or dependency has a requirement that only this
+    /// dependency satisfies. The recipe is reversed with the same best-effort
+    /// rules as [`remove_component`](Self::remove_component); it is taken from
+    /// the downloaded dependency when available; otherwise only the record and,
+    /// for Virgo storage, its cached layer are removed. With the default `fvs`
+    /// feature, a failed or cancelled removal restores a rollback checkpoint.
+    ///
+    /// # Errors
+    ///
+    /// The operation returns [`BottleError::DependencyNotInstalled`] if `id` is
+    /// not installed, as well as stop, prefix, and recipe failures.
+    pub fn uninstall_dependency(&self, id: Uuid) -> Operation<()> {
+        let bottle = self.clone();
+        let addons = self.0.addons.clone();
+        Operation::new(move |progress, cancellation| async move {
+            bottle
+                .update(async |state, cx| {
+                    let dependency = state
+                        .dependency(id)
+                        .cloned()
+                        .ok_or(BottleError::DependencyNotInstalled(id))?;
+                    let mut candidate = state.clone();
+                    candidate
+                        .dependencies
+                        .retain(|installed| installed.id() != id);
+                    let required_by = dependents(&candidate, &dependency);
+                    if !required_by.is_empty() {
+                        return Err(BottleError::DependencyInUse {
+                            dependency: id,
+                            required_by,
+                        }
+                        .into());
+                    }
+                    candidate.validate_requirements()?;
+                    let resources = match addons.dependency(id) {
+                        Some(entry) => entry
+                            .artifacts()
+                            .iter()
+                            .map(|artifact| {
+                                Artifact::new(
+                                    entry.path(cx.directories()).join(&artifact.path),
+                                    artifact.steps.clone(),
+                                )
+                            })
+                            .collect(),
+                        None => {
+                            tracing::warn!(
+                                %id,
+                                "dependency is not downloaded; its recipe will not be reversed"
+                            );
+                            Vec::new()
+                        }
+                    };
+                    Self::remove_item(
+                        state,
+                        &cx,
+                        id,
+                        resources,
+                        |state| *state = candidate,
+                        progress,
+                        &cancellation,
+                    )
+                    .await
+                })
+                .await
+        })
+    }
+
     #[allow(clippy::too_many_arguments)]
     /// Runs the shared, checkpointed addon mutation while the caller holds
     /// exclusive bottle access.
@@ -410,6 +492,8 @@ impl Bottle {
         F: FnOnce(&mut BottleState),
     {
         Self::stop_state(state, cx).await?;
+        #[cfg(feature = "fvs")]
+        back_up_if_due(state, cx, Trigger::AddonChange, Some(&progress)).await?;
         update_config(state);
         if cancellation.is_cancelled() {
             return Err(Error::Cancelled);
@@ -460,6 +544,74 @@ impl Bottle {
         Ok(())
     }
 
+    /// Runs the shared, checkpointed addon removal while the caller holds
+    /// exclusive bottle access.
+    ///
+    /// The draft configuration is updated before the recipe is reversed, so
+    /// the reversal's environment changes apply to the persisted draft.
+    async fn remove_item<F>(
+        state: &mut BottleState,
+        cx: &Context,
+        item_id: Uuid,
+        resources: Vec<Artifact>,
+        update_config: F,
+        progress: tokio::sync::watch::Sender<Option<Progress>>,
+        cancellation: &CancellationToken,
+    ) -> Result<()>
+    where
+        F: FnOnce(&mut BottleState),
+    {
+        let winebridge = state.winebridge().path(cx.directories());
+        let prefix_progress = progress.clone();
+        Self::stop_state(state, cx).await?;
+        #[cfg(feature = "fvs")]
+        back_up_if_due(state, cx, Trigger::AddonChange, Some(&progress)).await?;
+        if cancellation.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        update_config(state);
+        let runner = state
+            .runner()
+            .load_runner(cx.directories(), state.umu())
+            .await?;
+        let bottle_path = cx.directories().bottle(state.id);
+        let context = cx.clone();
+        let BottleState {
+            storage,
+            environment,
+            ..
+        } = state;
+        storage
+            .uninstall(
+                &bottle_path,
+                item_id,
+                async |prefix, restore_files| {
+                    uninstall(
+                        InstallInputs {
+                            prefix,
+                            runner: runner.as_ref(),
+                            winebridge: &winebridge,
+                            environment,
+                        },
+                        &resources,
+                        restore_files,
+                        item_id,
+                        cancellation,
+                        move |_| {
+                            progress.send_replace(Some(Progress::new(Stage::Removing)));
+                        },
+                    )
+                    .await
+                },
+                &context,
+                cancellation,
+                move |event| {
+                    prefix_progress.send_replace(Some(event));
+                },
+            )
+            .await
+    }
+
     /// Performs uncancellable lifecycle cleanup while exclusive bottle access
     /// prevents new bridge work.
     ///
@@ -507,13 +659,26 @@ impl Bottle {
     /// Holds shared bottle access while preparing the persisted prefix and
     /// performing one WineBridge request.
     ///
-    /// Environment and wrappers come from one published state snapshot, and
+    /// The environment comes from one published state snapshot, and
     /// WineBridge remains running afterward. Shared access permits concurrent
     /// requests but currently does not coalesce simultaneous first starts.
-    async fn with_bridge<T, F, Fut>(&self, work: F) -> Result<T>
+    pub(super) async fn with_bridge<T, F, Fut>(&self, work: F) -> Result<T>
     where
         F: FnOnce(WineBridgeClient) -> Fut,
         Fut: Future<Output = Result<T>>,
+    {
+        self.with_prefix(async move |state, runner| {
+            work(self.start_bridge(state, runner, &state.wrappers).await?).await
+        })
+        .await
+    }
+
+    /// Holds shared bottle access while preparing the persisted prefix for
+    /// `work`, which receives the state snapshot and runner it was prepared
+    /// with.
+    pub(super) async fn with_prefix<T, F>(&self, work: F) -> Result<T>
+    where
+        F: for<'a> AsyncFnOnce(&'a BottleState, &'a dyn Runner) -> Result<T>,
     {
         let _read = self.0.write_lock.read().await;
         let state = self.state()?;
@@ -521,19 +686,122 @@ impl Bottle {
             .runner()
             .load_runner(self.0.cx.directories(), state.umu())
             .await?;
-        let bottle_path = self.bottle_path();
+        state
+            .storage
+            .prepare(&self.bottle_path(), &state.drives, &self.0.cx)
+            .await?;
+        work(&state, runner.as_ref()).await
+    }
+
+    /// Connects to WineBridge, starting it with the bottle environment and
+    /// `wrappers` when it is not running.
+    ///
+    /// A started WineBridge writes its output to a new log listed by
+    /// [`bridge_logs`](Self::bridge_logs) and forwards its standard error to
+    /// the logs of running launches. Its wrappers are remembered for
+    /// [`bridge_wrappers`](Self::bridge_wrappers).
+    pub(super) async fn start_bridge(
+        &self,
+        state: &BottleState,
+        runner: &dyn Runner,
+        wrappers: &Wrappers,
+    ) -> Result<WineBridgeClient> {
         let prefix = self.prefix_path();
-        let storage = state.storage.clone();
-        let cx = self.0.cx.clone();
-        let command = state.wrappers.apply(
+        if let Some(bridge) = WineBridgeClient::try_connect(&prefix).await? {
+            return Ok(bridge);
+        }
+        let command = wrappers.apply(
             WineBridgeClient::command(
-                runner.as_ref(),
+                runner,
                 &prefix,
                 state.winebridge().path(self.0.cx.directories()),
             )
             .envs(state.environment.iter()),
         );
-        storage.prepare(&bottle_path, &cx).await?;
-        work(WineBridgeClient::connect_or_spawn(&prefix, command).await?).await
+        let (log, _) = logs::create(&self.log_dir(logs::BRIDGE)).await?;
+        let bottle = Arc::downgrade(&self.0);
+        let forward = move |line: &[u8]| {
+            if let Some(inner) = bottle.upgrade() {
+                logs::forward_to_launches(&inner, line);
+            }
+        };
+        let bridge = WineBridgeClient::spawn_logged(&prefix, command, log, forward).await?;
+        *self
+            .0
+            .bridge_wrappers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(wrappers.clone());
+        Ok(bridge)
+    }
+
+    /// Returns the wrappers of the running WineBridge, assuming the bottle's
+    /// own when this handle did not start it.
+    pub(super) fn bridge_wrappers(&self, state: &BottleState) -> Wrappers {
+        self.0
+            .bridge_wrappers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .unwrap_or_else(|| state.wrappers.clone())
+    }
+}
+
+/// Returns the addons left in `candidate` with a requirement that only the
+/// removed `dependency` satisfied.
+fn dependents(candidate: &BottleState, dependency: &Addon<Dependency>) -> Vec<Uuid> {
+    candidate
+        .components
+        .values()
+        .map(|addon| (addon.id(), addon.requirements()))
+        .chain(
+            candidate
+                .dependencies
+                .iter()
+                .map(|addon| (addon.id(), addon.requirements())),
+        )
+        .filter(|(_, requirements)| {
+            requirements.iter().any(|requirement| {
+                dependency.satisfies(requirement) && !candidate.contains_addon_matching(requirement)
+            })
+        })
+        .map(|(owner, _)| owner)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dependents_ignore_requirements_still_satisfied() {
+        let (removed, app, other) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let dependency =
+            |id, name: &str| serde_json::json!({ "id": id, "name": name, "version": "1" });
+        let mut state: BottleState = serde_json::from_value(serde_json::json!({
+            "id": Uuid::new_v4(),
+            "name": "Gaming",
+            "storage": { "kind": "Standard" },
+            "components": {},
+            "dependencies": [
+                dependency(removed, "vcrun"),
+                {
+                    "id": app,
+                    "name": "app",
+                    "version": "1",
+                    "requirements": [{ "name": "vcrun" }],
+                },
+            ],
+        }))
+        .unwrap();
+        let vcrun = state.dependency(removed).cloned().unwrap();
+        state
+            .dependencies
+            .retain(|installed| installed.id() != removed);
+        assert_eq!(dependents(&state, &vcrun), [app]);
+
+        state
+            .dependencies
+            .push(serde_json::from_value(dependency(other, "vcrun")).unwrap());
+        assert!(dependents(&state, &vcrun).is_empty());
     }
 }
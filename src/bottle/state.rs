@@ -1,22 +1,39 @@
 //! Persisted bottle state and the shared bottle handle.
 
-use std::{collections::HashMap, ops::AsyncFnOnce, path::PathBuf, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    ops::AsyncFnOnce,
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+};
 
 use futures_core::Stream;
 use next_config::Config;
 use serde::{Deserialize, Serialize};
-use tokio::sync::{RwLock, watch};
+use tokio::sync::{RwLock, broadcast, watch};
 use tokio_stream::{StreamExt, wrappers::WatchStream};
 use uuid::Uuid;
 
-use super::{edit::BottleEdit, error::BottleError};
+#[cfg(feature = "fvs")]
+use super::backup::BackupPolicy;
+use super::{
+    drives::DriveMapping,
+    edit::{BottleEdit, validate_env},
+    error::BottleError,
+    history::Playtime,
+    hooks::Hook,
+    run::{self, ProcessEvent, ProgramRun},
+    saves,
+};
 use crate::{
     Context,
     addons::{Addon, Addons, Component, Dependency, Requirement, Slot},
     error::Result,
     prefix::Prefix,
+    proto::DllOverrideMode,
     utils::environment::Environment,
-    wrapper::Wrappers,
+    wrapper::{Wrappers, gamescope::GamescopeConfig, mangohud::MangoHudConfig},
 };
 
 /// An immutable snapshot of a bottle's published configuration.
@@ -41,6 +58,13 @@ pub struct BottleState {
     pub(crate) dependencies: Vec<Addon<Dependency>>,
     #[serde(default, skip_serializing_if = "Environment::is_empty")]
     pub(crate) environment: Environment,
+    /// DOS drive links re-applied whenever the prefix is prepared, sorted by letter.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) drives: Vec<DriveMapping>,
+    /// Automatic backup policy; `None` takes no automatic backups.
+    #[cfg(feature = "fvs")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) backup: Option<BackupPolicy>,
 
     #[serde(flatten)]
     pub(crate) wrappers: Wrappers,
@@ -160,24 +184,48 @@ impl BottleState {
         Ok(())
     }
 
-    /// Returns environment variables supplied when WineBridge is started.
+    /// Returns environment variables supplied to launched programs and to
+    /// WineBridge when it starts.
     ///
-    /// Changes do not affect an already-running WineBridge. Call
-    /// [`Bottle::stop`] before the next bridge-backed operation to apply them
-    /// immediately.
+    /// Changes apply to the next launch. An already-running WineBridge keeps
+    /// its environment; call [`Bottle::stop`] before the next bridge-backed
+    /// operation to apply them there immediately.
     pub fn environment(&self) -> &Environment {
         &self.environment
     }
 
     /// Returns the wrapper configuration applied when WineBridge is started.
     ///
-    /// Changes do not affect an already-running WineBridge. Call
+    /// Launched programs run inside WineBridge and share its wrappers, so
+    /// changes do not affect an already-running WineBridge. Call
     /// [`Bottle::stop`] before the next bridge-backed operation to apply them
     /// immediately.
     pub fn wrappers(&self) -> &Wrappers {
         &self.wrappers
     }
 
+    /// Returns persisted drive mappings sorted by letter.
+    ///
+    /// Mappings are applied whenever the prefix is prepared, so they survive
+    /// Virgo remounts and are restored if the link is changed outside Bottles.
+    /// Drives that Wine created itself are not listed; use [`Bottle::drives`]
+    /// for the prefix's live drive table.
+    pub fn drive_mappings(&self) -> &[DriveMapping] {
+        &self.drives
+    }
+
+    /// Returns the persisted mapping for `letter`, matched case-insensitively.
+    pub fn drive_mapping(&self, letter: char) -> Option<&DriveMapping> {
+        let letter = letter.to_ascii_lowercase();
+        self.drives.iter().find(|drive| drive.letter() == letter)
+    }
+
+    /// Returns the automatic backup policy, if the bottle has one.
+    #[cfg(feature = "fvs")]
+    pub fn backup_policy(&self) -> Option<&BackupPolicy> {
+        self.backup.as_ref()
+    }
+
     /// Iterates over registered programs in unspecified order.
     pub fn programs(&self) -> impl Iterator<Item = &Program> {
         self.programs.values()
@@ -188,6 +236,12 @@ impl BottleState {
         self.programs.get(&id)
     }
 
+    fn apply_playtimes(&mut self, playtimes: &HashMap<Uuid, Playtime>) {
+        for (id, program) in &mut self.programs {
+            program.playtime = playtimes.get(id).copied().unwrap_or_default();
+        }
+    }
+
     /// Reports how the Wine prefix itself is stored.
     ///
     /// With the default `fvs` feature, both strategies use FVS for snapshot
@@ -209,6 +263,19 @@ pub(crate) struct BottleInner {
     pub(crate) cx: Context,
     /// Shared addon registry scoped to the owning manager.
     pub(crate) addons: Addons,
+    /// Launches that have not finished exiting yet.
+    pub(crate) launches: Mutex<Vec<ProgramRun>>,
+    /// Logs of tracked launches by PID, which receive WineBridge's standard
+    /// error while they run.
+    pub(crate) launch_output: Mutex<HashMap<u32, File>>,
+    /// Launch starts and exits reported by [`Bottle::watch_processes`].
+    pub(crate) process_events: broadcast::Sender<ProcessEvent>,
+    /// Serializes appends to and rotation of the launch history.
+    pub(crate) history: tokio::sync::Mutex<()>,
+    /// Wrappers of the WineBridge this handle last started.
+    pub(crate) bridge_wrappers: Mutex<Option<Wrappers>>,
+    /// Playtime by program, applied to every published state.
+    pub(crate) playtimes: Mutex<HashMap<Uuid, Playtime>>,
 }
 
 /// A live, shared handle to one bottle.
@@ -245,6 +312,9 @@ impl Bottle {
             programs: HashMap::new(),
             wrappers: Wrappers::default(),
             environment: Environment::default(),
+            drives: Vec::new(),
+            #[cfg(feature = "fvs")]
+            backup: None,
         };
         let bottle = Self::from_state(state, context, addons)?;
         bottle.save().await?;
@@ -256,12 +326,19 @@ impl Bottle {
         state.validate_requirements()?;
         let id = state.id;
         let (published, _) = watch::channel(Some(Arc::new(state)));
+        let (process_events, _) = broadcast::channel(run::EVENTS_CAPACITY);
         Ok(Self(Arc::new(BottleInner {
             id,
             published,
             write_lock: RwLock::new(()),
             cx,
             addons,
+            launches: Mutex::new(Vec::new()),
+            launch_output: Mutex::new(HashMap::new()),
+            process_events,
+            history: tokio::sync::Mutex::new(()),
+            bridge_wrappers: Mutex::new(None),
+            playtimes: Mutex::new(HashMap::new()),
         })))
     }
 
@@ -340,7 +417,13 @@ impl Bottle {
 
     /// Publishes only observable state changes; an equal state does not wake
     /// watchers.
-    pub(crate) fn publish(&self, state: BottleState) {
+    pub(crate) fn publish(&self, mut state: BottleState) {
+        let playtimes = self
+            .0
+            .playtimes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        state.apply_playtimes(&playtimes);
         let next = Arc::new(state);
         self.0.published.send_if_modified(|published| {
             if published.as_deref() == Some(next.as_ref()) {
@@ -352,6 +435,30 @@ impl Bottle {
         });
     }
 
+    /// Replaces the playtime applied to published programs and republishes
+    /// the latest state with it.
+    pub(super) fn publish_playtimes(&self, playtimes: HashMap<Uuid, Playtime>) {
+        let mut applied = self
+            .0
+            .playtimes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        *applied = playtimes;
+        self.0.published.send_if_modified(|published| {
+            let Some(current) = published else {
+                return false;
+            };
+            let mut next = current.as_ref().clone();
+            next.apply_playtimes(&applied);
+            if next == **current {
+                false
+            } else {
+                *current = Arc::new(next);
+                true
+            }
+        });
+    }
+
     pub(crate) fn bottle_path(&self) -> PathBuf {
         self.0.cx.directories().bottle(self.0.id)
     }
@@ -374,14 +481,14 @@ impl Bottle {
         Self::save_state(&state, &self.0.cx).await
     }
 
-    async fn save_state(state: &BottleState, cx: &Context) -> Result<()> {
+    pub(super) async fn save_state(state: &BottleState, cx: &Context) -> Result<()> {
         let path = cx.directories().bottle(state.id).join("bottle.toml");
         next_config::save(path, state).await?;
         Ok(())
     }
 }
 
-/// A persisted, immutable Windows launch definition registered with a bottle.
+/// A persisted Windows launch definition registered with a bottle.
 #[derive(Debug, Clone, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Program {
@@ -395,12 +502,42 @@ pub struct Program {
     /// does not produce an empty Windows argument.
     #[serde(default)]
     args: Vec<String>,
-    /// Windows working directory, or WineBridge's inherited directory when absent.
+    /// Windows working directory, or the runner's inherited directory when absent.
     #[serde(default)]
     working_directory: Option<String>,
-    /// Passed to WineBridge's `CREATE_NEW_CONSOLE` launch option.
+    /// Launches through Wine's `start /wait` to open a new console.
     #[serde(default)]
     new_console: bool,
+    /// `WINEDEBUG` channels for this program, overriding the bottle environment.
+    #[serde(default)]
+    wine_debug: Option<String>,
+    /// Variables applied over the bottle environment at launch.
+    #[serde(default, skip_serializing_if = "Environment::is_empty")]
+    environment: Environment,
+    /// Replaces the bottle's Gamescope configuration at launch.
+    #[serde(default)]
+    gamescope: Option<GamescopeConfig>,
+    /// Replaces the bottle's MangoHud configuration at launch.
+    #[serde(default)]
+    mangohud: Option<MangoHudConfig>,
+    /// Loading modes exported through `WINEDLLOVERRIDES` at launch.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    dll_overrides: BTreeMap<String, DllOverrideMode>,
+    /// Host commands run in order before each launch.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pre_launch: Vec<Hook>,
+    /// Host commands run in order after each launch exits.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    post_exit: Vec<Hook>,
+    /// Save-game rules added with [`Program::with_save_path`], in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    save_paths: Vec<String>,
+    /// Names the program's save backups; see [`Program::with_save_key`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    save_key: Option<String>,
+    /// Aggregated from the bottle's launch history rather than persisted.
+    #[serde(skip)]
+    playtime: Playtime,
 }
 
 impl Program {
@@ -421,9 +558,25 @@ impl Program {
             args: Vec::new(),
             working_directory: None,
             new_console: false,
+            wine_debug: None,
+            environment: Environment::default(),
+            gamescope: None,
+            mangohud: None,
+            dll_overrides: BTreeMap::new(),
+            pre_launch: Vec::new(),
+            post_exit: Vec::new(),
+            save_paths: Vec::new(),
+            save_key: None,
+            playtime: Playtime::default(),
         })
     }
 
+    /// Returns the same definition under a new UUID, as for a copied bottle.
+    pub(crate) fn with_new_id(mut self) -> Self {
+        self.id = Uuid::new_v4();
+        self
+    }
+
     /// Replaces the Windows command-line fragments passed at launch.
     pub fn with_args<I, S>(mut self, args: I) -> Self
     where
@@ -446,13 +599,149 @@ impl Program {
         Ok(self)
     }
 
-    /// Controls WineBridge's `CREATE_NEW_CONSOLE` launch option.
+    /// Controls whether launches open a new console through `start /wait`.
     pub fn with_new_console(mut self, new_console: bool) -> Self {
         self.new_console = new_console;
         self
     }
 
-    /// Returns the bottle-scoped identity used for lookup and process grouping.
+    /// Sets the Wine debug channels, such as `+relay,-heap` or `-all`, exported
+    /// as `WINEDEBUG` when the program launches.
+    ///
+    /// Wine writes the selected channels to the standard error of WineBridge,
+    /// which is copied into the launch's log; see [`crate::LaunchLog`].
+    pub fn with_wine_debug(mut self, channels: impl Into<String>) -> Result<Self> {
+        let channels = channels.into();
+        if channels.trim().is_empty() || channels.contains('\0') {
+            return Err(BottleError::InvalidProgram(
+                "Wine debug channels must not be blank or contain NUL".into(),
+            )
+            .into());
+        }
+        self.wine_debug = Some(channels);
+        Ok(self)
+    }
+
+    /// Sets an environment variable applied over the bottle environment when
+    /// the program launches.
+    ///
+    /// Names and values follow the rules of [`BottleEdit::set_env`]. A
+    /// `WINEDEBUG` set here is superseded by
+    /// [`with_wine_debug`](Self::with_wine_debug).
+    pub fn with_env(mut self, key: impl Into<String>, value: impl Into<String>) -> Result<Self> {
+        let (key, value) = (key.into(), value.into());
+        validate_env(&key, Some(&value))?;
+        self.environment.insert(key, value);
+        Ok(self)
+    }
+
+    /// Uses `config` instead of the bottle's Gamescope configuration, so a
+    /// disabled config turns Gamescope off for this program.
+    ///
+    /// Programs run inside WineBridge and share its wrappers, so the override
+    /// applies when a launch of this program starts WineBridge. Launching
+    /// while WineBridge runs with a different Gamescope configuration fails
+    /// with [`BottleError::WrappersInUse`].
+    pub fn with_gamescope(mut self, config: GamescopeConfig) -> Self {
+        self.gamescope = Some(config);
+        self
+    }
+
+    /// Uses `config` instead of the bottle's MangoHud configuration.
+    ///
+    /// As with [`with_gamescope`](Self::with_gamescope), the override applies
+    /// when a launch of this program starts WineBridge, and launching while it
+    /// runs with a different MangoHud configuration fails.
+    pub fn with_mangohud(mut self, config: MangoHudConfig) -> Self {
+        self.mangohud = Some(config);
+        self
+    }
+
+    /// Loads `dll` with `mode` for this program only.
+    ///
+    /// Modes are exported through `WINEDLLOVERRIDES`, which Wine applies
+    /// before the prefix registry, merged with any value from the bottle or
+    /// program environment. Names are matched case-insensitively by Wine.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::DllOverrideModeRequired`] for
+    /// [`DllOverrideMode::Unspecified`] and [`BottleError::InvalidDllName`]
+    /// for an empty name or one containing NUL, `,`, `;`, or `=`.
+    pub fn with_dll_override(
+        mut self,
+        dll: impl Into<String>,
+        mode: DllOverrideMode,
+    ) -> Result<Self> {
+        let dll = dll.into();
+        if mode == DllOverrideMode::Unspecified {
+            return Err(BottleError::DllOverrideModeRequired.into());
+        }
+        if dll.is_empty() || dll.contains(['\0', ',', ';', '=']) {
+            return Err(BottleError::InvalidDllName(dll).into());
+        }
+        self.dll_overrides.insert(dll, mode);
+        Ok(self)
+    }
+
+    /// Appends a hook run before each launch.
+    ///
+    /// Hooks run in the order they were added. If one fails or times out, the
+    /// launch is aborted with [`BottleError::HookFailed`] and later hooks do
+    /// not run.
+    pub fn with_pre_launch_hook(mut self, hook: Hook) -> Self {
+        self.pre_launch.push(hook);
+        self
+    }
+
+    /// Appends a hook run after each launch exits, including launches that
+    /// were killed.
+    ///
+    /// Hooks run in the order they were added, and all of them run even if
+    /// one fails; failures are logged because the launch has already ended.
+    pub fn with_post_exit_hook(mut self, hook: Hook) -> Self {
+        self.post_exit.push(hook);
+        self
+    }
+
+    /// Adds a rule locating the program's saved games, such as
+    /// `%APPDATA%/Game/Saves` or `Documents/My Games/Game/*.sav`.
+    ///
+    /// Rules are paths relative to the user profile, separated by `/` or
+    /// `\`, that may start with `%USERPROFILE%`, `%APPDATA%`, or
+    /// `%LOCALAPPDATA%`. Components may use `*` and `?` wildcards and match
+    /// names case-insensitively. See [`Bottle::locate_saves`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::InvalidSavePath`] for a rule that is empty,
+    /// absolute, leaves the profile, or uses another variable.
+    pub fn with_save_path(mut self, rule: impl Into<String>) -> Result<Self> {
+        let rule = rule.into();
+        saves::parse_rule(&rule)?;
+        self.save_paths.push(rule);
+        Ok(self)
+    }
+
+    /// Sets the key that names the program's save backups in the archive
+    /// store and in sync targets, such as `hollow-knight`.
+    ///
+    /// Program UUIDs differ between bottles, so a game moved to another
+    /// bottle finds its backups only under a key set on both programs.
+    /// Without a key, backups are named after the program's UUID.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::InvalidSaveKey`] for a key that is blank,
+    /// starts with `.`, or contains `/`, `\`, or NUL.
+    pub fn with_save_key(mut self, key: impl Into<String>) -> Result<Self> {
+        let key = key.into();
+        saves::validate_key(&key)?;
+        self.save_key = Some(key);
+        Ok(self)
+    }
+
+    /// Returns the bottle-scoped identity used for lookup, launches, and logs.
     pub fn id(&self) -> Uuid {
         self.id
     }
@@ -462,7 +751,7 @@ impl Program {
         &self.name
     }
 
-    /// Returns the Windows executable path passed to WineBridge.
+    /// Returns the Windows executable path passed to the runner.
     pub fn executable(&self) -> &str {
         &self.executable
     }
@@ -481,6 +770,61 @@ impl Program {
     pub fn new_console(&self) -> bool {
         self.new_console
     }
+
+    /// Returns the configured `WINEDEBUG` channels, when present.
+    pub fn wine_debug(&self) -> Option<&str> {
+        self.wine_debug.as_deref()
+    }
+
+    /// Returns variables applied over the bottle environment at launch.
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    /// Returns the Gamescope configuration replacing the bottle's, if any.
+    pub fn gamescope(&self) -> Option<&GamescopeConfig> {
+        self.gamescope.as_ref()
+    }
+
+    /// Returns the MangoHud configuration replacing the bottle's, if any.
+    pub fn mangohud(&self) -> Option<&MangoHudConfig> {
+        self.mangohud.as_ref()
+    }
+
+    /// Returns per-program DLL loading modes keyed by DLL name.
+    pub fn dll_overrides(&self) -> &BTreeMap<String, DllOverrideMode> {
+        &self.dll_overrides
+    }
+
+    /// Returns hooks run before each launch, in order.
+    pub fn pre_launch_hooks(&self) -> &[Hook] {
+        &self.pre_launch
+    }
+
+    /// Returns hooks run after each launch exits, in order.
+    pub fn post_exit_hooks(&self) -> &[Hook] {
+        &self.post_exit
+    }
+
+    /// Returns the save-game rules, in order.
+    pub fn save_paths(&self) -> &[String] {
+        &self.save_paths
+    }
+
+    /// Returns the key naming the program's save backups: the one set with
+    /// [`with_save_key`](Self::with_save_key), else the program's UUID.
+    pub fn save_key(&self) -> String {
+        self.save_key.clone().unwrap_or_else(|| self.id.to_string())
+    }
+
+    /// Returns the program's cumulative playtime and when it was last played.
+    ///
+    /// The value is taken from the bottle's launch history, as returned by
+    /// [`Bottle::playtimes`], and is updated as launches exit. A program that
+    /// has not completed a launch reports zero.
+    pub fn playtime(&self) -> Playtime {
+        self.playtime
+    }
 }
 
 /// The prefix-storage strategy persisted in [`BottleState`].
@@ -0,0 +1,265 @@
+//! Snapshot labels, pins, and queries.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use next_config::Config;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Result, prefix::resolve_snapshot};
+
+use super::{SnapshotSummary, state::Bottle};
+
+/// A label and pin attached to a snapshot.
+///
+/// Tags are kept outside the bottle directory, so rolling back or restoring
+/// from a snapshot does not change them.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotTag {
+    /// Label set with [`Bottle::tag_snapshot`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Whether [`Bottle::pin_snapshot`] protects the snapshot from
+    /// deletion and backup pruning.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+}
+
+/// Criteria for [`Bottle::find_snapshots`]. The default query matches every
+/// snapshot.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SnapshotQuery {
+    tag: Option<String>,
+    pinned: bool,
+    since: Option<SystemTime>,
+    until: Option<SystemTime>,
+}
+
+impl SnapshotQuery {
+    /// Matches only snapshots labeled exactly `label`.
+    pub fn with_tag(mut self, label: impl Into<String>) -> Self {
+        self.tag = Some(label.into());
+        self
+    }
+
+    /// Matches only pinned snapshots when `pinned` is true.
+    pub fn with_pinned(mut self, pinned: bool) -> Self {
+        self.pinned = pinned;
+        self
+    }
+
+    /// Matches only snapshots created at or after `time`.
+    pub fn with_since(mut self, time: SystemTime) -> Self {
+        self.since = Some(time);
+        self
+    }
+
+    /// Matches only snapshots created before `time`.
+    pub fn with_until(mut self, time: SystemTime) -> Self {
+        self.until = Some(time);
+        self
+    }
+
+    fn matches(&self, created: SystemTime, tag: Option<&SnapshotTag>) -> bool {
+        let label = tag.and_then(|tag| tag.label.as_deref());
+        self.tag
+            .as_deref()
+            .is_none_or(|wanted| label == Some(wanted))
+            && (!self.pinned || tag.is_some_and(|tag| tag.pinned))
+            && self.since.is_none_or(|since| created >= since)
+            && self.until.is_none_or(|until| created < until)
+    }
+}
+
+/// The tags of one bottle's snapshots by state ID, as persisted.
+#[derive(Debug, Default, Deserialize, Serialize, Config)]
+#[config(version = 1)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct SnapshotTags {
+    snapshots: BTreeMap<String, SnapshotTag>,
+}
+
+impl SnapshotTags {
+    /// Loads tags, treating a missing file as no tags.
+    pub(super) async fn load(path: &Path) -> Result<Self> {
+        match next_config::load::<Self>(path).await {
+            Ok(tags) => Ok(tags),
+            Err(next_config::error::Error::Io(error))
+                if error.kind() == std::io::ErrorKind::NotFound =>
+            {
+                Ok(Self::default())
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub(super) async fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            async_fs::create_dir_all(parent).await?;
+        }
+        next_config::save(path, self).await?;
+        Ok(())
+    }
+
+    pub(super) fn is_pinned(&self, state_id: &str) -> bool {
+        self.snapshots.get(state_id).is_some_and(|tag| tag.pinned)
+    }
+
+    /// Drops the tag of a deleted snapshot and returns whether it had one.
+    pub(super) fn forget(&mut self, state_id: &str) -> bool {
+        self.snapshots.remove(state_id).is_some()
+    }
+
+    /// Changes the tag of a snapshot, dropping it once it is empty.
+    fn update(&mut self, state_id: &str, change: impl FnOnce(&mut SnapshotTag)) {
+        let tag = self.snapshots.entry(state_id.to_owned()).or_default();
+        change(tag);
+        if *tag == SnapshotTag::default() {
+            self.snapshots.remove(state_id);
+        }
+    }
+}
+
+impl Bottle {
+    /// Returns the tags of the bottle's snapshots by state ID. Snapshots
+    /// that were never tagged or pinned are absent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bottle was deleted or its tags cannot be read.
+    pub async fn snapshot_tags(&self) -> Result<HashMap<String, SnapshotTag>> {
+        let _read = self.0.write_lock.read().await;
+        self.ensure_exists()?;
+        Ok(SnapshotTags::load(&self.snapshot_tags_path())
+            .await?
+            .snapshots
+            .into_iter()
+            .collect())
+    }
+
+    /// Lists the caller-visible snapshots that match `query`, newest-first.
+    ///
+    /// Snapshots are listed as [`snapshots`](Self::snapshots) lists them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bottle was deleted, the FVS service is
+    /// unavailable, or the snapshot history or tags cannot be read.
+    pub async fn find_snapshots(&self, query: &SnapshotQuery) -> Result<Vec<SnapshotSummary>> {
+        let snapshots = self.snapshots().await?;
+        let tags = self.snapshot_tags().await?;
+        Ok(snapshots
+            .into_iter()
+            .filter(|snapshot| query.matches(created(snapshot), tags.get(&snapshot.state_id)))
+            .collect())
+    }
+
+    /// Labels a snapshot selected by full state ID or prefix, replacing any
+    /// earlier label, and returns the resolved full state ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bottle was deleted, the snapshot is missing
+    /// or ambiguous, the FVS service is unavailable, or the tags cannot be
+    /// saved.
+    pub async fn tag_snapshot(
+        &self,
+        state_id_or_prefix: &str,
+        label: impl Into<String>,
+    ) -> Result<String> {
+        let label = label.into();
+        self.update_tag(state_id_or_prefix, |tag| tag.label = Some(label))
+            .await
+    }
+
+    /// Removes the label of a snapshot selected by full state ID or prefix
+    /// and returns the resolved full state ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error as [`tag_snapshot`](Self::tag_snapshot) does.
+    pub async fn untag_snapshot(&self, state_id_or_prefix: &str) -> Result<String> {
+        self.update_tag(state_id_or_prefix, |tag| tag.label = None)
+            .await
+    }
+
+    /// Pins or unpins a snapshot selected by full state ID or prefix and
+    /// returns the resolved full state ID.
+    ///
+    /// Pinned snapshots cannot be deleted with
+    /// [`delete_snapshot`](Self::delete_snapshot) and are never pruned as
+    /// automatic backups.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error as [`tag_snapshot`](Self::tag_snapshot) does.
+    pub async fn pin_snapshot(&self, state_id_or_prefix: &str, pinned: bool) -> Result<String> {
+        self.update_tag(state_id_or_prefix, |tag| tag.pinned = pinned)
+            .await
+    }
+
+    async fn update_tag(
+        &self,
+        state_id_or_prefix: &str,
+        change: impl FnOnce(&mut SnapshotTag),
+    ) -> Result<String> {
+        let _write = self.0.write_lock.write().await;
+        self.ensure_exists()?;
+        let commit = resolve_snapshot(&self.bottle_path(), state_id_or_prefix, &self.0.cx).await?;
+        let path = self.snapshot_tags_path();
+        let mut tags = SnapshotTags::load(&path).await?;
+        tags.update(&commit.state_id, change);
+        tags.save(&path).await?;
+        Ok(commit.state_id)
+    }
+
+    pub(super) fn snapshot_tags_path(&self) -> PathBuf {
+        self.0.cx.directories().snapshot_tags(self.0.id)
+    }
+}
+
+/// Returns when a snapshot was created.
+fn created(snapshot: &SnapshotSummary) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(u64::try_from(snapshot.timestamp).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_combine_tag_pin_and_date_range() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let created = UNIX_EPOCH + 10 * day;
+        let tag = SnapshotTag {
+            label: Some("before mods".into()),
+            pinned: true,
+        };
+
+        assert!(SnapshotQuery::default().matches(created, None));
+        let query = SnapshotQuery::default()
+            .with_tag("before mods")
+            .with_pinned(true)
+            .with_since(created)
+            .with_until(created + day);
+        assert!(query.matches(created, Some(&tag)));
+        assert!(!query.matches(created, None));
+        assert!(
+            !query
+                .clone()
+                .with_until(created)
+                .matches(created, Some(&tag))
+        );
+        assert!(!query.with_tag("other").matches(created, Some(&tag)));
+
+        let mut tags = SnapshotTags::default();
+        tags.update("a", |tag| tag.pinned = true);
+        assert!(tags.is_pinned("a"));
+        tags.update("a", |tag| tag.pinned = false);
+        assert!(!tags.forget("a"));
+    }
+}
@@ -0,0 +1,266 @@
+//! Declarative bottle recipes applied by [`BottleManager::create_from_template`].
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    Operation, Progress, Stage,
+    addons::{Requirement, Slot},
+    error::{Error, Result},
+    proto::{DllOverrideMode, RegistryHive},
+    utils::environment::Environment,
+    wrapper::Wrappers,
+};
+
+use super::{
+    error::BottleError,
+    manager::BottleManager,
+    registry::RegistryData,
+    state::{Bottle, Program, Storage},
+};
+
+/// A reproducible description of a new bottle's configuration.
+///
+/// Templates name exact downloaded addon releases by UUID and are serialized
+/// with the same conventions as `bottle.toml`, so presets can be shipped as
+/// files. Unlike `bottle.toml`, which lists `gamescope` and `mangohud` at the
+/// top level, templates nest them under `wrappers`. Apart from `storage` and
+/// `runner`, every field may be omitted.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BottleTemplate {
+    /// Prefix storage strategy for the new bottle.
+    pub storage: Storage,
+    /// Runner component used to create the prefix.
+    pub runner: Uuid,
+    /// Additional components keyed by the slot they must occupy.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub components: HashMap<Slot, Uuid>,
+    /// Dependencies installed in list order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<Uuid>,
+    /// Environment variables supplied to launched programs and WineBridge.
+    #[serde(default, skip_serializing_if = "Environment::is_empty")]
+    pub environment: Environment,
+    /// Gamescope and MangoHud configuration, as a `wrappers` table holding
+    /// `gamescope` and `mangohud`.
+    #[serde(default)]
+    pub wrappers: Wrappers,
+    /// Wine loading modes keyed by DLL name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dll_overrides: BTreeMap<String, DllOverrideMode>,
+    /// Registry values written in list order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub registry: Vec<TemplateRegistryValue>,
+    /// Programs registered with the bottle; each receives a new UUID.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub programs: Vec<Program>,
+}
+
+/// One registry value written by a [`BottleTemplate`].
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateRegistryValue {
+    /// Hive containing `key`.
+    pub hive: RegistryHive,
+    /// Key path, created when missing.
+    pub key: String,
+    /// Value name; an empty name addresses the key's default value.
+    #[serde(default)]
+    pub name: String,
+    /// Typed value data.
+    pub data: RegistryData,
+}
+
+impl BottleManager {
+    /// Creates a bottle named `name` and applies `template` to it.
+    ///
+    /// Every referenced addon is resolved before any files are created. The
+    /// bottle is then created as with [`create`](Self::create), configured with
+    /// the template's environment, wrappers, and programs, and receives its
+    /// components and dependencies in that order, so recipes run with the
+    /// template environment. DLL overrides and registry values are written
+    /// last and therefore take precedence over recipe changes. Progress from
+    /// each step is reported through the returned operation. If any step
+    /// fails or is cancelled, the new bottle is deleted on a best-effort basis.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BottleError::RequiresAddon`] listing every referenced addon
+    /// that is not downloaded, and [`BottleError::InvalidComponentSlot`] for a
+    /// component listed under a slot it does not occupy. Errors from creation,
+    /// configuration, installation, and WineBridge requests are also returned.
+    pub fn create_from_template(
+        &self,
+        name: impl Into<String>,
+        template: BottleTemplate,
+    ) -> Operation<Bottle> {
+        let name = name.into();
+        let manager = self.clone();
+        Operation::new(move |progress, cancellation| async move {
+            progress.send_replace(Some(Progress::new(Stage::Preparing)));
+            let mut missing = Vec::new();
+            for (slot, id) in &template.components {
+                match manager.addons.component(*id) {
+                    Some(component) if component.slot() != *slot => {
+                        return Err(BottleError::InvalidComponentSlot {
+                            component: *id,
+                            required: *slot,
+                        }
+                        .into());
+                    }
+                    Some(_) => {}
+                    None => missing.push(Requirement::Id(*id)),
+                }
+            }
+            missing.extend(
+                template
+                    .dependencies
+                    .iter()
+                    .filter(|id| manager.addons.dependency(**id).is_none())
+                    .map(|id| Requirement::Id(*id)),
+            );
+            if !missing.is_empty() {
+                return Err(BottleError::RequiresAddon {
+                    required_by: None,
+                    requirements: missing,
+                }
+                .into());
+            }
+
+            let bottle = manager
+                .create(name, template.storage, template.runner)
+                .forward(&progress, &cancellation)
+                .await?;
+            let result = apply(&bottle, template, &progress, &cancellation).await;
+            if let Err(error) = result {
+                if let Err(failed) = manager.delete(bottle.0.id).await {
+                    tracing::warn!(%failed, "failed to remove bottle after {error}");
+                }
+                return Err(error);
+            }
+            Ok(bottle)
+        })
+    }
+}
+
+/// Applies everything in `template` except storage and runner.
+async fn apply(
+    bottle: &Bottle,
+    template: BottleTemplate,
+    progress: &tokio::sync::watch::Sender<Option<Progress>>,
+    cancellation: &tokio_util::sync::CancellationToken,
+) -> Result<()> {
+    progress.send_replace(Some(Progress::new(Stage::Configuring)));
+    let mut edit = bottle.edit();
+    for (key, value) in template.environment.iter() {
+        edit.set_env(key, value);
+    }
+    edit.set_gamescope(template.wrappers.gamescope)
+        .set_mangohud(template.wrappers.mangohud);
+    for program in template.programs {
+        edit.add_program(program.with_new_id());
+    }
+    edit.commit().await?;
+
+    let mut components = template.components.into_iter().collect::<Vec<_>>();
+    // Runtime slots first, so later recipes run with the selected runtime.
+    components.sort_by_key(|(slot, _)| !slot.is_runtime());
+    for (_, id) in components {
+        bottle
+            .set_component(id)
+            .forward(progress, cancellation)
+            .await?;
+    }
+    for id in template.dependencies {
+        bottle.install(id).forward(progress, cancellation).await?;
+    }
+    if cancellation.is_cancelled() {
+        return Err(Error::Cancelled);
+    }
+
+    progress.send_replace(Some(Progress::new(Stage::Configuring)));
+    for (dll, mode) in template.dll_overrides {
+        bottle.set_dll_override(dll, mode).await?;
+    }
+    for value in template.registry {
+        bottle
+            .set_registry_value(value.hive, value.key, value.name, value.data)
+            .await?;
+    }
+    bottle.stop().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minimal_templates_default_optional_fields() {
+        let runner = Uuid::new_v4();
+        let template: BottleTemplate = serde_json::from_value(serde_json::json!({
+            "storage": "Standard",
+            "runner": runner,
+        }))
+        .unwrap();
+
+        assert_eq!(template.storage, Storage::Standard);
+        assert_eq!(template.runner, runner);
+        assert!(template.components.is_empty());
+        assert!(template.dependencies.is_empty());
+        assert!(template.environment.is_empty());
+        assert_eq!(template.wrappers, Wrappers::default());
+        assert!(template.programs.is_empty());
+    }
+
+    #[test]
+    fn wrappers_are_nested_in_templates() {
+        let template: BottleTemplate = serde_json::from_value(serde_json::json!({
+            "storage": "Standard",
+            "runner": Uuid::new_v4(),
+            "wrappers": { "gamescope": { "enabled": true } },
+        }))
+        .unwrap();
+
+        assert!(template.wrappers.gamescope.enabled);
+        assert!(!template.wrappers.mangohud.enabled);
+        assert!(
+            serde_json::from_value::<BottleTemplate>(serde_json::json!({
+                "storage": "Standard",
+                "runner": Uuid::new_v4(),
+                "gamescope": { "enabled": true },
+            }))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn unknown_template_fields_are_rejected() {
+        assert!(
+            serde_json::from_value::<BottleTemplate>(serde_json::json!({
+                "storage": "Standard",
+                "runner": Uuid::new_v4(),
+                "dll_overrides": {},
+            }))
+            .is_ok()
+        );
+        assert!(
+            serde_json::from_value::<BottleTemplate>(serde_json::json!({
+                "storage": "Standard",
+                "runner": Uuid::new_v4(),
+                "dll-overrides": {},
+            }))
+            .is_err()
+        );
+        assert!(
+            serde_json::from_value::<BottleTemplate>(serde_json::json!({
+                "storage": "Standard",
+                "runner": Uuid::new_v4(),
+                "name": "gaming",
+            }))
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,280 @@
+//! Disk space accounting for bottles.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use futures_lite::StreamExt;
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::{
+    Operation, Progress, Stage, Transfer,
+    error::{Error, Result},
+};
+
+use super::{error::BottleError, manager::BottleManager, state::Bottle};
+
+/// Bytes allocated on disk for one bottle, as returned by
+/// [`Bottle::disk_usage`].
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct DiskUsage {
+    /// The prefix files the bottle owns: the whole prefix for Standard
+    /// storage, or the writable upper layer for Virgo storage.
+    pub prefix: u64,
+    /// The part of [`prefix`](Self::prefix) in `drive_c/users`, where
+    /// programs keep settings and saves.
+    pub user_data: u64,
+    /// The bottle's snapshot repository, including rollback checkpoints.
+    pub snapshots: u64,
+    /// Configuration, logs, launch history, and other bottle files.
+    pub other: u64,
+    /// The cached Virgo layers below the upper layer. These may be shared
+    /// with other bottles and are not freed by deleting this one. Always
+    /// zero for Standard storage.
+    pub shared_layers: u64,
+}
+
+impl DiskUsage {
+    /// Returns the bytes that deleting the bottle would free, which excludes
+    /// shared layers.
+    pub fn owned(&self) -> u64 {
+        self.prefix + self.snapshots + self.other
+    }
+}
+
+/// Disk usage of every bottle in a [`BottleManager`], as returned by
+/// [`BottleManager::disk_usage`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DiskUsageSummary {
+    /// Usage of each bottle by UUID.
+    pub bottles: HashMap<Uuid, DiskUsage>,
+    /// The cached Virgo layers referenced by any bottle, each counted once.
+    pub shared_layers: u64,
+}
+
+impl DiskUsageSummary {
+    /// Returns the bytes used by all bottles together with their shared
+    /// layers.
+    pub fn total(&self) -> u64 {
+        self.bottles.values().map(DiskUsage::owned).sum::<u64>() + self.shared_layers
+    }
+}
+
+impl Bottle {
+    /// Measures the disk space used by the bottle.
+    ///
+    /// Sizes are allocated bytes, so sparse files count only their allocated
+    /// blocks and hard-linked files are counted once. Symbolic links are not
+    /// followed, so drive mappings and `dosdevices` links do not count
+    /// towards the bottle. Copies that share blocks through reflinks, such
+    /// as duplicated bottles, are counted in full in each bottle.
+    ///
+    /// The operation holds shared bottle access, reports the bytes counted
+    /// so far with [`Stage::Measuring`], and observes cancellation between
+    /// directories.
+    ///
+    /// # Errors
+    ///
+    /// The operation returns an error if the bottle was deleted, cancellation
+    /// is requested, or a directory cannot be read.
+    pub fn disk_usage(&self) -> Operation<DiskUsage> {
+        let bottle = self.clone();
+        Operation::new(move |progress, cancellation| async move {
+            let mut meter = Meter::new(&progress, &cancellation);
+            bottle.measure(&mut meter).await
+        })
+    }
+
+    async fn measure(&self, meter: &mut Meter<'_>) -> Result<DiskUsage> {
+        let _read = self.0.write_lock.read().await;
+        let state = self.state()?;
+        let bottle_path = self.bottle_path();
+        let root = self.writable_prefix_path(&state);
+        let users = root.join("drive_c/users");
+        let snapshot_repository = bottle_path.join(".fvs2");
+
+        let user_data = meter.measure(&users, &[]).await?;
+        let prefix = user_data + meter.measure(&root, &[users]).await?;
+        let snapshots = meter.measure(&snapshot_repository, &[]).await?;
+        let other = meter
+            .measure(
+                &bottle_path,
+                &[
+                    bottle_path.join("prefix"),
+                    bottle_path.join("upper"),
+                    snapshot_repository,
+                ],
+            )
+            .await?;
+        let mut shared_layers = 0;
+        for repository in state
+            .storage
+            .layer_repositories()
+            .into_iter()
+            .collect::<HashSet<_>>()
+        {
+            shared_layers += meter.layer(&repository).await?;
+        }
+        Ok(DiskUsage {
+            prefix,
+            user_data,
+            snapshots,
+            other,
+            shared_layers,
+        })
+    }
+}
+
+impl BottleManager {
+    /// Measures the disk space used by every bottle.
+    ///
+    /// Bottles are measured one at a time as [`Bottle::disk_usage`] measures
+    /// them. Each bottle reports the layers it references, while the summary
+    /// counts every shared layer once. Bottles deleted while the operation
+    /// runs are left out.
+    ///
+    /// # Errors
+    ///
+    /// The operation returns an error if cancellation is requested or a
+    /// directory cannot be read.
+    pub fn disk_usage(&self) -> Operation<DiskUsageSummary> {
+        let bottles = self.list();
+        Operation::new(move |progress, cancellation| async move {
+            let mut meter = Meter::new(&progress, &cancellation);
+            let mut summary = DiskUsageSummary::default();
+            for bottle in bottles {
+                match bottle.measure(&mut meter).await {
+                    Ok(usage) => {
+                        summary.bottles.insert(bottle.0.id, usage);
+                    }
+                    Err(Error::Bottle(BottleError::Deleted(_))) => {}
+                    Err(error) => return Err(error),
+                }
+            }
+            summary.shared_layers = meter.layers.values().sum();
+            Ok(summary)
+        })
+    }
+}
+
+/// Walks directory trees for one operation, counting every file once.
+struct Meter<'a> {
+    /// Hard-linked files already counted, by device and inode.
+    seen: HashSet<(u64, u64)>,
+    /// Sizes of the layer repositories measured so far.
+    layers: HashMap<PathBuf, u64>,
+    counted: u64,
+    progress: &'a watch::Sender<Option<Progress>>,
+    cancellation: &'a CancellationToken,
+}
+
+impl<'a> Meter<'a> {
+    fn new(
+        progress: &'a watch::Sender<Option<Progress>>,
+        cancellation: &'a CancellationToken,
+    ) -> Self {
+        Self {
+            seen: HashSet::new(),
+            layers: HashMap::new(),
+            counted: 0,
+            progress,
+            cancellation,
+        }
+    }
+
+    /// Returns the bytes allocated below `root`, or zero if it is missing.
+    ///
+    /// Paths in `skip` are left out together with everything below them.
+    async fn measure(&mut self, root: &Path, skip: &[PathBuf]) -> Result<u64> {
+        let mut pending = vec![root.to_path_buf()];
+        let mut bytes = 0;
+        while let Some(path) = pending.pop() {
+            if self.cancellation.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+            let metadata = match async_fs::symlink_metadata(&path).await {
+                Ok(metadata) => metadata,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error.into()),
+            };
+            if !metadata.is_dir()
+                && metadata.nlink() > 1
+                && !self.seen.insert((metadata.dev(), metadata.ino()))
+            {
+                continue;
+            }
+            bytes += metadata.blocks() * 512;
+            if !metadata.is_dir() {
+                continue;
+            }
+            let mut entries = match async_fs::read_dir(&path).await {
+                Ok(entries) => entries,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error.into()),
+            };
+            while let Some(entry) = entries.try_next().await? {
+                let path = entry.path();
+                if !skip.contains(&path) {
+                    pending.push(path);
+                }
+            }
+            self.progress.send_replace(Some(Progress::transferring(
+                Stage::Measuring,
+                Transfer {
+                    current: self.counted + bytes,
+                    total: None,
+                },
+            )));
+        }
+        self.counted += bytes;
+        Ok(bytes)
+    }
+
+    /// Returns the size of a layer repository, measuring it only once.
+    async fn layer(&mut self, repository: &Path) -> Result<u64> {
+        if let Some(bytes) = self.layers.get(repository) {
+            return Ok(*bytes);
+        }
+        let bytes = self.measure(repository, &[]).await?;
+        self.layers.insert(repository.to_path_buf(), bytes);
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hard_links_and_skipped_paths_are_not_counted_twice() {
+        let root = std::env::temp_dir().join(format!("bottles-next-{}", Uuid::new_v4()));
+        let skipped = root.join("skipped");
+        std::fs::create_dir_all(&skipped).unwrap();
+        std::fs::write(root.join("data"), vec![1; 64 * 1024]).unwrap();
+        std::fs::hard_link(root.join("data"), root.join("link")).unwrap();
+        std::fs::write(skipped.join("data"), vec![1; 64 * 1024]).unwrap();
+
+        let (progress, _) = watch::channel(None);
+        let cancellation = CancellationToken::new();
+        let mut meter = Meter::new(&progress, &cancellation);
+        let (total, again, skipped, missing) = futures_lite::future::block_on(async {
+            (
+                meter.measure(&root, &[skipped.clone()]).await.unwrap(),
+                meter.measure(&root.join("link"), &[]).await.unwrap(),
+                meter.measure(&skipped, &[]).await.unwrap(),
+                meter.measure(&root.join("missing"), &[]).await.unwrap(),
+            )
+        });
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert!(total > 0);
+        assert_eq!(again, 0);
+        assert!(skipped > 0);
+        assert_eq!(missing, 0);
+    }
+}
@@ -5,7 +5,7 @@ pub use crate::bottle::error::VirgoError;
 pub use crate::{
     accounts::AccountError,
     addons::{AddonError, CatalogError, InstallerError},
-    bottle::BottleError,
+    bottle::{BottleError, HookFailure},
     credentials::CredentialError,
     profile::ProfileError,
     runner::RunnerError,
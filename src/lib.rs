@@ -5,7 +5,9 @@ mod core;
 pub mod credentials;
 pub mod error;
 pub mod library;
+mod lnk;
 mod operation;
+mod pe;
 mod prefix;
 pub mod profile;
 mod runner;
@@ -18,13 +20,20 @@ pub use addons::{
     Addon, AddonError, Addons, CatalogEntry, CatalogError, Component, Dependency, IndexEntry,
     InstallerError, Requirement, Slot,
 };
+#[cfg(feature = "fvs")]
 pub use bottle::{
-    Bottle, BottleEdit, BottleManager, BottleState, DllOverride, DllOverrideMode, GamescopeConfig,
-    GamescopeFilter, GamescopeScaler, MangoHudConfig, Process, Program, RegistryHive, Storage,
-    Wrappers,
+    BackupPolicy, BackupSchedule, ChangeKind, FileChange, RegistryChange, RestorePath, Retention,
+    Snapshot, SnapshotDiff, SnapshotQuery, SnapshotSummary, SnapshotTag,
+};
+pub use bottle::{
+    Bottle, BottleEdit, BottleFiles, BottleManager, BottleState, BottleTemplate, Check, DiskUsage,
+    DiskUsageSummary, DllOverride, DllOverrideMode, Drive, DriveMapping, ExecutableArchitecture,
+    ExecutableInfo, ExecutableSubsystem, Finding, GamescopeConfig, GamescopeFilter,
+    GamescopeScaler, Hook, IconSize, LaunchLog, LaunchRecord, MangoHudConfig, PathInfo, Playtime,
+    Process, ProcessEvent, Program, ProgramExit, ProgramRun, RegistryData, RegistryHive,
+    RegistryKey, Repair, RepairMode, Report, RunArg, RunOptions, SaveBackup, SaveSync, Service,
+    ServiceStartType, Storage, SyncTarget, TemplateRegistryValue, Wrappers,
 };
-#[cfg(feature = "fvs")]
-pub use bottle::{Snapshot, SnapshotSummary};
 pub use core::{Bottles, Config};
 pub use error::Error;
 pub use operation::{Operation, Progress, Stage, Transfer};
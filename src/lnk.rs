@@ -0,0 +1,265 @@
+//! Reading Windows shell links (`.lnk` files) without the shell.
+//!
+//! Only the parts of a link that name a file-system target are interpreted:
+//! the local path in the link information, the string data, and the
+//! environment-variable target block. Targets stored only as shell item IDs,
+//! such as virtual folders, are not resolved. Malformed links yield `None`.
+
+/// Size of `ShellLinkHeader`, which is also its first field.
+const HEADER_SIZE: u32 = 0x4c;
+
+/// `CLSID_ShellLink`, as stored in the header.
+const LINK_CLSID: [u8; 16] = [
+    0x01, 0x14, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46,
+];
+
+const HAS_LINK_TARGET_ID_LIST: u32 = 0x01;
+const HAS_LINK_INFO: u32 = 0x02;
+const HAS_NAME: u32 = 0x04;
+const HAS_RELATIVE_PATH: u32 = 0x08;
+const HAS_WORKING_DIR: u32 = 0x10;
+const HAS_ARGUMENTS: u32 = 0x20;
+const HAS_ICON_LOCATION: u32 = 0x40;
+const IS_UNICODE: u32 = 0x80;
+
+/// `LinkInfo` flag saying the target is on a local volume.
+const VOLUME_ID_AND_LOCAL_BASE_PATH: u32 = 0x01;
+
+/// Signature of `EnvironmentVariableDataBlock`.
+const ENVIRONMENT_VARIABLE_BLOCK: u32 = 0xa000_0001;
+/// Size of `EnvironmentVariableDataBlock`.
+const ENVIRONMENT_VARIABLE_BLOCK_SIZE: usize = 0x314;
+/// Size of each fixed-length target field of that block.
+const MAX_PATH: usize = 260;
+
+/// The file-system parts of a shell link.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub(crate) struct Shortcut {
+    /// Absolute Windows path of the target from the link information.
+    pub(crate) target: Option<String>,
+    /// Target path containing `%VARIABLE%` references, which installers use
+    /// for targets under folders such as `%ProgramFiles%`.
+    pub(crate) expandable_target: Option<String>,
+    /// Windows directory the target starts in.
+    pub(crate) working_directory: Option<String>,
+    /// Command line passed to the target.
+    pub(crate) arguments: Option<String>,
+}
+
+impl Shortcut {
+    /// Parses a shell link, or returns `None` if `data` is not one.
+    pub(crate) fn parse(data: &[u8]) -> Option<Self> {
+        if u32_at(data, 0)? != HEADER_SIZE || data.get(4..20)? != LINK_CLSID {
+            return None;
+        }
+        let flags = u32_at(data, 0x14)?;
+        let unicode = flags & IS_UNICODE != 0;
+        let mut offset = HEADER_SIZE as usize;
+        let mut shortcut = Self::default();
+
+        if flags & HAS_LINK_TARGET_ID_LIST != 0 {
+            offset += 2 + usize::from(u16_at(data, offset)?);
+        }
+        if flags & HAS_LINK_INFO != 0 {
+            let size = u32_at(data, offset)? as usize;
+            let info = data.get(offset..offset.checked_add(size)?)?;
+            shortcut.target = local_path(info);
+            offset += size;
+        }
+        for flag in [
+            HAS_NAME,
+            HAS_RELATIVE_PATH,
+            HAS_WORKING_DIR,
+            HAS_ARGUMENTS,
+            HAS_ICON_LOCATION,
+        ] {
+            if flags & flag == 0 {
+                continue;
+            }
+            let count = usize::from(u16_at(data, offset)?);
+            let size = if unicode { count * 2 } else { count };
+            let bytes = data.get(offset + 2..offset + 2 + size)?;
+            offset += 2 + size;
+            let value = if unicode { utf16(bytes) } else { ansi(bytes) };
+            match flag {
+                HAS_WORKING_DIR => shortcut.working_directory = non_empty(value),
+                HAS_ARGUMENTS => shortcut.arguments = non_empty(value),
+                _ => {}
+            }
+        }
+        while let Some(size) = u32_at(data, offset) {
+            let size = size as usize;
+            if size < 8 {
+                break;
+            }
+            let Some(block) = data.get(offset..offset.checked_add(size)?) else {
+                break;
+            };
+            if u32_at(block, 4) == Some(ENVIRONMENT_VARIABLE_BLOCK)
+                && size >= ENVIRONMENT_VARIABLE_BLOCK_SIZE
+            {
+                let ansi_target = &block[8..8 + MAX_PATH];
+                let unicode_target = &block[8 + MAX_PATH..8 + MAX_PATH * 3];
+                shortcut.expandable_target = non_empty(terminated_utf16(unicode_target))
+                    .or_else(|| non_empty(terminated_ansi(ansi_target)));
+            }
+            offset += size;
+        }
+        Some(shortcut)
+    }
+}
+
+/// Returns the local target path of a `LinkInfo` structure.
+fn local_path(info: &[u8]) -> Option<String> {
+    let header_size = u32_at(info, 4)?;
+    if u32_at(info, 8)? & VOLUME_ID_AND_LOCAL_BASE_PATH == 0 {
+        return None;
+    }
+    let string_at = |offset: Option<u32>, unicode: bool| {
+        let bytes = info.get(offset? as usize..)?;
+        Some(if unicode {
+            terminated_utf16(bytes)
+        } else {
+            terminated_ansi(bytes)
+        })
+    };
+    // Headers of at least 0x24 bytes add Unicode copies of both strings.
+    let unicode = header_size >= 0x24;
+    let base = if unicode {
+        string_at(u32_at(info, 28), true)
+    } else {
+        string_at(u32_at(info, 16), false)
+    }?;
+    let suffix = if unicode {
+        string_at(u32_at(info, 32), true)
+    } else {
+        string_at(u32_at(info, 24), false)
+    }
+    .unwrap_or_default();
+    non_empty(base + &suffix)
+}
+
+fn non_empty(value: String) -> Option<String> {
+    (!value.is_empty()).then_some(value)
+}
+
+fn utf16(bytes: &[u8]) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .collect::<Vec<_>>();
+    String::from_utf16_lossy(&units)
+}
+
+fn terminated_utf16(bytes: &[u8]) -> String {
+    let end = bytes
+        .chunks_exact(2)
+        .position(|unit| unit == [0, 0])
+        .map_or(bytes.len(), |index| index * 2);
+    utf16(&bytes[..end])
+}
+
+/// Decodes system code page text as Latin-1, which matches Windows-1252
+/// outside the range `0x80..0xa0`.
+fn ansi(bytes: &[u8]) -> String {
+    bytes.iter().copied().map(char::from).collect()
+}
+
+fn terminated_ansi(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+    ansi(&bytes[..end])
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset.checked_add(2)?)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset.checked_add(4)?)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(flags: u32) -> Vec<u8> {
+        let mut data = vec![0; HEADER_SIZE as usize];
+        data[0..4].copy_from_slice(&HEADER_SIZE.to_le_bytes());
+        data[4..20].copy_from_slice(&LINK_CLSID);
+        data[0x14..0x18].copy_from_slice(&flags.to_le_bytes());
+        data
+    }
+
+    fn string(text: &str) -> Vec<u8> {
+        let units = text.encode_utf16().collect::<Vec<_>>();
+        let mut data = (units.len() as u16).to_le_bytes().to_vec();
+        data.extend(units.iter().flat_map(|unit| unit.to_le_bytes()));
+        data
+    }
+
+    #[test]
+    fn links_yield_local_targets_and_string_data() {
+        let mut data = header(
+            HAS_LINK_TARGET_ID_LIST
+                | HAS_LINK_INFO
+                | HAS_NAME
+                | HAS_WORKING_DIR
+                | HAS_ARGUMENTS
+                | IS_UNICODE,
+        );
+        // An empty item ID list is only its terminator.
+        data.extend([2, 0, 0, 0]);
+        let base = b"C:\\Games\\game.exe\0";
+        // The common path suffix is the empty string ending the base path.
+        let suffix = 0x1c + base.len() as u32 - 1;
+        let mut info = Vec::new();
+        for field in [0, 0x1c, VOLUME_ID_AND_LOCAL_BASE_PATH, 0, 0x1c, 0, suffix] {
+            info.extend(u32::to_le_bytes(field));
+        }
+        info.extend(base);
+        let size = info.len() as u32;
+        info[0..4].copy_from_slice(&size.to_le_bytes());
+        data.extend(info);
+        data.extend(string("Play the game"));
+        data.extend(string(r"C:\Games"));
+        data.extend(string("-windowed"));
+        data.extend([0; 4]);
+
+        assert_eq!(
+            Shortcut::parse(&data),
+            Some(Shortcut {
+                target: Some(r"C:\Games\game.exe".to_owned()),
+                expandable_target: None,
+                working_directory: Some(r"C:\Games".to_owned()),
+                arguments: Some("-windowed".to_owned()),
+            })
+        );
+        assert_eq!(Shortcut::parse(b"MZ"), None);
+    }
+
+    #[test]
+    fn environment_blocks_supply_expandable_targets() {
+        let mut data = header(IS_UNICODE);
+        let mut block = vec![0; ENVIRONMENT_VARIABLE_BLOCK_SIZE];
+        block[0..4].copy_from_slice(&(ENVIRONMENT_VARIABLE_BLOCK_SIZE as u32).to_le_bytes());
+        block[4..8].copy_from_slice(&ENVIRONMENT_VARIABLE_BLOCK.to_le_bytes());
+        let target = r"%ProgramFiles%\Game\game.exe";
+        for (index, unit) in target.encode_utf16().enumerate() {
+            let offset = 8 + MAX_PATH + index * 2;
+            block[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        data.extend(block);
+        data.extend([0; 4]);
+
+        let shortcut = Shortcut::parse(&data).unwrap();
+        assert_eq!(shortcut.target, None);
+        assert_eq!(shortcut.expandable_target.as_deref(), Some(target));
+    }
+}
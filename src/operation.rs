@@ -6,6 +6,7 @@ use std::{
 };
 
 use futures_core::Stream;
+use futures_lite::future;
 use tokio::sync::watch;
 use tokio_stream::{StreamExt, wrappers::WatchStream};
 use tokio_util::sync::CancellationToken;
@@ -82,6 +83,7 @@ pub enum Stage {
         file: String,
     },
     Extracting,
+    Archiving,
     CreatingPrefix,
     #[cfg(feature = "fvs")]
     Checkpointing,
@@ -90,6 +92,10 @@ pub enum Stage {
     Rebuilding,
     Configuring,
     Removing,
+    Measuring,
+    Syncing,
+    #[cfg(feature = "fvs")]
+    Comparing,
     #[cfg(feature = "fvs")]
     Committing,
 }
@@ -102,6 +108,7 @@ impl fmt::Display for Stage {
             Self::Downloading { file } => write!(formatter, "Downloading {file}"),
             Self::Verifying { file } => write!(formatter, "Verifying {file}"),
             Self::Extracting => formatter.write_str("Extracting"),
+            Self::Archiving => formatter.write_str("Archiving"),
             Self::CreatingPrefix => formatter.write_str("Creating prefix"),
             #[cfg(feature = "fvs")]
             Self::Checkpointing => formatter.write_str("Checkpointing"),
@@ -110,6 +117,10 @@ impl fmt::Display for Stage {
             Self::Rebuilding => formatter.write_str("Rebuilding"),
             Self::Configuring => formatter.write_str("Configuring"),
             Self::Removing => formatter.write_str("Removing"),
+            Self::Measuring => formatter.write_str("Measuring"),
+            Self::Syncing => formatter.write_str("Syncing"),
+            #[cfg(feature = "fvs")]
+            Self::Comparing => formatter.write_str("Comparing"),
             #[cfg(feature = "fvs")]
             Self::Committing => formatter.write_str("Committing"),
         }
@@ -167,6 +178,32 @@ impl<T> Operation<T> {
     pub fn cancellation_token(&self) -> CancellationToken {
         self.cancellation.clone()
     }
+
+    /// Drives this operation as one step of an enclosing operation.
+    ///
+    /// Progress is relayed to the enclosing operation's sender, and a
+    /// cancellation request on `cancellation` is passed on to this operation,
+    /// which still runs to its terminal result.
+    pub(crate) async fn forward(
+        self,
+        progress: &watch::Sender<Option<Progress>>,
+        cancellation: &CancellationToken,
+    ) -> Result<T> {
+        let token = self.cancellation_token();
+        let mut updates = self.progress();
+        let relay = async {
+            while let Some(update) = updates.next().await {
+                progress.send_replace(Some(update));
+            }
+        };
+        let cancel = async {
+            cancellation.cancelled().await;
+            token.cancel();
+            future::pending::<()>().await
+        };
+        let (result, ()) = future::zip(self, future::or(relay, cancel)).await;
+        result
+    }
 }
 
 impl<T> Operation<T> {
@@ -0,0 +1,593 @@
+//! Reading Portable Executable images without running them.
+//!
+//! Only the headers and the resource tree are interpreted, and only those
+//! parts of a file are read, so large game executables stay cheap to inspect.
+//! Malformed or truncated images yield `None` rather than errors, because
+//! executables in a prefix come from arbitrary installers.
+
+use std::{collections::HashMap, io, path::Path};
+
+use futures_lite::{AsyncReadExt, AsyncSeekExt, io::SeekFrom};
+
+use crate::utils::png;
+
+/// Resource type of a single icon image.
+const RT_ICON: u16 = 3;
+/// Resource type of an icon group, which lists the sizes of one icon.
+const RT_GROUP_ICON: u16 = 14;
+/// Resource type of the version information block.
+const RT_VERSION: u16 = 16;
+
+/// Index of the resource table among the optional header's data directories.
+const RESOURCE_DIRECTORY: usize = 2;
+
+/// Bytes read from the start of a file to find the headers.
+const HEADER_LIMIT: u64 = 64 * 1024;
+
+/// Largest resource tree that is read; images with a larger one are treated
+/// as having no resources.
+const RESOURCE_LIMIT: u32 = 64 * 1024 * 1024;
+
+/// Signature of `VS_FIXEDFILEINFO`.
+const FIXED_FILE_INFO_SIGNATURE: u32 = 0xfeef_04bd;
+
+/// The headers and resource tree of a PE image.
+pub(crate) struct Image {
+    /// `IMAGE_FILE_MACHINE_*` value of the COFF header.
+    pub(crate) machine: u16,
+    /// `IMAGE_FILE_*` flags of the COFF header.
+    pub(crate) characteristics: u16,
+    /// `IMAGE_SUBSYSTEM_*` value of the optional header.
+    pub(crate) subsystem: u16,
+    resources: Resources,
+}
+
+/// The fields of an image's headers needed to locate its resources.
+struct Headers {
+    machine: u16,
+    characteristics: u16,
+    subsystem: u16,
+    /// Virtual address, file offset, and size of the resource tree.
+    resources: Option<(u32, u32, u32)>,
+}
+
+/// The raw resource tree and the virtual address it is loaded at.
+#[derive(Default)]
+struct Resources {
+    data: Vec<u8>,
+    rva: u32,
+}
+
+/// A resource identifier, which is either numeric or a UTF-16 name.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum ResourceName {
+    Id(u16),
+    Name(String),
+}
+
+/// One image listed by an icon group.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct IconEntry {
+    /// Width in pixels.
+    pub(crate) width: u32,
+    /// Height in pixels.
+    pub(crate) height: u32,
+    /// Bits per pixel, or zero when the group does not say.
+    pub(crate) bit_count: u16,
+    /// Identifier of the `RT_ICON` resource holding the image.
+    id: u16,
+}
+
+/// The contents of an image's version resource.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub(crate) struct VersionInfo {
+    /// File version from `VS_FIXEDFILEINFO`, most significant part first.
+    pub(crate) file_version: Option<[u16; 4]>,
+    /// Values of the preferred string table, such as `ProductName`.
+    pub(crate) strings: HashMap<String, String>,
+}
+
+impl Image {
+    /// Reads the image at `path`, or returns `None` if it is not a PE image.
+    pub(crate) async fn read(path: &Path) -> io::Result<Option<Self>> {
+        let mut file = async_fs::File::open(path).await?;
+        let mut head = Vec::new();
+        (&mut file)
+            .take(HEADER_LIMIT)
+            .read_to_end(&mut head)
+            .await?;
+        let Some(headers) = Headers::parse(&head) else {
+            return Ok(None);
+        };
+        let mut resources = Resources::default();
+        if let Some((rva, offset, size)) = headers.resources
+            && size <= RESOURCE_LIMIT
+        {
+            let mut data = vec![0; size as usize];
+            file.seek(SeekFrom::Start(offset.into())).await?;
+            match file.read_exact(&mut data).await {
+                Ok(()) => resources = Resources { data, rva },
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(Some(Self::new(headers, resources)))
+    }
+
+    fn new(headers: Headers, resources: Resources) -> Self {
+        Self {
+            machine: headers.machine,
+            characteristics: headers.characteristics,
+            subsystem: headers.subsystem,
+            resources,
+        }
+    }
+
+    /// Returns the images of every icon group in resource order. Windows
+    /// shows the first group as the executable's icon.
+    pub(crate) fn icon_groups(&self) -> Vec<Vec<IconEntry>> {
+        self.resources
+            .list(RT_GROUP_ICON)
+            .into_iter()
+            .map(|(_, group)| {
+                let count = u16_at(group, 4).unwrap_or_default() as usize;
+                (0..count)
+                    .map_while(|index| {
+                        let entry = 6 + index * 14;
+                        let size = |byte: u8| if byte == 0 { 256 } else { u32::from(byte) };
+                        Some(IconEntry {
+                            width: size(*group.get(entry)?),
+                            height: size(*group.get(entry + 1)?),
+                            bit_count: u16_at(group, entry + 6)?,
+                            id: u16_at(group, entry + 12)?,
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Decodes `entry` from [`icon_groups`](Self::icon_groups) as a PNG image.
+    pub(crate) fn icon_png(&self, entry: &IconEntry) -> Option<Vec<u8>> {
+        let (_, data) = self
+            .resources
+            .list(RT_ICON)
+            .into_iter()
+            .find(|(name, _)| *name == ResourceName::Id(entry.id))?;
+        if data.starts_with(png::SIGNATURE) {
+            return Some(data.to_vec());
+        }
+        let (width, height, pixels) = decode_dib(data)?;
+        Some(png::encode_rgba(width, height, &pixels))
+    }
+
+    /// Parses the version resource, if the image has one.
+    ///
+    /// Of several string tables, the US English one is preferred, then the
+    /// first.
+    pub(crate) fn version_info(&self) -> Option<VersionInfo> {
+        let (_, data) = self.resources.list(RT_VERSION).into_iter().next()?;
+        let root = Block::parse(data, 0)?;
+        let mut info = VersionInfo::default();
+        if u32_at(root.value, 0) == Some(FIXED_FILE_INFO_SIGNATURE) {
+            let (high, low) = (u32_at(root.value, 8)?, u32_at(root.value, 12)?);
+            info.file_version = Some([
+                (high >> 16) as u16,
+                high as u16,
+                (low >> 16) as u16,
+                low as u16,
+            ]);
+        }
+        let tables = root
+            .children(data)
+            .filter(|block| block.key == "StringFileInfo")
+            .flat_map(|block| block.children(data).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let table = tables
+            .iter()
+            .find(|table| table.key.starts_with("0409"))
+            .or(tables.first());
+        if let Some(table) = table {
+            for string in table.children(data) {
+                let units = string
+                    .value
+                    .chunks_exact(2)
+                    .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                    .take_while(|unit| *unit != 0)
+                    .collect::<Vec<_>>();
+                info.strings
+                    .insert(string.key, String::from_utf16_lossy(&units));
+            }
+        }
+        Some(info)
+    }
+}
+
+impl Headers {
+    /// Parses the headers and section table at the start of `data`.
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.get(..2)? != b"MZ" {
+            return None;
+        }
+        let pe = u32_at(data, 0x3c)? as usize;
+        if data.get(pe..pe.checked_add(4)?)? != b"PE\0\0" {
+            return None;
+        }
+        let coff = pe + 4;
+        let machine = u16_at(data, coff)?;
+        let section_count = u16_at(data, coff + 2)? as usize;
+        let optional_size = u16_at(data, coff + 16)? as usize;
+        let characteristics = u16_at(data, coff + 18)?;
+        let optional = coff + 20;
+        let directories = match u16_at(data, optional)? {
+            0x10b => optional + 96,
+            0x20b => optional + 112,
+            _ => return None,
+        };
+        let subsystem = u16_at(data, optional + 68)?;
+        let directory_count = u32_at(data, directories - 4)? as usize;
+
+        let mut resources = None;
+        if directory_count > RESOURCE_DIRECTORY {
+            let entry = directories + RESOURCE_DIRECTORY * 8;
+            let (rva, size) = (u32_at(data, entry)?, u32_at(data, entry + 4)?);
+            let table = optional + optional_size;
+            resources = (0..section_count)
+                .map_while(|index| {
+                    let header = table + index * 40;
+                    let virtual_size = u32_at(data, header + 8)?;
+                    let virtual_address = u32_at(data, header + 12)?;
+                    let raw_size = u32_at(data, header + 16)?;
+                    let raw_offset = u32_at(data, header + 20)?;
+                    Some((
+                        virtual_address,
+                        virtual_size.max(raw_size),
+                        raw_size,
+                        raw_offset,
+                    ))
+                })
+                .find(|&(address, extent, _, _)| rva >= address && rva - address < extent)
+                .and_then(|(address, _, raw_size, raw_offset)| {
+                    let start = rva - address;
+                    // The tree must be stored in the file, not zero-filled.
+                    (size != 0 && start.checked_add(size)? <= raw_size).then_some((
+                        rva,
+                        raw_offset.checked_add(start)?,
+                        size,
+                    ))
+                });
+        }
+        Some(Self {
+            machine,
+            characteristics,
+            subsystem,
+            resources,
+        })
+    }
+}
+
+impl Resources {
+    /// Returns every resource of type `kind` in its first language, in the
+    /// order of the resource tree.
+    fn list(&self, kind: u16) -> Vec<(ResourceName, &[u8])> {
+        let tree = self.data.as_slice();
+        let Some(names) = directory_entries(tree, 0)
+            .into_iter()
+            .find(|(name, _)| *name == Ok(kind))
+            .and_then(|(_, target)| subdirectory(target))
+        else {
+            return Vec::new();
+        };
+        directory_entries(tree, names)
+            .into_iter()
+            .filter_map(|(name, target)| {
+                let name = match name {
+                    Ok(id) => ResourceName::Id(id),
+                    Err(offset) => ResourceName::Name(string_at(tree, offset)?),
+                };
+                let languages = subdirectory(target)?;
+                let (_, leaf) = directory_entries(tree, languages).into_iter().next()?;
+                if subdirectory(leaf).is_some() {
+                    return None;
+                }
+                let leaf = leaf as usize;
+                let start = u32_at(tree, leaf)?.checked_sub(self.rva)? as usize;
+                let size = u32_at(tree, leaf + 4)? as usize;
+                Some((name, tree.get(start..start.checked_add(size)?)?))
+            })
+            .collect()
+    }
+}
+
+/// Lists a resource directory's entries as numeric IDs, or the offsets of
+/// their names, with the offset of what each entry points to.
+fn directory_entries(tree: &[u8], offset: u32) -> Vec<(Result<u16, u32>, u32)> {
+    let offset = offset as usize;
+    let (Some(named), Some(ids)) = (u16_at(tree, offset + 12), u16_at(tree, offset + 14)) else {
+        return Vec::new();
+    };
+    (0..usize::from(named) + usize::from(ids))
+        .map_while(|index| {
+            let entry = offset + 16 + index * 8;
+            let name = u32_at(tree, entry)?;
+            let target = u32_at(tree, entry + 4)?;
+            let name = if name & 0x8000_0000 != 0 {
+                Err(name & 0x7fff_ffff)
+            } else {
+                Ok(name as u16)
+            };
+            Some((name, target))
+        })
+        .collect()
+}
+
+/// Returns the offset of a subdirectory target, or `None` for a data entry.
+fn subdirectory(target: u32) -> Option<u32> {
+    (target & 0x8000_0000 != 0).then_some(target & 0x7fff_ffff)
+}
+
+/// Reads a length-prefixed UTF-16 resource name.
+fn string_at(tree: &[u8], offset: u32) -> Option<String> {
+    let offset = offset as usize;
+    let length = u16_at(tree, offset)? as usize;
+    let units = (0..length)
+        .map(|index| u16_at(tree, offset + 2 + index * 2))
+        .collect::<Option<Vec<_>>>()?;
+    Some(String::from_utf16_lossy(&units))
+}
+
+/// One node of a version resource: `VS_VERSIONINFO`, a string table, or a
+/// string.
+struct Block<'a> {
+    key: String,
+    value: &'a [u8],
+    /// Range of the children within the resource.
+    children: (usize, usize),
+}
+
+impl<'a> Block<'a> {
+    /// Parses the block at `offset`, which is relative to the start of the
+    /// version resource because padding aligns to 32 bits from there.
+    fn parse(data: &'a [u8], offset: usize) -> Option<Self> {
+        let end = offset.checked_add(u16_at(data, offset)? as usize)?;
+        let data = data.get(..end)?;
+        let value_length = u16_at(data, offset + 2)? as usize;
+        let text = u16_at(data, offset + 4)? == 1;
+        let mut key = Vec::new();
+        let mut position = offset + 6;
+        loop {
+            let unit = u16_at(data, position)?;
+            position += 2;
+            if unit == 0 {
+                break;
+            }
+            key.push(unit);
+        }
+        let value_start = align(position).min(end);
+        // Text lengths count UTF-16 units, but some linkers count bytes, so
+        // text runs to the end of the block and stops at its terminator.
+        // Blocks without a value, like string tables, hold children instead.
+        let (value_end, children_start) = if value_length == 0 {
+            (value_start, value_start)
+        } else if text {
+            (end, end)
+        } else {
+            let value_end = value_start.saturating_add(value_length).min(end);
+            (value_end, align(value_end).min(end))
+        };
+        Some(Self {
+            key: String::from_utf16_lossy(&key),
+            value: &data[value_start..value_end],
+            children: (children_start, end),
+        })
+    }
+
+    fn children(&self, data: &'a [u8]) -> impl Iterator<Item = Block<'a>> + use<'a> {
+        let (mut offset, end) = self.children;
+        std::iter::from_fn(move || {
+            if offset >= end {
+                return None;
+            }
+            let length = u16_at(data, offset)? as usize;
+            let block = Block::parse(data, offset)?;
+            offset = if length == 0 {
+                end
+            } else {
+                align(offset + length)
+            };
+            Some(block)
+        })
+    }
+}
+
+fn align(offset: usize) -> usize {
+    offset.next_multiple_of(4)
+}
+
+/// Decodes an icon stored as a device-independent bitmap with an AND mask
+/// into top-down RGBA pixels.
+fn decode_dib(data: &[u8]) -> Option<(u32, u32, Vec<u8>)> {
+    let header = u32_at(data, 0)? as usize;
+    let width = i32_at(data, 4)?;
+    // The height covers both the color bitmap and the mask.
+    let height = i32_at(data, 8)? / 2;
+    let bit_count = u16_at(data, 14)?;
+    let compression = u32_at(data, 16)?;
+    if header < 40 || width <= 0 || height <= 0 || width > 1024 || height > 1024 {
+        return None;
+    }
+    if compression != 0 {
+        return None;
+    }
+    let (width, height) = (width as usize, height as usize);
+    let palette_size = match bit_count {
+        1 | 4 | 8 => match u32_at(data, 32)? {
+            0 => 1 << bit_count,
+            used => used as usize,
+        },
+        24 | 32 => 0,
+        _ => return None,
+    };
+    let palette = data.get(header..header + palette_size * 4)?;
+    let stride = (width * usize::from(bit_count)).div_ceil(32) * 4;
+    let colors = header + palette_size * 4;
+    let mask = colors + stride * height;
+    let mask_stride = width.div_ceil(32) * 4;
+    data.get(mask..mask + mask_stride * height)?;
+
+    // Rows are stored bottom-up.
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    let mut masks = Vec::with_capacity(width * height);
+    for row in (0..height).rev() {
+        let line = &data[colors + row * stride..colors + (row + 1) * stride];
+        let mask_line = &data[mask + row * mask_stride..mask + (row + 1) * mask_stride];
+        for x in 0..width {
+            let opaque = mask_line[x / 8] & (0x80 >> (x % 8)) == 0;
+            let [b, g, r, a] = match bit_count {
+                32 => [
+                    line[x * 4],
+                    line[x * 4 + 1],
+                    line[x * 4 + 2],
+                    line[x * 4 + 3],
+                ],
+                24 => [line[x * 3], line[x * 3 + 1], line[x * 3 + 2], 0],
+                _ => {
+                    let bits = usize::from(bit_count);
+                    let bit = x * bits;
+                    let index = (line[bit / 8] >> (8 - bits - bit % 8)) & ((1 << bits) - 1) as u8;
+                    let color = palette.get(usize::from(index) * 4..usize::from(index) * 4 + 3)?;
+                    [color[0], color[1], color[2], 0]
+                }
+            };
+            pixels.extend([r, g, b, a]);
+            masks.push(opaque);
+        }
+    }
+    // Only 32-bit images carry alpha, and older ones leave it zero and rely
+    // on the mask like the other depths.
+    if bit_count != 32 || pixels.chunks_exact(4).all(|pixel| pixel[3] == 0) {
+        for (pixel, opaque) in pixels.chunks_exact_mut(4).zip(masks) {
+            pixel[3] = if opaque { 255 } else { 0 };
+        }
+    }
+    Some((width as u32, height as u32, pixels))
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset.checked_add(2)?)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset.checked_add(4)?)?.try_into().ok()?,
+    ))
+}
+
+fn i32_at(data: &[u8], offset: usize) -> Option<i32> {
+    u32_at(data, offset).map(|value| value as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bitmap_icons_decode_top_down_with_their_mask() {
+        let mut dib = Vec::new();
+        for field in [40, 2, 4] {
+            dib.extend(u32::to_le_bytes(field));
+        }
+        dib.extend([1, 0, 24, 0]);
+        dib.extend([0; 24]);
+        // Color rows, bottom row first and padded to four bytes.
+        dib.extend([255, 0, 0, 0, 255, 0, 0, 0]);
+        dib.extend([0, 0, 255, 255, 255, 255, 0, 0]);
+        // Mask rows; a set bit makes the pixel transparent.
+        dib.extend([0x40, 0, 0, 0]);
+        dib.extend([0, 0, 0, 0]);
+
+        let (width, height, pixels) = decode_dib(&dib).unwrap();
+
+        assert_eq!((width, height), (2, 2));
+        assert_eq!(
+            pixels,
+            [
+                255, 0, 0, 255, 255, 255, 255, 255, //
+                0, 0, 255, 255, 0, 255, 0, 0,
+            ]
+        );
+    }
+
+    fn block(key: &str, text: bool, value: &[u8], children: &[Vec<u8>]) -> Vec<u8> {
+        let mut block = vec![0; 6];
+        block.extend(utf16(key));
+        block.resize(align(block.len()), 0);
+        block.extend(value);
+        for child in children {
+            block.resize(align(block.len()), 0);
+            block.extend(child);
+        }
+        let value_length = if text { value.len() / 2 } else { value.len() };
+        let length = block.len() as u16;
+        block[0..2].copy_from_slice(&length.to_le_bytes());
+        block[2..4].copy_from_slice(&(value_length as u16).to_le_bytes());
+        block[4..6].copy_from_slice(&u16::from(text).to_le_bytes());
+        block
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16()
+            .chain([0])
+            .flat_map(u16::to_le_bytes)
+            .collect()
+    }
+
+    #[test]
+    fn version_resources_prefer_us_english_strings() {
+        let mut fixed = Vec::new();
+        for field in [FIXED_FILE_INFO_SIGNATURE, 0x1_0000, 0x1_0002, 0x3_0004] {
+            fixed.extend(field.to_le_bytes());
+        }
+        fixed.resize(52, 0);
+        let table = |language: &str, product: &str| {
+            let strings = [("ProductName", product), ("CompanyName", "Studio")]
+                .map(|(key, value)| block(key, true, &utf16(value), &[]));
+            block(language, true, &[], &strings)
+        };
+        let strings = block(
+            "StringFileInfo",
+            true,
+            &[],
+            &[table("040704b0", "Spiel"), table("040904b0", "Game")],
+        );
+        let version = block("VS_VERSION_INFO", false, &fixed, &[strings]);
+
+        // A tree with one RT_VERSION resource whose data follows the tree.
+        let rva: u32 = 0x1000;
+        let mut tree = Vec::new();
+        for (id, target) in [(RT_VERSION, 0x8000_0018), (1, 0x8000_0030), (0x409, 0x48)] {
+            tree.extend([0; 14]);
+            tree.extend(1u16.to_le_bytes());
+            tree.extend(u32::from(id).to_le_bytes());
+            tree.extend(u32::to_le_bytes(target));
+        }
+        tree.extend((rva + 0x58).to_le_bytes());
+        tree.extend((version.len() as u32).to_le_bytes());
+        tree.extend([0; 8]);
+        tree.extend(&version);
+        let image = Image {
+            machine: 0x8664,
+            characteristics: 0,
+            subsystem: 2,
+            resources: Resources { data: tree, rva },
+        };
+
+        let info = image.version_info().unwrap();
+
+        assert_eq!(info.file_version, Some([1, 2, 3, 4]));
+        assert_eq!(info.strings["ProductName"], "Game");
+        assert_eq!(info.strings["CompanyName"], "Studio");
+    }
+}
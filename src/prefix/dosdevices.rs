@@ -0,0 +1,264 @@
+//! Wine DOS drive links under `<prefix>/dosdevices`.
+//!
+//! Wine resolves a drive letter through a symlink named `<letter>:`. These
+//! links live inside the prefix, so Virgo writes them through its mount into
+//! the bottle's upper directory.
+
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use futures_lite::StreamExt;
+
+use crate::{bottle::DriveMapping, error::Result};
+
+/// Points each mapped letter at its host directory.
+///
+/// Links that already have the requested target are left untouched, so this
+/// is cheap enough to run on every prefix preparation. Letters that are not
+/// mapped keep whatever Wine or the user created for them.
+pub(crate) async fn apply(prefix: &Path, drives: &[DriveMapping]) -> Result<()> {
+    if drives.is_empty() {
+        return Ok(());
+    }
+    let dosdevices = prefix.join("dosdevices");
+    async_fs::create_dir_all(&dosdevices).await?;
+    for drive in drives {
+        let link = dosdevices.join(format!("{}:", drive.letter()));
+        match async_fs::read_link(&link).await {
+            Ok(target) if target == drive.path() => continue,
+            Ok(_) => async_fs::remove_file(&link).await?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+        std::os::unix::fs::symlink(drive.path(), &link)?;
+    }
+    Ok(())
+}
+
+/// Removes the links for `letters`; missing links are already unmapped.
+pub(crate) async fn unmap(prefix: &Path, letters: &[char]) -> Result<()> {
+    for letter in letters {
+        let link = prefix.join("dosdevices").join(format!("{letter}:"));
+        match async_fs::remove_file(link).await {
+            Ok(()) => {}
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Translates an absolute host path into the Windows path Wine uses for it.
+///
+/// Every drive link is considered, and the one with the most specific target
+/// wins, so a file inside `drive_c` becomes `C:\...` even though the default
+/// `Z:` drive also exposes it. Paths are compared lexically; host symlinks are
+/// not resolved. Returns `None` when no drive exposes the path or it is not
+/// valid UTF-8.
+pub(crate) async fn windows_path(prefix: &Path, host: &Path) -> Result<Option<String>> {
+    let dosdevices = prefix.join("dosdevices");
+    let host = normalize(host);
+    let mut best: Option<(usize, char, PathBuf)> = None;
+    let mut entries = match async_fs::read_dir(&dosdevices).await {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+    while let Some(entry) = entries.try_next().await? {
+        let name = entry.file_name();
+        let Some(letter) = name
+            .to_str()
+            .and_then(|name| name.strip_suffix(':'))
+            .filter(|letter| letter.len() == 1)
+            .and_then(|letter| letter.chars().next())
+            .filter(char::is_ascii_alphabetic)
+        else {
+            continue;
+        };
+        let Ok(target) = async_fs::read_link(entry.path()).await else {
+            continue;
+        };
+        let target = normalize(&dosdevices.join(target));
+        if let Ok(rest) = host.strip_prefix(&target) {
+            let depth = target.components().count();
+            if best.as_ref().is_none_or(|(best, ..)| depth > *best) {
+                best = Some((depth, letter, rest.to_path_buf()));
+            }
+        }
+    }
+    let Some((_, letter, rest)) = best else {
+        return Ok(None);
+    };
+    let Some(parts) = rest
+        .iter()
+        .map(|part| part.to_str())
+        .collect::<Option<Vec<_>>>()
+    else {
+        return Ok(None);
+    };
+    Ok(Some(format!(
+        "{}:\\{}",
+        letter.to_ascii_uppercase(),
+        parts.join("\\")
+    )))
+}
+
+/// Translates an absolute Windows path such as `D:\Games` into the host path
+/// its drive link exposes.
+///
+/// Both `\` and `/` separate components, and the result is normalized
+/// lexically. Returns `None` for a path without a drive letter or whose
+/// drive has no link. Case is preserved, so a host directory that differs
+/// only in case from the Windows spelling is not found.
+pub(crate) async fn host_path(prefix: &Path, windows: &str) -> Result<Option<PathBuf>> {
+    let mut chars = windows.chars();
+    let (Some(letter), Some(':')) = (chars.next(), chars.next()) else {
+        return Ok(None);
+    };
+    let rest = chars.as_str();
+    if !letter.is_ascii_alphabetic() || !(rest.is_empty() || rest.starts_with(['\\', '/'])) {
+        return Ok(None);
+    }
+    let dosdevices = prefix.join("dosdevices");
+    let link = dosdevices.join(format!("{}:", letter.to_ascii_lowercase()));
+    let target = match async_fs::read_link(&link).await {
+        Ok(target) => dosdevices.join(target),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+    let mut host = target;
+    for part in rest.split(['\\', '/']).filter(|part| !part.is_empty()) {
+        host.push(part);
+    }
+    Ok(Some(normalize(&host)))
+}
+
+/// Resolves `.` and `..` components without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_replaces_stale_links_and_unmap_removes_them() {
+        futures_lite::future::block_on(async {
+            let root = std::env::temp_dir().join(format!("bottles-next-{}", uuid::Uuid::new_v4()));
+            let prefix = root.join("prefix");
+            let dosdevices = prefix.join("dosdevices");
+            std::fs::create_dir_all(&dosdevices).unwrap();
+            std::os::unix::fs::symlink("/stale", dosdevices.join("d:")).unwrap();
+
+            let drives = [
+                DriveMapping::new('d', root.join("games")).unwrap(),
+                DriveMapping::new('E', root.join("media")).unwrap(),
+            ];
+            apply(&prefix, &drives).await.unwrap();
+            apply(&prefix, &drives).await.unwrap();
+
+            assert_eq!(
+                std::fs::read_link(dosdevices.join("d:")).unwrap(),
+                root.join("games")
+            );
+            assert_eq!(
+                std::fs::read_link(dosdevices.join("e:")).unwrap(),
+                root.join("media")
+            );
+
+            unmap(&prefix, &['d', 'f']).await.unwrap();
+            assert!(std::fs::symlink_metadata(dosdevices.join("d:")).is_err());
+            assert_eq!(
+                std::fs::read_link(dosdevices.join("e:")).unwrap(),
+                root.join("media")
+            );
+
+            std::fs::remove_dir_all(root).unwrap();
+        });
+    }
+
+    #[test]
+    fn host_paths_use_the_most_specific_drive() {
+        futures_lite::future::block_on(async {
+            let root = std::env::temp_dir().join(format!("bottles-next-{}", uuid::Uuid::new_v4()));
+            let prefix = root.join("prefix");
+            let dosdevices = prefix.join("dosdevices");
+            std::fs::create_dir_all(&dosdevices).unwrap();
+            std::os::unix::fs::symlink("../drive_c", dosdevices.join("c:")).unwrap();
+            std::os::unix::fs::symlink("/", dosdevices.join("z:")).unwrap();
+            apply(
+                &prefix,
+                &[DriveMapping::new('d', root.join("games")).unwrap()],
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(
+                windows_path(&prefix, &prefix.join("drive_c/users/setup.exe"))
+                    .await
+                    .unwrap()
+                    .as_deref(),
+                Some(r"C:\users\setup.exe")
+            );
+            assert_eq!(
+                windows_path(&prefix, &root.join("games/../games/a b/game.exe"))
+                    .await
+                    .unwrap()
+                    .as_deref(),
+                Some(r"D:\a b\game.exe")
+            );
+            assert_eq!(
+                windows_path(&prefix, Path::new("/etc/hosts"))
+                    .await
+                    .unwrap()
+                    .as_deref(),
+                Some(r"Z:\etc\hosts")
+            );
+
+            std::fs::remove_file(dosdevices.join("z:")).unwrap();
+            assert_eq!(
+                windows_path(&prefix, Path::new("/etc/hosts"))
+                    .await
+                    .unwrap(),
+                None
+            );
+            std::fs::remove_dir_all(root).unwrap();
+        });
+    }
+
+    #[test]
+    fn windows_paths_resolve_through_drive_links() {
+        futures_lite::future::block_on(async {
+            let root = std::env::temp_dir().join(format!("bottles-next-{}", uuid::Uuid::new_v4()));
+            let prefix = root.join("prefix");
+            let dosdevices = prefix.join("dosdevices");
+            std::fs::create_dir_all(&dosdevices).unwrap();
+            std::os::unix::fs::symlink("../drive_c", dosdevices.join("c:")).unwrap();
+
+            assert_eq!(
+                host_path(&prefix, r"c:\Games\.\Demo/bin").await.unwrap(),
+                Some(prefix.join("drive_c/Games/Demo/bin"))
+            );
+            assert_eq!(
+                host_path(&prefix, "C:").await.unwrap(),
+                Some(prefix.join("drive_c"))
+            );
+            assert_eq!(host_path(&prefix, r"D:\Games").await.unwrap(), None);
+            assert_eq!(host_path(&prefix, r"Games\Demo").await.unwrap(), None);
+            std::fs::remove_dir_all(root).unwrap();
+        });
+    }
+}
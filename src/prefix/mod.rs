@@ -5,27 +5,39 @@
 //! stack with a per-bottle writable upper directory. With the default `fvs`
 //! feature, addon installation and removal use an FVS rollback checkpoint.
 
+pub(crate) mod dosdevices;
 mod standard;
 #[cfg(feature = "fvs")]
 mod virgo;
 
-use std::{future::Future, path::Path};
+use std::{
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 #[cfg(feature = "fvs")]
 use {
+    crate::bottle::error::VirgoError,
     crate::{Stage, Transfer, error::Error},
     futures_core::Stream,
     futures_util::TryStreamExt,
     fvs_rs::{
-        Commit, Layer, Progress as FvsProgress, Repository, RestoreResponse,
+        Commit, CommitSummary, Layer, Progress as FvsProgress, Repository, RestoreResponse,
         error::Error as FvsError,
     },
 };
 
-use crate::{Context, Progress, bottle::Storage, error::Result, runner::Runner};
+use crate::{
+    Context, Progress,
+    bottle::{BottleError, DriveMapping, Storage},
+    error::Result,
+    runner::Runner,
+    utils::exists,
+};
 
 /// Identifies rollback checkpoints that must not appear as user snapshots.
 ///
@@ -91,13 +103,20 @@ impl Prefix {
         }
     }
 
-    pub(crate) async fn prepare(&self, bottle_path: &Path, context: &Context) -> Result<()> {
-        let _ = (bottle_path, context);
+    /// Makes the prefix available at `<bottle>/prefix` and applies drive mappings.
+    pub(crate) async fn prepare(
+        &self,
+        bottle_path: &Path,
+        drives: &[DriveMapping],
+        context: &Context,
+    ) -> Result<()> {
+        let _ = context;
         match self {
-            Self::Standard => Ok(()),
+            Self::Standard => {}
             #[cfg(feature = "fvs")]
-            Self::Virgo { layers } => virgo::prepare(bottle_path, layers, context).await,
+            Self::Virgo { layers } => virgo::prepare(bottle_path, layers, context).await?,
         }
+        dosdevices::apply(&bottle_path.join("prefix"), drives).await
     }
 
     pub(crate) async fn stop(&self, bottle_path: &Path, context: &Context) -> Result<()> {
@@ -130,6 +149,59 @@ impl Prefix {
         }
     }
 
+    /// Returns the persisted layers whose cached repositories cannot be
+    /// mounted. Standard storage has no layers.
+    pub(crate) async fn missing_layers(&self, context: &Context) -> Result<Vec<PathBuf>> {
+        match self {
+            Self::Standard => {
+                let _ = context;
+                Ok(Vec::new())
+            }
+            #[cfg(feature = "fvs")]
+            Self::Virgo { layers } => virgo::missing_layers(layers, context).await,
+        }
+    }
+
+    /// Returns the bottle subdirectory holding the prefix files the bottle
+    /// owns: the prefix itself, or Virgo's writable upper directory.
+    pub(crate) fn writable_dir(&self) -> &'static str {
+        match self {
+            Self::Standard => "prefix",
+            #[cfg(feature = "fvs")]
+            Self::Virgo { .. } => "upper",
+        }
+    }
+
+    /// Reads a file, relative to the prefix root, as the persisted layers
+    /// provide it without the bottle's upper directory.
+    ///
+    /// Standard storage has no layers, so it and a file the layers lack read
+    /// as `None`.
+    #[cfg(feature = "fvs")]
+    pub(crate) async fn read_lower(
+        &self,
+        relative: &Path,
+        context: &Context,
+    ) -> Result<Option<Vec<u8>>> {
+        match self {
+            Self::Standard => Ok(None),
+            Self::Virgo { layers } => virgo::read_lower(layers, relative, context).await,
+        }
+    }
+
+    /// Returns the repositories of the persisted layers. Standard storage has
+    /// no layers.
+    pub(crate) fn layer_repositories(&self) -> Vec<PathBuf> {
+        match self {
+            Self::Standard => Vec::new(),
+            #[cfg(feature = "fvs")]
+            Self::Virgo { layers } => layers
+                .iter()
+                .map(|layer| PathBuf::from(&layer.repository_path))
+                .collect(),
+        }
+    }
+
     pub(crate) async fn install<F, P>(
         &mut self,
         bottle_path: &Path,
@@ -183,6 +255,113 @@ impl Prefix {
         };
         transact(bottle_path, context, work, cancellation, on_progress).await
     }
+
+    /// Recreates the prefix and runs `reinstall` for each addon in
+    /// `installed` order, behind a rollback checkpoint.
+    ///
+    /// The `drive_c` directories named in `preserved` are moved aside first
+    /// and moved back over the recreated ones afterwards, also when
+    /// recreation fails. If moving them back fails, they stay in
+    /// `<bottle>/.repair` and later resets are refused until it is removed.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn reset<F, P>(
+        &mut self,
+        bottle_path: &Path,
+        runner: &dyn Runner,
+        runner_key: &str,
+        preserved: &[&str],
+        installed: &[Uuid],
+        reinstall: F,
+        context: &Context,
+        cancellation: &CancellationToken,
+        on_progress: P,
+    ) -> Result<()>
+    where
+        F: for<'a> std::ops::AsyncFnMut(&'a Path, Uuid) -> Result<()>,
+        P: FnMut(Progress),
+    {
+        #[cfg(not(feature = "fvs"))]
+        let _ = runner_key;
+        let drive_c = match self {
+            Self::Standard => bottle_path.join("prefix/drive_c"),
+            #[cfg(feature = "fvs")]
+            Self::Virgo { .. } => bottle_path.join("upper/drive_c"),
+        };
+        let stash = bottle_path.join(".repair");
+        let work = async {
+            match async_fs::create_dir(&stash).await {
+                Ok(()) => {}
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                    return Err(BottleError::RepairInterrupted(stash.clone()).into());
+                }
+                Err(error) => return Err(error.into()),
+            }
+            let result = async {
+                for name in preserved {
+                    move_if_exists(&drive_c.join(name), &stash.join(name)).await?;
+                }
+                match self {
+                    Self::Standard => {
+                        standard::reset(bottle_path, runner, installed, reinstall).await
+                    }
+                    #[cfg(feature = "fvs")]
+                    Self::Virgo { layers } => {
+                        virgo::reset(
+                            bottle_path,
+                            layers,
+                            runner,
+                            runner_key,
+                            installed,
+                            reinstall,
+                            context,
+                        )
+                        .await
+                    }
+                }
+            }
+            .await;
+            let restored = async {
+                for name in preserved {
+                    let saved = stash.join(name);
+                    if !exists(&saved).await? {
+                        continue;
+                    }
+                    let destination = drive_c.join(name);
+                    if let Err(error) = async_fs::remove_dir_all(&destination).await
+                        && error.kind() != io::ErrorKind::NotFound
+                    {
+                        return Err(error.into());
+                    }
+                    async_fs::create_dir_all(&drive_c).await?;
+                    async_fs::rename(&saved, &destination).await?;
+                }
+                async_fs::remove_dir(&stash).await?;
+                Ok::<_, crate::error::Error>(())
+            }
+            .await;
+            match (result, restored) {
+                (Ok(()), restored) => restored,
+                (Err(error), Ok(())) => Err(error),
+                (Err(error), Err(failed)) => {
+                    tracing::error!(%failed, "restoring preserved files failed after {error}");
+                    Err(error)
+                }
+            }
+        };
+        transact(bottle_path, context, work, cancellation, on_progress).await
+    }
+}
+
+/// Renames `from` to `to` unless `from` does not exist.
+async fn move_if_exists(from: &Path, to: &Path) -> Result<()> {
+    if !exists(from).await? {
+        return Ok(());
+    }
+    if let Some(parent) = to.parent() {
+        async_fs::create_dir_all(parent).await?;
+    }
+    async_fs::rename(from, to).await?;
+    Ok(())
 }
 
 /// Runs a prefix mutation behind a rollback checkpoint.
@@ -193,7 +372,7 @@ impl Prefix {
 /// Dropping the surrounding [`crate::Operation`] abandons this future and does
 /// not drive the restore path.
 #[cfg(feature = "fvs")]
-async fn transact<F, T, P>(
+pub(crate) async fn transact<F, T, P>(
     bottle_path: &Path,
     context: &Context,
     work: F,
@@ -259,9 +438,86 @@ where
     }
 }
 
+/// Finds the commit of the bottle's snapshot history whose state ID is
+/// `state_id_or_prefix` or, failing that, the only one starting with it.
+///
+/// Automatic rollback checkpoints are not caller-visible snapshots, so they
+/// are never resolved.
+#[cfg(feature = "fvs")]
+pub(crate) async fn resolve_snapshot(
+    bottle_path: &Path,
+    state_id_or_prefix: &str,
+    context: &Context,
+) -> Result<CommitSummary> {
+    let repository = Repository {
+        repository_path: bottle_path.display().to_string(),
+        block_size: FVS_BLOCK_SIZE,
+    };
+    let commits = context
+        .fvs()
+        .await?
+        .list_commits(&repository)
+        .await?
+        .into_iter()
+        .filter(|commit| commit.message != AUTO_CHECKPOINT_MESSAGE)
+        .collect::<Vec<_>>();
+    if let Some(commit) = commits
+        .iter()
+        .find(|commit| commit.state_id == state_id_or_prefix)
+    {
+        return Ok(commit.clone());
+    }
+    let mut matching = commits
+        .into_iter()
+        .filter(|_| !state_id_or_prefix.is_empty())
+        .filter(|commit| commit.state_id.starts_with(state_id_or_prefix));
+    match (matching.next(), matching.next()) {
+        (Some(commit), None) => Ok(commit),
+        (Some(_), _) => Err(BottleError::AmbiguousSnapshot(state_id_or_prefix.to_owned()).into()),
+        (None, _) => Err(VirgoError::MissingCommit {
+            repository: bottle_path.to_path_buf(),
+            state: state_id_or_prefix.to_owned(),
+        }
+        .into()),
+    }
+}
+
+/// Mounts one commit of the bottle's snapshot history for the duration of
+/// `work`, which receives the root of the bottle tree as it was then.
+///
+/// The mount has no writable upper layer and is removed afterwards.
+#[cfg(feature = "fvs")]
+pub(crate) async fn with_snapshot<F, T>(
+    bottle_path: &Path,
+    state_id_or_prefix: &str,
+    context: &Context,
+    work: F,
+) -> Result<T>
+where
+    F: for<'a> std::ops::AsyncFnOnce(&'a Path) -> Result<T>,
+{
+    let repository = Repository {
+        repository_path: bottle_path.display().to_string(),
+        block_size: FVS_BLOCK_SIZE,
+    };
+    let commit = resolve_snapshot(bottle_path, state_id_or_prefix, context).await?;
+    let mountpoint = context
+        .directories()
+        .runtime_dir()
+        .join("snapshots")
+        .join(Uuid::new_v4().to_string());
+    let layers = vec![Layer::from_summary(&repository, Some(&commit))];
+    let result = virgo::with_mount(&mountpoint, layers, None, context, async |_| {
+        work(&mountpoint).await
+    })
+    .await;
+    let _ = async_fs::remove_dir(&mountpoint).await;
+    result
+}
+
 /// Runs a prefix mutation directly when FVS rollback support is not compiled in.
 #[cfg(not(feature = "fvs"))]
-async fn transact<F, T, P>(
+pub(crate) async fn transact<F, T, P>(
     _bottle_path: &Path,
     _context: &Context,
     work: F,
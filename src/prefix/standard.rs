@@ -4,7 +4,13 @@
 //! restore overwritten files because, unlike Virgo, this backend has no lower
 //! layer to reveal. Transaction rollback is provided by the parent module.
 
-use std::{ops::AsyncFnOnce, path::Path};
+use std::{
+    io,
+    ops::{AsyncFnMut, AsyncFnOnce},
+    path::Path,
+};
+
+use uuid::Uuid;
 
 use crate::{
     error::Result,
@@ -28,3 +34,27 @@ where
 {
     execute(&bottle_path.join("prefix"), true).await
 }
+
+/// Deletes the prefix, initializes a new one, and runs `reinstall` for each
+/// addon in `installed` order.
+pub(super) async fn reset<F>(
+    bottle_path: &Path,
+    runner: &dyn Runner,
+    installed: &[Uuid],
+    mut reinstall: F,
+) -> Result<()>
+where
+    F: for<'a> AsyncFnMut(&'a Path, Uuid) -> Result<()>,
+{
+    let prefix = bottle_path.join("prefix");
+    if let Err(error) = async_fs::remove_dir_all(&prefix).await
+        && error.kind() != io::ErrorKind::NotFound
+    {
+        return Err(error.into());
+    }
+    create(bottle_path, runner).await?;
+    for id in installed {
+        reinstall(&prefix, *id).await?;
+    }
+    Ok(())
+}
@@ -8,7 +8,7 @@
 mod cache;
 
 use std::{
-    ops::AsyncFnOnce,
+    ops::{AsyncFnMut, AsyncFnOnce},
     path::{Path, PathBuf},
 };
 
@@ -61,6 +61,44 @@ pub(super) async fn rebuild(
     Ok(())
 }
 
+/// Discards the bottle's upper directory and rebuilds its layers from the
+/// runner, reinstalling each addon in `installed` order.
+///
+/// Addons with a cached layer reuse it and only have their registry patches
+/// applied again; the others run `reinstall` as a fresh installation would.
+pub(super) async fn reset<F>(
+    bottle_path: &Path,
+    layers: &mut Vec<Layer>,
+    runner: &dyn Runner,
+    runner_key: &str,
+    installed: &[Uuid],
+    mut reinstall: F,
+    context: &Context,
+) -> Result<()>
+where
+    F: for<'a> AsyncFnMut(&'a Path, Uuid) -> Result<()>,
+{
+    if let Err(error) = async_fs::remove_dir_all(bottle_path.join("upper")).await
+        && error.kind() != std::io::ErrorKind::NotFound
+    {
+        return Err(error.into());
+    }
+    let mut rebuilt = create(bottle_path, runner, runner_key, context).await?;
+    for id in installed {
+        install(
+            bottle_path,
+            &mut rebuilt,
+            *id,
+            None,
+            async |prefix| reinstall(prefix, *id).await,
+            context,
+        )
+        .await?;
+    }
+    *layers = rebuilt;
+    Ok(())
+}
+
 pub(super) async fn install<F>(
     bottle_path: &Path,
     layers: &mut Vec<Layer>,
@@ -108,11 +146,32 @@ where
     .await
 }
 
+/// Returns the repositories of `layers` that are missing or have no commits.
+pub(super) async fn missing_layers(layers: &[Layer], context: &Context) -> Result<Vec<PathBuf>> {
+    let mut missing = Vec::new();
+    for layer in layers {
+        let repository_path = PathBuf::from(&layer.repository_path);
+        if !async_fs::metadata(repository_path.join(".fvs2"))
+            .await
+            .is_ok_and(|entry| entry.is_dir())
+        {
+            missing.push(repository_path);
+            continue;
+        }
+        let client = context.fvs().await?;
+        let repository = client.new_repository(&repository_path, 0).await?;
+        if client.list_commits(&repository).await?.is_empty() {
+            missing.push(repository_path);
+        }
+    }
+    Ok(missing)
+}
+
 /// Mounts for the duration of `work` and always attempts a normal unmount.
 ///
 /// An unmount failure becomes the result only when `work` succeeded. If both
 /// fail, the work error is preserved and the unmount failure is logged.
-async fn with_mount<F, T>(
+pub(super) async fn with_mount<F, T>(
     mountpoint: &Path,
     layers: Vec<Layer>,
     upper: Option<&Path>,
@@ -142,6 +201,35 @@ where
     }
 }
 
+/// Reads `relative` from `layers` mounted without an upper directory, or
+/// returns `None` when the layers do not provide it.
+pub(super) async fn read_lower(
+    layers: &[Layer],
+    relative: &Path,
+    context: &Context,
+) -> Result<Option<Vec<u8>>> {
+    let mountpoint = context
+        .directories()
+        .runtime_dir()
+        .join("lower")
+        .join(Uuid::new_v4().to_string());
+    let result =
+        with_mount(
+            &mountpoint,
+            layers.to_vec(),
+            None,
+            context,
+            async |_| match async_fs::read(mountpoint.join(relative)).await {
+                Ok(contents) => Ok(Some(contents)),
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(error) => Err(error.into()),
+            },
+        )
+        .await;
+    remove_dir(mountpoint).await;
+    result
+}
+
 /// Prepares a bottle's long-lived Virgo mount.
 ///
 /// An existing mount at the same path is trusted without comparing its layer
@@ -3,12 +3,15 @@ use std::{
     path::{Component, Path, PathBuf},
 };
 
-use async_compression::futures::bufread::{GzipDecoder, XzDecoder};
+use async_compression::futures::{
+    bufread::{GzipDecoder, XzDecoder, ZstdDecoder},
+    write::{XzEncoder, ZstdEncoder},
+};
 use futures_lite::{
     StreamExt,
-    io::{AsyncRead, BufReader, copy},
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, copy},
 };
-use smol_tar::{TarEntry, TarReader};
+use smol_tar::{TarDirectory, TarEntry, TarReader, TarRegularFile, TarSymlink, TarWriter};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -35,6 +38,8 @@ pub(crate) async fn extract(archive: &Path, destination: &Path) -> Result<(), Ar
         unpack(GzipDecoder::new(BufReader::new(file)), destination).await
     } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
         unpack(XzDecoder::new(BufReader::new(file)), destination).await
+    } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+        unpack(ZstdDecoder::new(BufReader::new(file)), destination).await
     } else if name.ends_with(".tar") {
         unpack(file, destination).await
     } else {
@@ -48,11 +53,13 @@ async fn unpack(
 ) -> Result<(), ArchiveError> {
     let mut archive = TarReader::new(reader);
     let mut directories = Vec::new();
+    // Created last, so no member is written through a link from the archive.
+    let mut links = Vec::new();
 
     while let Some(entry) = archive.next().await {
         match entry? {
             TarEntry::File(mut file) => {
-                let path = safe_path(file.path())?;
+                let path = member_path(file.path())?;
                 let destination = destination.join(&path);
                 if let Some(parent) = destination.parent() {
                     async_fs::create_dir_all(parent).await?;
@@ -63,21 +70,17 @@ async fn unpack(
             }
 
             TarEntry::Directory(directory) => {
-                let path = safe_path(directory.path())?;
+                let path = member_path(directory.path())?;
                 let destination = destination.join(path);
                 async_fs::create_dir_all(&destination).await?;
                 directories.push((destination, directory.mode()));
             }
 
             TarEntry::Symlink(link) => {
-                let path = safe_path(link.path())?;
+                let path = member_path(link.path())?;
                 let target = link.link();
                 safe_symlink_target(&path, target)?;
-                let link_path = destination.join(&path);
-                if let Some(parent) = link_path.parent() {
-                    async_fs::create_dir_all(parent).await?;
-                }
-                std::os::unix::fs::symlink(target, link_path)?;
+                links.push((destination.join(&path), target.to_owned()));
             }
 
             entry => {
@@ -86,12 +89,97 @@ async fn unpack(
         }
     }
 
+    for (link_path, target) in links {
+        if let Some(parent) = link_path.parent() {
+            async_fs::create_dir_all(parent).await?;
+        }
+        std::os::unix::fs::symlink(target, link_path)?;
+    }
     for (path, mode) in directories.into_iter().rev() {
         set_mode(&path, mode).await?;
     }
     Ok(())
 }
 
+/// Writes `members` to a new compressed tarball at `archive`.
+///
+/// Each member pairs an archive path with a host file or directory; directories
+/// are archived recursively with their modes. The compression is chosen from
+/// the `.tar.zst` or `.tar.xz` suffix. Symbolic links are stored only when
+/// their target stays inside the archive, so [`extract`] accepts every link
+/// written here; other links and special files are skipped.
+pub(crate) async fn pack(archive: &Path, members: &[(&str, &Path)]) -> Result<(), ArchiveError> {
+    let name = archive
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| ArchiveError::InvalidName(archive.to_path_buf()))?;
+    if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+        let mut encoder = ZstdEncoder::new(async_fs::File::create(archive).await?);
+        write_members(&mut encoder, members).await?;
+        encoder.close().await?;
+    } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+        let mut encoder = XzEncoder::new(async_fs::File::create(archive).await?);
+        write_members(&mut encoder, members).await?;
+        encoder.close().await?;
+    } else {
+        return Err(ArchiveError::Unsupported(archive.to_path_buf()));
+    }
+    Ok(())
+}
+
+async fn write_members(
+    writer: &mut (impl AsyncWrite + Unpin),
+    members: &[(&str, &Path)],
+) -> Result<(), ArchiveError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut archive = TarWriter::<_, async_fs::File>::new(writer);
+    for (name, source) in members {
+        let mut pending = vec![(safe_path(name)?, source.to_path_buf())];
+        while let Some((path, source)) = pending.pop() {
+            let entry_name = path
+                .to_str()
+                .ok_or_else(|| ArchiveError::InvalidName(source.clone()))?
+                .to_owned();
+            let metadata = async_fs::symlink_metadata(&source).await?;
+            let mode = metadata.permissions().mode() & 0o7777;
+            let file_type = metadata.file_type();
+            if file_type.is_dir() {
+                archive
+                    .write(TarDirectory::new(entry_name).with_mode(mode).into())
+                    .await?;
+                let mut entries = async_fs::read_dir(&source).await?;
+                while let Some(entry) = entries.next().await {
+                    let entry = entry?;
+                    pending.push((path.join(entry.file_name()), entry.path()));
+                }
+            } else if file_type.is_file() {
+                let file = async_fs::File::open(&source).await?;
+                archive
+                    .write(
+                        TarRegularFile::new(entry_name, metadata.len(), file)
+                            .with_mode(mode)
+                            .into(),
+                    )
+                    .await?;
+            } else if file_type.is_symlink()
+                && let Some(target) = async_fs::read_link(&source)
+                    .await?
+                    .to_str()
+                    .filter(|target| safe_symlink_target(&path, target).is_ok())
+            {
+                archive
+                    .write(TarSymlink::new(entry_name, target).into())
+                    .await?;
+            } else {
+                tracing::debug!(path = %source.display(), "skipping unarchivable entry");
+            }
+        }
+    }
+    archive.finish().await?;
+    Ok(())
+}
+
 fn safe_path(path: impl AsRef<Path>) -> Result<PathBuf, ArchiveError> {
     let path = path.as_ref();
     let mut result = PathBuf::new();
@@ -108,6 +196,19 @@ fn safe_path(path: impl AsRef<Path>) -> Result<PathBuf, ArchiveError> {
     Ok(result)
 }
 
+/// Validates a member path, which must be relative and must not use `..`,
+/// so that it cannot step out of a symbolic link already in the destination.
+fn member_path(path: impl AsRef<Path>) -> Result<PathBuf, ArchiveError> {
+    let path = path.as_ref();
+    if path
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(ArchiveError::EntryOutsideDestination(path.to_path_buf()));
+    }
+    safe_path(path)
+}
+
 fn safe_symlink_target(link: &Path, target: &str) -> Result<(), ArchiveError> {
     safe_path(link.parent().unwrap_or(Path::new("")).join(target))?;
     Ok(())
@@ -147,7 +248,6 @@ mod tests {
     use std::os::unix::fs::PermissionsExt;
 
     use async_compression::futures::write::{GzipEncoder, XzEncoder};
-    use futures_lite::io::AsyncWriteExt;
     use smol_tar::{TarRegularFile, TarSymlink, TarWriter};
 
     use super::*;
@@ -294,6 +394,119 @@ mod tests {
         });
     }
 
+    #[test]
+    fn packs_trees_that_extract_identically() {
+        futures_lite::future::block_on(async {
+            let root = temporary_directory();
+            let source = root.join("source");
+            async_fs::create_dir_all(source.join("bin")).await.unwrap();
+            async_fs::write(source.join("bin/run.sh"), b"#!/bin/sh\n")
+                .await
+                .unwrap();
+            async_fs::set_permissions(
+                source.join("bin/run.sh"),
+                std::fs::Permissions::from_mode(0o755),
+            )
+            .await
+            .unwrap();
+            std::os::unix::fs::symlink("bin/run.sh", source.join("run")).unwrap();
+            std::os::unix::fs::symlink("/", source.join("root")).unwrap();
+            async_fs::write(root.join("bottle.toml"), b"name = \"test\"\n")
+                .await
+                .unwrap();
+
+            for name in ["bottle.tar.zst", "bottle.tar.xz"] {
+                let archive_path = root.join(name);
+                let destination = root.join(format!("{name}-output"));
+                async_fs::create_dir_all(&destination).await.unwrap();
+                let bottle = root.join("bottle.toml");
+                pack(
+                    &archive_path,
+                    &[("bottle.toml", &bottle), ("prefix", &source)],
+                )
+                .await
+                .unwrap();
+                extract(&archive_path, &destination).await.unwrap();
+
+                assert_eq!(
+                    async_fs::read(destination.join("bottle.toml"))
+                        .await
+                        .unwrap(),
+                    b"name = \"test\"\n"
+                );
+                assert_eq!(
+                    async_fs::metadata(destination.join("prefix/bin/run.sh"))
+                        .await
+                        .unwrap()
+                        .permissions()
+                        .mode()
+                        & 0o777,
+                    0o755
+                );
+                assert_eq!(
+                    async_fs::read_link(destination.join("prefix/run"))
+                        .await
+                        .unwrap(),
+                    PathBuf::from("bin/run.sh")
+                );
+                assert!(
+                    async_fs::symlink_metadata(destination.join("prefix/root"))
+                        .await
+                        .is_err()
+                );
+            }
+
+            assert!(matches!(
+                pack(&root.join("bottle.zip"), &[]).await,
+                Err(ArchiveError::Unsupported(_))
+            ));
+            async_fs::remove_dir_all(root).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn never_writes_members_through_links() {
+        futures_lite::future::block_on(async {
+            let root = temporary_directory();
+            let archive_path = root.join("saves.tar");
+            let destination = root.join("output");
+            async_fs::create_dir_all(destination.join("inside"))
+                .await
+                .unwrap();
+            std::os::unix::fs::symlink(root.join("host"), destination.join("Documents")).unwrap();
+            async_fs::write(
+                &archive_path,
+                tar_with_file("Documents/../escaped", 0o644).await,
+            )
+            .await
+            .unwrap();
+            assert!(matches!(
+                extract(&archive_path, &destination).await,
+                Err(ArchiveError::EntryOutsideDestination(_))
+            ));
+
+            let mut bytes = Vec::new();
+            {
+                let mut archive = TarWriter::new(&mut bytes);
+                archive
+                    .write(TarSymlink::new("linked", "inside").into())
+                    .await
+                    .unwrap();
+                let body = b"save";
+                archive
+                    .write(TarRegularFile::new("linked/file", 4, body.as_slice()).into())
+                    .await
+                    .unwrap();
+                archive.finish().await.unwrap();
+            }
+            async_fs::write(&archive_path, bytes).await.unwrap();
+            assert!(extract(&archive_path, &destination).await.is_err());
+            assert!(!destination.join("inside/file").exists());
+
+            async_fs::remove_dir_all(root).await.unwrap();
+        });
+    }
+
     #[test]
     fn rejects_escaping_symlink_targets() {
         assert!(matches!(
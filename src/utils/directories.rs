@@ -1,20 +1,25 @@
 use std::path::{Path, PathBuf};
 
-use ::directories::ProjectDirs;
+use ::directories::{BaseDirs, ProjectDirs};
 
 use uuid::Uuid;
 
 use crate::{bottle::error::BottleError, error::Result};
 
 #[derive(Clone, Debug)]
-pub struct Directories(ProjectDirs);
+pub struct Directories {
+    project: ProjectDirs,
+    /// The user's shared data directory, where desktop entries are installed.
+    user_data: Option<PathBuf>,
+}
 
 impl Directories {
     pub(crate) async fn new() -> Result<Self> {
-        let directories = Self(
-            ProjectDirs::from("com", "usebottles", "bottles-next")
+        let directories = Self {
+            project: ProjectDirs::from("com", "usebottles", "bottles-next")
                 .ok_or(BottleError::ProjectDirectoriesUnavailable)?,
-        );
+            user_data: BaseDirs::new().map(|base| base.data_dir().to_path_buf()),
+        };
         for directory in directories.paths() {
             async_fs::create_dir_all(directory).await?;
         }
@@ -23,10 +28,12 @@ impl Directories {
 
     #[cfg(test)]
     pub(crate) fn from_path(path: impl Into<PathBuf>) -> Result<Self> {
-        let directories = Self(
-            ProjectDirs::from_path(path.into())
+        let path = path.into();
+        let directories = Self {
+            user_data: Some(path.join("share")),
+            project: ProjectDirs::from_path(path)
                 .ok_or(BottleError::ProjectDirectoriesUnavailable)?,
-        );
+        };
         for directory in directories.paths() {
             std::fs::create_dir_all(directory)?;
         }
@@ -34,15 +41,22 @@ impl Directories {
     }
 
     pub(crate) fn data_dir(&self) -> &Path {
-        self.0.data_local_dir()
+        self.project.data_local_dir()
+    }
+
+    pub(crate) fn user_data_dir(&self) -> Result<&Path> {
+        Ok(self
+            .user_data
+            .as_deref()
+            .ok_or(BottleError::ProjectDirectoriesUnavailable)?)
     }
 
     pub(crate) fn config_dir(&self) -> &Path {
-        self.0.config_dir()
+        self.project.config_dir()
     }
 
     pub(crate) fn runtime_dir(&self) -> PathBuf {
-        self.0
+        self.project
             .runtime_dir()
             .map(Path::to_path_buf)
             .unwrap_or_else(|| self.data_dir().join("runtime"))
@@ -56,6 +70,17 @@ impl Directories {
         self.bottles().join(id.to_string())
     }
 
+    #[cfg(feature = "fvs")]
+    pub(crate) fn snapshot_tags(&self, id: Uuid) -> PathBuf {
+        self.data_dir()
+            .join("snapshot-tags")
+            .join(format!("{id}.toml"))
+    }
+
+    pub(crate) fn saves(&self) -> PathBuf {
+        self.data_dir().join("saves")
+    }
+
     pub(crate) fn components(&self) -> PathBuf {
         self.data_dir().join("components")
     }
@@ -3,6 +3,7 @@ pub(crate) mod checksum;
 pub(crate) mod context;
 pub(crate) mod directories;
 pub(crate) mod environment;
+pub(crate) mod png;
 
 #[cfg(feature = "fvs")]
 use std::path::PathBuf;
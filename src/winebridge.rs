@@ -1,13 +1,14 @@
 use std::{
-    io,
+    io::{self, Write},
     path::{Path, PathBuf},
     process::ExitStatus,
+    thread,
     time::Duration,
 };
 
 use async_io::Timer;
-use async_process::Child;
-use futures_lite::future;
+use async_process::{Child, ChildStderr};
+use futures_lite::{AsyncBufReadExt, StreamExt, future, io::BufReader};
 use thiserror::Error;
 use tonic::transport::{Channel, Endpoint};
 use tonic_health::pb::{
@@ -99,6 +100,24 @@ impl WineBridgeClient {
         Self::connect(prefix, command.spawn()?).await
     }
 
+    /// Spawns WineBridge with its output appended to `log` and connects to it.
+    ///
+    /// Each line written to standard error, which Wine processes launched
+    /// through WineBridge inherit, is also passed to `forward` from a thread
+    /// that runs until every process holding the stream exits.
+    pub(crate) async fn spawn_logged(
+        prefix: &Path,
+        command: impl Spawnable,
+        log: std::fs::File,
+        forward: impl FnMut(&[u8]) + Send + 'static,
+    ) -> Result<Self> {
+        let mut process = command.spawn_logged(log.try_clone()?)?;
+        if let Some(stderr) = process.stderr.take() {
+            thread::spawn(move || forward_lines(stderr, log, forward));
+        }
+        Self::connect(prefix, process).await
+    }
+
     async fn connect(prefix: &Path, mut process: Child) -> Result<Self> {
         let ready = async {
             loop {
@@ -152,6 +171,17 @@ impl WineBridgeClient {
         prefix.join("drive_c/windows/temp").join(PORT_FILE_NAME)
     }
 
+    /// Returns the port file in `prefix` if it exists but no serving
+    /// WineBridge answers on the port it names, as after a crash.
+    pub(crate) async fn stale_port_file(prefix: &Path) -> Result<Option<PathBuf>> {
+        let port_file = Self::port_file(prefix);
+        if !exists(&port_file).await? {
+            return Ok(None);
+        }
+        let serving = matches!(Self::try_connect(prefix).await, Ok(Some(_)));
+        Ok((!serving).then_some(port_file))
+    }
+
     // --- Process Management ---
 
     pub async fn list_processes(&self) -> Result<Vec<Process>> {
@@ -670,3 +700,17 @@ impl WineBridgeClient {
         Err(BridgeError::ShutdownTimeout.into())
     }
 }
+
+/// Appends each line of `stderr` to `log` and passes it to `forward`.
+fn forward_lines(stderr: ChildStderr, mut log: std::fs::File, mut forward: impl FnMut(&[u8])) {
+    future::block_on(async {
+        let mut lines = BufReader::new(stderr).split(b'\n');
+        while let Some(Ok(mut line)) = lines.next().await {
+            line.push(b'\n');
+            if let Err(error) = log.write_all(&line) {
+                tracing::warn!("failed to write WineBridge log: {error}");
+            }
+            forward(&line);
+        }
+    });
+}
@@ -1,9 +1,12 @@
 pub(crate) mod gamescope;
 pub(crate) mod mangohud;
 
-use async_process::{Child, Command as AsyncCommand};
+use async_process::{Child, Command as AsyncCommand, Stdio};
 use serde::{Deserialize, Serialize};
-use std::ffi::{OsStr, OsString};
+use std::{
+    ffi::{OsStr, OsString},
+    fs::File,
+};
 
 use crate::{runner::RunnerCommand, utils::environment::Environment};
 
@@ -55,10 +58,17 @@ impl<O: Wrapper, I: Into<Command>> From<Wrapped<O, I>> for Command {
 
 pub(crate) trait Spawnable: Into<Command> + Sized {
     fn spawn(self) -> std::io::Result<Child> {
-        let command = self.into();
-        AsyncCommand::new(command.executable)
-            .args(command.args)
-            .envs(command.envs)
+        self.into().into_async().spawn()
+    }
+
+    /// Spawns with stdin closed, standard output appended to `log`, and
+    /// standard error piped.
+    fn spawn_logged(self, log: File) -> std::io::Result<Child> {
+        self.into()
+            .into_async()
+            .stdin(Stdio::null())
+            .stdout(log)
+            .stderr(Stdio::piped())
             .spawn()
     }
 }
@@ -117,4 +127,10 @@ impl Command {
         self.envs.extend(inner.envs);
         self
     }
+
+    fn into_async(self) -> AsyncCommand {
+        let mut command = AsyncCommand::new(self.executable);
+        command.args(self.args).envs(self.envs);
+        command
+    }
 }